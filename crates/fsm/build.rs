@@ -35,6 +35,12 @@ fn main() {
 
     println!("cargo::rerun-if-changed=build.rs");
 
+    // The linker scripts below only exist for the pod. Host builds (used for
+    // testing the FSM) link like any other binary.
+    if env::var("CARGO_CFG_TARGET_OS").as_deref() != Ok("none") {
+        return;
+    }
+
     // Specify linker arguments.
 
     // `--nmagic` is required if memory section addresses are not aligned to
@@ -0,0 +1,118 @@
+//! This module contains the abstractions the `FSM` uses to interact with the
+//! hardware of the main PCB, so that the transition logic can also run (and be
+//! tested) on a host machine.
+
+use core::future::Future;
+
+use embassy_time::Duration;
+use embassy_time::Instant;
+
+/// Trait for everything the `FSM` needs to control the shutdown circuit (SDC)
+/// and to reset the main PCB.
+pub trait SdcControl {
+    /// Pulls the SDC line high, allowing high voltage and retracting the
+    /// brakes.
+    fn set_sdc_high(&mut self);
+
+    /// Pulls the SDC line low, which triggers the brakes and shuts off high
+    /// voltage.
+    fn set_sdc_low(&mut self);
+
+    /// Checks whether the SDC line is currently being pulled low.
+    fn is_sdc_low(&self) -> bool;
+
+    /// Sets the pin used for rearming the SDC high.
+    fn set_rearm_high(&mut self);
+
+    /// Sets the pin used for rearming the SDC low.
+    fn set_rearm_low(&mut self);
+
+    /// Resets the main PCB. On the pod this never returns.
+    fn reset(&mut self);
+}
+
+/// Trait for the time source used by the `FSM`.
+pub trait Clock {
+    /// Returns the current time.
+    fn now(&self) -> Instant;
+
+    /// Waits for the given duration.
+    fn delay(&self, duration: Duration) -> impl Future<Output = ()>;
+}
+
+/// The SDC pins on the main PCB.
+#[cfg(target_os = "none")]
+pub struct SdcPins {
+    /// The pin on the main PCB used for rearming the sdc
+    rearm_sdc_pin: embassy_stm32::gpio::Output<'static>,
+    /// The pin on the main PCB used for controlling the sdc
+    sdc_pin: embassy_stm32::gpio::Output<'static>,
+}
+
+#[cfg(target_os = "none")]
+impl SdcPins {
+    /// Constructor for the `SdcPins` struct.
+    ///
+    /// # Parameters:
+    /// - `rearm_sdc_pin`: The pin used for rearming the sdc
+    /// - `sdc_pin`: The pin used for controlling the sdc
+    pub fn new(
+        rearm_sdc_pin: embassy_stm32::gpio::Output<'static>,
+        sdc_pin: embassy_stm32::gpio::Output<'static>,
+    ) -> Self {
+        Self {
+            rearm_sdc_pin,
+            sdc_pin,
+        }
+    }
+}
+
+#[cfg(target_os = "none")]
+impl core::fmt::Debug for SdcPins {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "SdcPins {{ sdc_low: {} }}", self.sdc_pin.is_set_low())
+    }
+}
+
+#[cfg(target_os = "none")]
+impl SdcControl for SdcPins {
+    fn set_sdc_high(&mut self) {
+        self.sdc_pin.set_high();
+    }
+
+    fn set_sdc_low(&mut self) {
+        self.sdc_pin.set_low();
+    }
+
+    fn is_sdc_low(&self) -> bool {
+        self.sdc_pin.is_set_low()
+    }
+
+    fn set_rearm_high(&mut self) {
+        self.rearm_sdc_pin.set_high();
+    }
+
+    fn set_rearm_low(&mut self) {
+        self.rearm_sdc_pin.set_low();
+    }
+
+    fn reset(&mut self) {
+        cortex_m::peripheral::SCB::sys_reset();
+    }
+}
+
+/// Clock backed by the embassy time driver.
+#[cfg(target_os = "none")]
+#[derive(Debug, Clone, Copy)]
+pub struct EmbassyClock;
+
+#[cfg(target_os = "none")]
+impl Clock for EmbassyClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    async fn delay(&self, duration: Duration) {
+        embassy_time::Timer::after(duration).await
+    }
+}
@@ -7,22 +7,33 @@ use defmt::info;
 use defmt::warn;
 use embassy_futures::select::select;
 use embassy_futures::select::Either;
-use embassy_stm32::gpio::Output;
 use embassy_time::Duration;
 use embassy_time::Instant;
-use embassy_time::Timer;
+use lib::config::heartbeat_timeout;
+use lib::config::Datatype;
+use lib::config::HEARTBEAT_GRACE_PERIOD;
 use lib::EmergencyType;
 use lib::Event;
 use lib::EventReceiver;
 use lib::EventSender;
+use lib::FsmHistory;
 use lib::States;
+use lib::Transition;
+use lib::TransitionAction;
+use lib::TransitionGuard;
+use lib::TransitionRecord;
 use log::error;
 
+use crate::control::Clock;
+use crate::control::SdcControl;
 use crate::CheckedSystem;
 use crate::CheckedSystems;
 
 /// The struct for the `MainFSM`
-pub struct FSM {
+///
+/// Generic over the hardware it controls, so that the same transition logic
+/// runs on the pod and in host tests.
+pub struct FSM<S: SdcControl, C: Clock> {
     /// The state in which the pod is in
     state: States,
     /// Object used for receive access to the event channel
@@ -33,23 +44,33 @@ pub struct FSM {
     systems: CheckedSystems,
     /// the last time we received a heartbeat from the frontend
     last_heartbeat: Instant,
-    /// The pin on the main PCB used for rearming the sdc
-    rearm_sdc_pin: Output<'static>,
-    /// The pin on the main PCB used for controlling the sdc
-    sdc_pin: Output<'static>,
+    /// Heartbeats aren't checked before this time, to give the frontend time
+    /// to start sending them after connecting to the ground station
+    heartbeat_grace_end: Instant,
+    /// Controls the sdc and rearm pins on the main PCB
+    sdc: S,
+    /// The time source of the FSM
+    clock: C,
+    /// The time at which the FSM entered the current state
+    state_entered: Instant,
+    /// A state requested from the subsystems, and the time by which they have
+    /// to acknowledge it
+    pending_request: Option<(States, Instant)>,
     /// The time at which we received the last pressure reading. Used to delay
     /// the emergency sent when the wrong EBS state is detected when entering
     /// Demo or discharging
     last_pressure_check: Option<Instant>,
+    /// The last transitions of the FSM, sent to the GS on `DumpFsmHistory`
+    history: &'static FsmHistory,
 }
 
-impl Debug for FSM {
+impl<S: SdcControl, C: Clock> Debug for FSM<S, C> {
     fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
         write!(f, "FSM {{ state: {:?}}}", self.state)
     }
 }
 
-impl FSM {
+impl<S: SdcControl, C: Clock> FSM<S, C> {
     /// Constructor for the `FSM` struct. Initializes the FSM in the `Boot`
     /// state.
     ///
@@ -60,14 +81,18 @@ impl FSM {
     ///   `PriorityChannel` used to send events on CAN 2
     /// - `event_sender_gs`: Static reference to a sender object from the
     ///   `PriorityChannel` used to send events to the groundstation
+    /// - `sdc`: Controls the sdc and rearm pins of the main PCB
+    /// - `clock`: The time source used for timers and timestamps
+    /// - `history`: Where the FSM records its transitions
     ///
     /// # Returns:
     /// - A future for an instance of the `FSM` struct
     pub async fn new(
         event_receiver: EventReceiver,
         event_sender: EventSender,
-        rearm_sdc_pin: Output<'static>,
-        sdc_pin: Output<'static>,
+        sdc: S,
+        clock: C,
+        history: &'static FsmHistory,
     ) -> Self {
         Self {
             state: States::Boot,
@@ -79,10 +104,14 @@ impl FSM {
                 propulsion2: false,
             },
             // this is a lie but i don't really care.
-            last_heartbeat: Instant::now(),
-            rearm_sdc_pin,
-            sdc_pin,
+            last_heartbeat: clock.now(),
+            heartbeat_grace_end: clock.now(),
+            sdc,
+            clock,
+            state_entered: clock.now(),
+            pending_request: None,
             last_pressure_check: None,
+            history,
         }
     }
 
@@ -93,7 +122,12 @@ impl FSM {
     /// the `ShutDown` event.
     pub async fn run(&mut self) -> ! {
         loop {
-            match select(self.event_receiver.receive(), Timer::after_millis(100)).await {
+            match select(
+                self.event_receiver.receive(),
+                self.clock.delay(Duration::from_millis(100)),
+            )
+            .await
+            {
                 Either::First(event) => {
                     if (self.state != States::Braking && event != Event::Stopped
                         || self.state == States::Braking)
@@ -120,21 +154,20 @@ impl FSM {
 
                     // Checks if the braking line is still high. If not, send an emergency message
                     // to the ground station and transition to fault state.
-                    if self.sdc_pin.is_set_low()
+                    if self.sdc.is_sdc_low()
                         && self.state != States::Fault
                         && self.state != States::Discharge
                         && self.state != States::SystemCheck
                     {
                         error!("SDC pin is low! Sending emergency to the ground station!");
-                        self.event_sender
-                            .send(Event::Emergency {
-                                emergency_type: EmergencyType::GeneralEmergency,
-                            })
-                            .await;
-                        self.transition(States::Fault).await;
+                        let emergency = Event::Emergency {
+                            emergency_type: EmergencyType::GeneralEmergency,
+                        };
+                        self.event_sender.send(emergency).await;
+                        self.transition(States::Fault, emergency).await;
                     }
 
-                    Timer::after_micros(100).await;
+                    self.clock.delay(Duration::from_micros(100)).await;
                 }
                 Either::Second(_timeout) => {
                     // ensure the ground station knows the current fsm state
@@ -145,13 +178,8 @@ impl FSM {
                 }
             }
 
-            // check heartbeat
-            // if self.last_heartbeat.elapsed().as_millis() > IP_TIMEOUT
-            //     && self.state != States::Boot
-            //     && self.state != States::Fault
-            // {
-            //     self.transition(States::Fault).await;
-            // }
+            self.check_heartbeat().await;
+            self.check_timeouts().await;
         }
     }
 
@@ -159,9 +187,11 @@ impl FSM {
     ///
     /// This method transitions the `FSM` from one state to another
     /// depending on which state it currently is in and what event it
-    /// received. If it receives an event that it wasn't expecting in the
-    /// current state, it ignores it or sends a `TransitionFail` event to the GS
-    /// if it was an event related to a state transition.
+    /// received. Most transitions come from the `[[Transition]]` table in
+    /// `config.toml`; emergencies, the system check, EBS pressure and resets
+    /// are handled here. If it receives an event that it wasn't expecting in
+    /// the current state, it ignores it or sends a `TransitionFail` event to
+    /// the GS if it was an event related to a state transition.
     ///
     /// # Parameters:
     /// - `event`: Event that can cause a transition in the FSM.
@@ -170,10 +200,15 @@ impl FSM {
     /// - `false`: If the FSM receives a `Quit` event
     /// - `true`: Otherwise
     async fn handle_events(&mut self, event: Event) -> bool {
+        if event == Event::ConnectToGS {
+            self.heartbeat_grace_end =
+                self.clock.now() + Duration::from_millis(HEARTBEAT_GRACE_PERIOD);
+        }
+
         match (self.state, event) {
             (_, Event::Emergency { emergency_type }) if self.state != States::Fault => {
                 // 1. Trigger emergency using the sdc
-                self.sdc_pin.set_low();
+                self.sdc.set_sdc_low();
                 error!("Going into Fault state with emergency {emergency_type:?}");
 
                 // 2. send the emergency message to all other devices on the CAN line
@@ -192,29 +227,15 @@ impl FSM {
                 }
 
                 // lastly, transition to fault state.
-                self.transition(States::Fault).await;
+                self.transition(States::Fault, event).await;
             }
 
             (_, Event::ResetFSM) => {
                 info!("Reset FSM triggered. Resetting the main PCB...");
                 self.event_sender.send(Event::ResetFSM).await;
-                self.sdc_pin.set_low();
-                Timer::after_millis(5).await;
-                cortex_m::peripheral::SCB::sys_reset();
-            }
-
-            (States::Fault, Event::FaultFixed) => {
-                self.sdc_pin.set_high();
-                Timer::after_millis(120).await;
-                self.transition(States::SystemCheck).await
-            }
-            (States::Boot, Event::ConnectToGS) => self.transition(States::ConnectedToGS).await,
-            (States::ConnectedToGS, Event::StartSystemCheck) => {
-                self.transition(States::SystemCheck).await
-            }
-
-            (States::SystemCheck, Event::StartSystemCheck) => {
-                self.transition(States::SystemCheck).await;
+                self.sdc.set_sdc_low();
+                self.clock.delay(Duration::from_millis(5)).await;
+                self.sdc.reset();
             }
 
             // Add the checked system to the list of checked systems
@@ -228,98 +249,12 @@ impl FSM {
                 self.add_system_check(CheckedSystem::Levitation).await
             }
 
-            // Go into fault state if any of the system checks fail
-            (States::SystemCheck, Event::Prop1SystemCheckFailure) => {
-                error!("Prop 1 system check failure!");
-                self.event_sender.send(Event::Prop1SystemCheckFailure).await;
-                self.transition(States::Fault).await;
-            }
-            (States::SystemCheck, Event::Prop2SystemCheckFailure) => {
-                error!("Prop 2 system check failure!");
-                self.event_sender.send(Event::Prop2SystemCheckFailure).await;
-                self.transition(States::Fault).await;
-            }
-            (States::SystemCheck, Event::LeviSystemCheckFailure) => {
-                error!("Levi system check failure!");
-                self.transition(States::Fault).await;
-                self.event_sender.send(Event::LeviSystemCheckFailure).await;
-            }
-            (States::Accelerating, Event::LocalizationLimitReached) => {
-                self.transition(States::Braking).await;
-                self.event_sender
-                    .send(Event::LocalizationLimitReached)
-                    .await;
-                warn!("Localization limit reached! Starting to brake with the motor!");
-            }
-
-            (States::Idle, Event::StartPreCharge) => self.transition(States::PreCharge).await,
-            (States::PreCharge, Event::HVOnAck) => self.transition(States::Active).await,
-            (States::Active, Event::Charge) => self.transition(States::Charging).await,
-            (States::Charging, Event::StopCharge) => self.transition(States::Active).await,
             (_, Event::OverrideRearmSdc) => {
-                self.sdc_pin.set_high();
-                Timer::after_millis(100).await;
-                self.rearm_sdc_pin.set_high();
-                Timer::after_millis(100).await;
-                self.rearm_sdc_pin.set_low();
-            }
-            (States::Active, Event::EnterDemo) => {
-                self.sdc_pin.set_high();
-                Timer::after_millis(100).await;
-                self.transition(States::Demo).await;
-                self.rearm_sdc_pin.set_high();
-                Timer::after_millis(100).await;
-                self.rearm_sdc_pin.set_low();
-            }
-            (States::Demo, Event::Discharge) => {
-                // Pull the SDC pin low to engage the brakes since you can't have the brakes
-                // retracted without high voltage turned on.
-                self.sdc_pin.set_low();
-                self.transition(States::Discharge).await;
-
-                // After 25 milliseconds, pull it back high to
-                Timer::after_millis(25).await;
-                self.sdc_pin.set_high();
-            }
-
-            // Start levitating
-            (States::Demo, Event::Levitate) => {
-                self.event_sender
-                    .send(Event::FSMTransition(States::Levitating.to_index()))
-                    .await;
-            }
-            (States::Demo, Event::LeviOnAck) => {
-                self.transition(States::Levitating).await;
+                self.run_action(TransitionAction::RearmSdc, event).await;
+                self.finish_action(TransitionAction::RearmSdc).await;
             }
-
-            // Stop levitating
-            (States::Levitating, Event::StopLevitating) => {
-                self.event_sender
-                    .send(Event::FSMTransition(States::Demo.to_index()))
-                    .await
-            }
-            (States::Levitating, Event::LeviOffAck) => {
-                self.transition(States::Demo).await;
-            }
-
-            (States::Levitating, Event::Accelerate) => self.transition(States::Accelerating).await,
-            (States::Accelerating, Event::Brake) => self.transition(States::Braking).await,
-            (States::Braking, Event::Stopped) => self.transition(States::Levitating).await,
-            (States::Discharge, Event::EnterIdle) => self.transition(States::Idle).await,
             (States::Idle, Event::ShutDown) => return false,
 
-            // If the PTC goes back into Idle at any point, transition back to Idle
-            (
-                States::PreCharge
-                | States::Active
-                | States::Demo
-                | States::Discharge
-                | States::Levitating
-                | States::Accelerating
-                | States::Braking,
-                Event::PTCIdleAck,
-            ) => self.transition(States::Idle).await,
-
             // If the low pressure sensors indicate that the EBS is in the wrong state, transition
             // to fault
             (
@@ -336,45 +271,42 @@ impl FSM {
                 // Timer::after_secs(1).await;
                 if self
                     .last_pressure_check
-                    .is_some_and(|i| i.elapsed() > Duration::from_secs(1))
+                    .is_some_and(|i| self.clock.now() - i > Duration::from_secs(1))
                 {
-                    self.event_sender
-                        .send(Event::Emergency {
-                            emergency_type: EmergencyType::EmergencyWrongEbsState,
-                        })
-                        .await;
-                    self.transition(States::Fault).await;
+                    let emergency = Event::Emergency {
+                        emergency_type: EmergencyType::EmergencyWrongEbsState,
+                    };
+                    self.event_sender.send(emergency).await;
+                    self.transition(States::Fault, emergency).await;
                     self.last_pressure_check = None;
                 } else if self.last_pressure_check.is_none() {
-                    self.last_pressure_check = Some(Instant::now());
+                    self.last_pressure_check = Some(self.clock.now());
                 }
             }
             (States::Demo, Event::EbsPressureDeployed) => {
                 if self
                     .last_pressure_check
-                    .is_some_and(|i| i.elapsed() > Duration::from_millis(5000))
+                    .is_some_and(|i| self.clock.now() - i > Duration::from_millis(5000))
                 {
-                    self.event_sender
-                        .send(Event::Emergency {
-                            emergency_type: EmergencyType::EmergencyWrongEbsState,
-                        })
-                        .await;
-                    self.transition(States::Fault).await;
+                    let emergency = Event::Emergency {
+                        emergency_type: EmergencyType::EmergencyWrongEbsState,
+                    };
+                    self.event_sender.send(emergency).await;
+                    self.transition(States::Fault, emergency).await;
                     self.last_pressure_check = None;
                 } else if self.last_pressure_check.is_none() {
-                    self.last_pressure_check = Some(Instant::now());
+                    self.last_pressure_check = Some(self.clock.now());
                 }
             }
             (
                 States::Levitating | States::Accelerating | States::Braking,
                 Event::EbsPressureDeployed,
             ) => {
-                self.event_sender
-                    .send(Event::Emergency {
-                        emergency_type: EmergencyType::EmergencyWrongEbsState,
-                    })
-                    .await;
-                self.transition(States::Fault).await;
+                let emergency = Event::Emergency {
+                    emergency_type: EmergencyType::EmergencyWrongEbsState,
+                };
+                self.event_sender.send(emergency).await;
+                self.transition(States::Fault, emergency).await;
                 self.last_pressure_check = None;
             }
 
@@ -384,7 +316,7 @@ impl FSM {
 
             (_, Event::PTCFailure) if self.state != States::Fault => {
                 // 1. Trigger emergency using the sdc
-                self.sdc_pin.set_low();
+                self.sdc.set_sdc_low();
                 error!("Going into Fault state with emergency PTC Failure");
 
                 // 2. send the emergency message to all other devices on the CAN line
@@ -394,64 +326,193 @@ impl FSM {
                     })
                     .await;
 
-                self.transition(States::Fault).await
+                self.transition(States::Fault, event).await
+            }
+
+            (_, Event::Heartbeat) => self.last_heartbeat = self.clock.now(),
+
+            (_, Event::DumpFsmHistory) => self.event_sender.send(event).await,
+
+            // Everything else is looked up in the transition table generated from config.toml
+            (_, event) => self.dispatch_transition(event).await,
+        }
+        true
+    }
+
+    /// Triggers a `FrontendHeartbeatLost` emergency if the frontend hasn't sent
+    /// a heartbeat within the timeout configured in `[pod.heartbeats]`. Only
+    /// checked in states with `check_heartbeat = true`, and not during the
+    /// grace period after connecting to the ground station.
+    async fn check_heartbeat(&mut self) {
+        let Some(timeout) = heartbeat_timeout(Datatype::FrontendHeartbeating) else {
+            return;
+        };
+        let now = self.clock.now();
+        if self.state.checks_heartbeat()
+            && now > self.heartbeat_grace_end
+            && now.saturating_duration_since(self.last_heartbeat) > Duration::from_millis(timeout)
+        {
+            error!("No heartbeat from the frontend for {timeout}ms!");
+            self.handle_events(Event::Emergency {
+                emergency_type: EmergencyType::FrontendHeartbeatLost,
+            })
+            .await;
+        }
+    }
+
+    /// Falls back to the configured `timeout_state` if the FSM stayed in the
+    /// current state for longer than its `timeout`, and reports requests the
+    /// subsystems didn't acknowledge in time to the GS. Falling back to `Fault`
+    /// goes through the emergency path, so that the other devices are told and
+    /// the batteries are discharged.
+    async fn check_timeouts(&mut self) {
+        let now = self.clock.now();
+
+        if let Some((timeout, fallback)) = self.state.timeout() {
+            if now.saturating_duration_since(self.state_entered) > Duration::from_millis(timeout) {
+                warn!("{} timed out, falling back to {}", self.state, fallback);
+                let timeout = Event::StateTimeout(self.state.to_index());
+                self.event_sender.send(timeout).await;
+                if fallback == States::Fault {
+                    self.handle_events(Event::Emergency {
+                        emergency_type: EmergencyType::EmergencyStateTimeout,
+                    })
+                    .await;
+                } else {
+                    self.transition(fallback, timeout).await;
+                }
+            }
+        }
+
+        if let Some((requested, deadline)) = self.pending_request {
+            if now > deadline {
+                warn!("Request to go to {} wasn't acknowledged", requested);
+                self.pending_request = None;
+                self.event_sender
+                    .send(Event::TransitionFail(requested.to_index()))
+                    .await;
             }
+        }
+    }
 
-            (_, Event::Heartbeat) => self.last_heartbeat = Instant::now(),
-
-            // Send a message to the GS with the state in which it failed to transition in case it
-            // receives a wrong event. Doesn't apply for `Event::Stopped` since that is sent
-            // whenever we get velocity 0, which should be the case for every state except
-            // `States::Accelerating` and `States::Braking`
-            (_, event) => {
-                if let Some(failed_state_transition) = match event {
-                    Event::ResetFSM => Some(States::Boot),
-                    Event::StartPreCharge => Some(States::PreCharge),
-                    Event::Charge => Some(States::Charging),
-                    Event::StopCharge => Some(States::Active),
-                    Event::EnterDemo => Some(States::Demo),
-                    Event::Discharge => Some(States::Discharge),
-                    Event::Levitate => Some(States::Levitating),
-                    Event::StopLevitating => Some(States::Demo),
-                    Event::Accelerate => Some(States::Accelerating),
-                    Event::Brake => Some(States::Braking),
-                    Event::EnterIdle => Some(States::Idle),
-                    Event::ShutDown => Some(States::Boot),
-                    _ => None,
-                } {
+    /// Looks up the transition triggered by `event` in the current state and
+    /// executes it. If `event` doesn't trigger a transition in this state but
+    /// does in others, a `TransitionFail` is sent to the GS instead.
+    async fn dispatch_transition(&mut self, event: Event) {
+        match Transition::find(self.state, event) {
+            Some(transition) if transition.guard.is_some_and(|g| !self.check_guard(g)) => {
+                warn!("Guard of transition to {} failed", transition.to);
+                self.event_sender
+                    .send(Event::TransitionFail(transition.to.to_index()))
+                    .await;
+            }
+            // The subsystems go first, the FSM follows once they acknowledge it
+            Some(Transition {
+                to,
+                ack: Some(_),
+                ack_timeout,
+                ..
+            }) => {
+                if let Some(timeout) = ack_timeout {
+                    self.pending_request =
+                        Some((to, self.clock.now() + Duration::from_millis(timeout)));
+                }
+                self.event_sender
+                    .send(Event::FSMTransition(to.to_index()))
+                    .await
+            }
+            Some(Transition { to, action, .. }) => {
+                if let Some(action) = action {
+                    self.run_action(action, event).await;
+                }
+                self.transition(to, event).await;
+                if let Some(action) = action {
+                    self.finish_action(action).await;
+                }
+            }
+            None => {
+                if let Some(failed_state_transition) = Transition::failed_target(event) {
                     self.event_sender
                         .send(Event::TransitionFail(failed_state_transition.to_index()))
                         .await;
                 }
             }
         }
-        true
+    }
+
+    /// Checks a guard of the transition table.
+    fn check_guard(&self, guard: TransitionGuard) -> bool {
+        match guard {}
+    }
+
+    /// Runs the part of an action of the transition table that comes before
+    /// entering the new state. `event` is the event that triggered the
+    /// transition.
+    async fn run_action(&mut self, action: TransitionAction, event: Event) {
+        match action {
+            TransitionAction::ForwardEvent => {
+                warn!("Forwarding {:?}", event);
+                self.event_sender.send(event).await;
+            }
+            TransitionAction::CloseSdc => {
+                self.sdc.set_sdc_high();
+                self.clock.delay(Duration::from_millis(120)).await;
+            }
+            TransitionAction::RearmSdc => {
+                self.sdc.set_sdc_high();
+                self.clock.delay(Duration::from_millis(100)).await;
+            }
+            TransitionAction::PulseSdc => {
+                // Pull the SDC pin low to engage the brakes since you can't have the brakes
+                // retracted without high voltage turned on.
+                self.sdc.set_sdc_low();
+            }
+        }
+    }
+
+    /// Runs the part of an action of the transition table that comes after
+    /// entering the new state.
+    async fn finish_action(&mut self, action: TransitionAction) {
+        match action {
+            TransitionAction::ForwardEvent | TransitionAction::CloseSdc => {}
+            TransitionAction::RearmSdc => {
+                self.sdc.set_rearm_high();
+                self.clock.delay(Duration::from_millis(100)).await;
+                self.sdc.set_rearm_low();
+            }
+            TransitionAction::PulseSdc => {
+                // After 25 milliseconds, pull it back high
+                self.clock.delay(Duration::from_millis(25)).await;
+                self.sdc.set_sdc_high();
+            }
+        }
     }
 
     /// Marks one of the subsystems as checked while in the `SystemCheck` state.
     /// If all of them are checked, marks them as false for the next system
     /// check and transitions to the `Idle` state.
     async fn add_system_check(&mut self, system: CheckedSystem) {
-        match system {
+        let event = match system {
             CheckedSystem::Levitation => {
                 self.systems.levitation = true;
                 info!("Levi system check success!");
-                self.event_sender.send(Event::LeviSystemCheckSuccess).await;
+                Event::LeviSystemCheckSuccess
             }
             CheckedSystem::Propulsion1 => {
                 self.systems.propulsion1 = true;
                 info!("Prop1 system check success!");
-                self.event_sender.send(Event::Prop1SystemCheckSuccess).await;
+                Event::Prop1SystemCheckSuccess
             }
             CheckedSystem::Propulsion2 => {
                 self.systems.propulsion2 = true;
                 info!("Prop2 system check success!");
-                self.event_sender.send(Event::Prop2SystemCheckSuccess).await;
+                Event::Prop2SystemCheckSuccess
             }
-        }
+        };
+        self.event_sender.send(event).await;
 
         if self.systems.propulsion1 && self.systems.propulsion2 && self.systems.levitation {
-            self.transition(States::Idle).await;
+            self.transition(States::Idle, event).await;
             self.systems.levitation = false;
             self.systems.propulsion1 = false;
             self.systems.propulsion2 = false;
@@ -459,11 +520,21 @@ impl FSM {
     }
 
     /// Transitions the FSM to a new state while executing the
-    /// former state's exit method and the new state's entry method.
-    async fn transition(&mut self, new_state: States) {
+    /// former state's exit method and the new state's entry method. The
+    /// transition is recorded in the history together with the `cause`.
+    async fn transition(&mut self, new_state: States, cause: Event) {
         self.call_exit_method(self.state).await;
 
+        self.history.push(TransitionRecord {
+            from: self.state,
+            to: new_state,
+            event: cause,
+            timestamp: self.clock.now(),
+        });
+
         self.state = new_state;
+        self.state_entered = self.clock.now();
+        self.pending_request = None;
 
         self.event_sender
             .send(Event::FSMTransition(new_state.to_index()))
@@ -477,7 +548,7 @@ impl FSM {
     /// whenever a transition happens.
     async fn call_entry_method(&mut self, state: States) {
         match state {
-            States::Fault => self.sdc_pin.set_low(),
+            States::Fault => self.sdc.set_sdc_low(),
             States::Boot => {
                 // Reset PCB here
                 // SEND extra "restarting..." msg to gs
@@ -497,3 +568,7 @@ impl FSM {
         }
     }
 }
+
+#[cfg(test)]
+#[path = "tests/fsm.rs"]
+mod tests;
@@ -3,10 +3,15 @@
 //! The 'fsm' crate is used by Dh09 to keep track of the state in which the pod
 //! is. Transitions between states are triggered by pre-determined events sent
 //! from each subsystem.
+//!
+//! The FSM only talks to the hardware through the traits in [`control`], so
+//! the transition logic can be tested on a laptop with
+//! `cargo test -p fsm --target x86_64-unknown-linux-gnu`.
 
-#![no_std]
-#![no_main]
+#![cfg_attr(not(test), no_std)]
+#![cfg_attr(target_os = "none", no_main)]
 
+pub mod control;
 pub(crate) mod fsm;
 
 // TODO: Move to generated
@@ -32,6 +37,12 @@ pub enum CheckedSystem {
     Propulsion2,
 }
 
+pub use control::Clock;
+#[cfg(target_os = "none")]
+pub use control::EmbassyClock;
+pub use control::SdcControl;
+#[cfg(target_os = "none")]
+pub use control::SdcPins;
 pub use fsm::FSM;
 pub use lib::utils::data::Event;
 pub use lib::States;
@@ -0,0 +1,497 @@
+//! Host tests for the transition logic of the FSM.
+//!
+//! Run with `cargo test -p fsm --target x86_64-unknown-linux-gnu`.
+
+use core::cell::Cell;
+use core::future::Future;
+use std::vec::Vec;
+
+use embassy_futures::block_on;
+use embassy_time::Duration;
+use embassy_time::Instant;
+use lib::EmergencyType;
+use lib::Event;
+use lib::EventChannel;
+use lib::EventReceiver;
+use lib::FsmHistory;
+use lib::States;
+
+use super::FSM;
+use crate::control::Clock;
+use crate::control::SdcControl;
+
+/// defmt needs a global logger to link, the tests don't care about the output.
+#[defmt::global_logger]
+struct NoopLogger;
+
+// SAFETY: the logger does nothing, so there is nothing to synchronise.
+unsafe impl defmt::Logger for NoopLogger {
+    fn acquire() {}
+
+    unsafe fn flush() {}
+
+    unsafe fn release() {}
+
+    unsafe fn write(_bytes: &[u8]) {}
+}
+
+/// SDC pins that only remember their level.
+#[derive(Debug, Default)]
+struct MockSdc {
+    /// Whether the sdc line is pulled low
+    sdc_low: bool,
+    /// Whether the rearm pin is high
+    rearm_high: bool,
+    /// How many times the PCB was reset
+    resets: usize,
+}
+
+impl SdcControl for MockSdc {
+    fn set_sdc_high(&mut self) {
+        self.sdc_low = false;
+    }
+
+    fn set_sdc_low(&mut self) {
+        self.sdc_low = true;
+    }
+
+    fn is_sdc_low(&self) -> bool {
+        self.sdc_low
+    }
+
+    fn set_rearm_high(&mut self) {
+        self.rearm_high = true;
+    }
+
+    fn set_rearm_low(&mut self) {
+        self.rearm_high = false;
+    }
+
+    fn reset(&mut self) {
+        self.resets += 1;
+    }
+}
+
+/// Clock that only moves when the FSM waits or the test advances it.
+#[derive(Debug)]
+struct MockClock {
+    /// The current time
+    now: Cell<Instant>,
+}
+
+impl MockClock {
+    /// Moves the clock forward by `duration`.
+    fn advance(&self, duration: Duration) {
+        self.now.set(self.now.get() + duration);
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        self.now.get()
+    }
+
+    fn delay(&self, duration: Duration) -> impl Future<Output = ()> {
+        self.advance(duration);
+        core::future::ready(())
+    }
+}
+
+/// Every state the pod can be in.
+const ALL_STATES: [States; 13] = [
+    States::Boot,
+    States::ConnectedToGS,
+    States::SystemCheck,
+    States::Idle,
+    States::PreCharge,
+    States::Active,
+    States::Demo,
+    States::Levitating,
+    States::Accelerating,
+    States::Braking,
+    States::Discharge,
+    States::Charging,
+    States::Fault,
+];
+
+/// One instance of every event the FSM can receive.
+const ALL_EVENTS: [Event; 44] = [
+    Event::Emergency {
+        emergency_type: EmergencyType::GeneralEmergency,
+    },
+    Event::PTCFailure,
+    Event::Prop2SystemCheckFailure,
+    Event::Prop1SystemCheckFailure,
+    Event::LeviSystemCheckFailure,
+    Event::TransitionFail(0),
+    Event::StateTimeout(0),
+    Event::ConnectToGS,
+    Event::EbsPressureDeployed,
+    Event::EbsPressureRetracted,
+    Event::StartSystemCheck,
+    Event::EnterIdle,
+    Event::StartPreCharge,
+    Event::HVOnAck,
+    Event::PTCIdleAck,
+    Event::EnterDemo,
+    Event::LocalizationLimitReached,
+    Event::Levitate,
+    Event::StopLevitating,
+    Event::Accelerate,
+    Event::Brake,
+    Event::Stopped,
+    Event::Discharge,
+    Event::ShutDown,
+    Event::Charge,
+    Event::StopCharge,
+    Event::ResetFSM,
+    Event::FaultFixed,
+    Event::FSMTransition(0),
+    Event::FSMHeartbeat(0),
+    Event::LeviOnAck,
+    Event::LeviOffAck,
+    Event::PropulsionAck1,
+    Event::PropulsionAck2,
+    Event::PowertrainAck,
+    Event::ClearFaultAckLevi,
+    Event::LeviSystemCheckSuccess,
+    Event::Prop1SystemCheckSuccess,
+    Event::Prop2SystemCheckSuccess,
+    Event::OverrideRearmSdc,
+    Event::Heartbeat,
+    Event::NoEvent,
+    Event::SendHashes,
+    Event::DumpFsmHistory,
+];
+
+/// Creates an FSM in `state` with mock hardware, and the receiver for the
+/// events it sends out.
+fn fsm_in(state: States) -> (FSM<MockSdc, MockClock>, EventReceiver) {
+    let channel_in: &'static EventChannel = Box::leak(Box::new(EventChannel::new()));
+    let channel_out: &'static EventChannel = Box::leak(Box::new(EventChannel::new()));
+    let history: &'static FsmHistory = Box::leak(Box::new(FsmHistory::new()));
+    let clock = MockClock {
+        now: Cell::new(Instant::from_secs(1)),
+    };
+    let mut fsm = block_on(FSM::new(
+        channel_in.receiver(),
+        channel_out.sender(),
+        MockSdc::default(),
+        clock,
+        history,
+    ));
+    fsm.state = state;
+    (fsm, channel_out.receiver())
+}
+
+/// Empties the outgoing channel, returning everything the FSM sent.
+fn drain(receiver: &EventReceiver) -> Vec<Event> {
+    core::iter::from_fn(|| receiver.try_receive()).collect()
+}
+
+/// The state the FSM should be in after receiving `event` in `state`, if the
+/// FSM starts without any pending pressure check.
+fn expected(state: States, event: Event) -> States {
+    use States::*;
+    match (state, event) {
+        (Fault, Event::FaultFixed) => SystemCheck,
+        (Fault, _) => Fault,
+        (_, Event::Emergency { .. } | Event::PTCFailure) => Fault,
+        (Boot, Event::ConnectToGS) => ConnectedToGS,
+        (ConnectedToGS | SystemCheck, Event::StartSystemCheck) => SystemCheck,
+        (
+            SystemCheck,
+            Event::Prop1SystemCheckFailure
+            | Event::Prop2SystemCheckFailure
+            | Event::LeviSystemCheckFailure,
+        ) => Fault,
+        (Accelerating, Event::LocalizationLimitReached) => Braking,
+        (Idle, Event::StartPreCharge) => PreCharge,
+        (PreCharge, Event::HVOnAck) => Active,
+        (Active, Event::Charge) => Charging,
+        (Charging, Event::StopCharge) => Active,
+        (Active, Event::EnterDemo) => Demo,
+        (Demo, Event::Discharge) => Discharge,
+        (Demo, Event::LeviOnAck) => Levitating,
+        (Levitating, Event::LeviOffAck) => Demo,
+        (Levitating, Event::Accelerate) => Accelerating,
+        (Accelerating, Event::Brake) => Braking,
+        (Braking, Event::Stopped) => Levitating,
+        (Discharge, Event::EnterIdle) => Idle,
+        (
+            PreCharge | Active | Demo | Discharge | Levitating | Accelerating | Braking,
+            Event::PTCIdleAck,
+        ) => Idle,
+        (Levitating | Accelerating | Braking, Event::EbsPressureDeployed) => Fault,
+        (s, _) => s,
+    }
+}
+
+/// Checks the resulting state of every (state, event) pair.
+#[test]
+fn every_state_event_pair() {
+    for state in ALL_STATES {
+        for event in ALL_EVENTS {
+            let (mut fsm, out) = fsm_in(state);
+            let keep_running = block_on(fsm.handle_events(event));
+            drain(&out);
+
+            assert_eq!(
+                fsm.state,
+                expected(state, event),
+                "{state:?} + {event:?} ended up in the wrong state"
+            );
+            assert_eq!(
+                keep_running,
+                !(state == States::Idle && event == Event::ShutDown)
+            );
+            assert_eq!(fsm.sdc.resets, usize::from(event == Event::ResetFSM));
+            if fsm.state == States::Fault && state != States::Fault {
+                assert!(
+                    fsm.sdc.sdc_low,
+                    "{state:?} + {event:?} left the sdc high in Fault"
+                );
+            }
+        }
+    }
+}
+
+/// Transitions are sent out so that the GS and CAN know about them.
+#[test]
+fn transitions_are_reported() {
+    let (mut fsm, out) = fsm_in(States::Boot);
+    block_on(fsm.handle_events(Event::ConnectToGS));
+    assert_eq!(
+        drain(&out),
+        [Event::FSMTransition(States::ConnectedToGS.to_index())]
+    );
+}
+
+/// Every transition ends up in the history, together with what caused it.
+#[test]
+fn transitions_are_recorded_in_the_history() {
+    let (mut fsm, out) = fsm_in(States::Boot);
+    block_on(fsm.handle_events(Event::ConnectToGS));
+    fsm.clock.advance(Duration::from_millis(250));
+    let emergency = Event::Emergency {
+        emergency_type: EmergencyType::EmergencyBMS,
+    };
+    block_on(fsm.handle_events(emergency));
+    drain(&out);
+
+    let history = fsm.history.snapshot();
+    assert_eq!(history.len(), 2);
+    assert_eq!(
+        (history[0].from, history[0].to, history[0].event),
+        (States::Boot, States::ConnectedToGS, Event::ConnectToGS)
+    );
+    assert_eq!(
+        (history[1].from, history[1].to, history[1].event),
+        (States::ConnectedToGS, States::Fault, emergency)
+    );
+    assert_eq!(
+        history[1].timestamp - history[0].timestamp,
+        Duration::from_millis(250)
+    );
+}
+
+/// Other tasks can tell the state of the FSM from its history.
+#[test]
+fn current_state_follows_the_history() {
+    let (mut fsm, out) = fsm_in(States::Boot);
+    assert_eq!(fsm.history.current_state(), States::Boot);
+    block_on(fsm.handle_events(Event::ConnectToGS));
+    drain(&out);
+    assert_eq!(fsm.history.current_state(), States::ConnectedToGS);
+}
+
+/// Events that can't be handled in the current state are reported to the GS.
+#[test]
+fn rejected_transitions_are_reported() {
+    let (mut fsm, out) = fsm_in(States::Boot);
+    block_on(fsm.handle_events(Event::StartPreCharge));
+    assert_eq!(fsm.state, States::Boot);
+    assert_eq!(
+        drain(&out),
+        [Event::TransitionFail(States::PreCharge.to_index())]
+    );
+}
+
+/// Shutting down is only possible from Idle.
+#[test]
+fn shutdown_outside_idle_is_reported() {
+    let (mut fsm, out) = fsm_in(States::Active);
+    assert!(block_on(fsm.handle_events(Event::ShutDown)));
+    assert_eq!(fsm.state, States::Active);
+    assert_eq!(
+        drain(&out),
+        [Event::TransitionFail(States::Boot.to_index())]
+    );
+}
+
+/// The pod only leaves the system check after all subsystems passed it.
+#[test]
+fn system_check_needs_every_subsystem() {
+    let (mut fsm, out) = fsm_in(States::SystemCheck);
+    block_on(fsm.handle_events(Event::LeviSystemCheckSuccess));
+    block_on(fsm.handle_events(Event::Prop1SystemCheckSuccess));
+    assert_eq!(fsm.state, States::SystemCheck);
+    block_on(fsm.handle_events(Event::Prop2SystemCheckSuccess));
+    assert_eq!(fsm.state, States::Idle);
+    assert!(!fsm.systems.levitation && !fsm.systems.propulsion1 && !fsm.systems.propulsion2);
+    drain(&out);
+}
+
+/// Retracted brakes are only an emergency if they stay retracted for a second.
+#[test]
+fn retracted_brakes_outside_demo_cause_emergency_after_a_second() {
+    let (mut fsm, out) = fsm_in(States::Idle);
+    block_on(fsm.handle_events(Event::EbsPressureRetracted));
+    assert_eq!(fsm.state, States::Idle);
+
+    fsm.clock.advance(Duration::from_millis(500));
+    block_on(fsm.handle_events(Event::EbsPressureRetracted));
+    assert_eq!(fsm.state, States::Idle);
+
+    fsm.clock.advance(Duration::from_millis(600));
+    block_on(fsm.handle_events(Event::EbsPressureRetracted));
+    assert_eq!(fsm.state, States::Fault);
+    assert!(drain(&out).contains(&Event::Emergency {
+        emergency_type: EmergencyType::EmergencyWrongEbsState
+    }));
+}
+
+/// Entering demo pulls the sdc high again and pulses the rearm pin.
+#[test]
+fn entering_demo_rearms_the_sdc() {
+    let (mut fsm, out) = fsm_in(States::Active);
+    let start = fsm.clock.now();
+    fsm.sdc.sdc_low = true;
+    block_on(fsm.handle_events(Event::EnterDemo));
+    assert_eq!(fsm.state, States::Demo);
+    assert!(!fsm.sdc.sdc_low);
+    assert!(!fsm.sdc.rearm_high);
+    // the rearm pin is pulsed after entering demo
+    assert_eq!(
+        fsm.history.snapshot()[0].timestamp,
+        start + Duration::from_millis(100)
+    );
+    drain(&out);
+}
+
+/// Discharging pulls the sdc low before entering Discharge, and only pulls it
+/// high again 25ms after.
+#[test]
+fn discharging_pulses_the_sdc_after_the_transition() {
+    let (mut fsm, out) = fsm_in(States::Demo);
+    let start = fsm.clock.now();
+    block_on(fsm.handle_events(Event::Discharge));
+    assert_eq!(fsm.state, States::Discharge);
+    assert!(!fsm.sdc.sdc_low);
+    assert_eq!(fsm.history.snapshot()[0].timestamp, start);
+    assert_eq!(fsm.clock.now() - start, Duration::from_millis(25));
+    drain(&out);
+}
+
+/// Levitation is only requested, the FSM follows once levi acknowledges it.
+#[test]
+fn levitation_waits_for_ack() {
+    let (mut fsm, out) = fsm_in(States::Demo);
+    block_on(fsm.handle_events(Event::Levitate));
+    assert_eq!(fsm.state, States::Demo);
+    assert_eq!(
+        drain(&out),
+        [Event::FSMTransition(States::Levitating.to_index())]
+    );
+
+    block_on(fsm.handle_events(Event::LeviOnAck));
+    assert_eq!(fsm.state, States::Levitating);
+    drain(&out);
+}
+
+/// Losing the frontend faults the pod, but not during the grace period after
+/// connecting.
+#[test]
+fn frontend_heartbeat_watchdog() {
+    let timeout = lib::config::heartbeat_timeout(lib::config::Datatype::FrontendHeartbeating)
+        .expect("FrontendHeartbeating has no timeout in [pod.heartbeats]");
+    let (mut fsm, out) = fsm_in(States::Boot);
+    block_on(fsm.handle_events(Event::ConnectToGS));
+    assert!(fsm.state.checks_heartbeat());
+
+    fsm.clock.advance(Duration::from_millis(timeout + 1));
+    block_on(fsm.check_heartbeat());
+    assert_eq!(fsm.state, States::ConnectedToGS);
+
+    fsm.clock
+        .advance(Duration::from_millis(lib::config::HEARTBEAT_GRACE_PERIOD));
+    block_on(fsm.handle_events(Event::Heartbeat));
+    block_on(fsm.check_heartbeat());
+    assert_eq!(fsm.state, States::ConnectedToGS);
+
+    fsm.clock.advance(Duration::from_millis(timeout + 1));
+    block_on(fsm.check_heartbeat());
+    assert_eq!(fsm.state, States::Fault);
+    assert!(drain(&out).contains(&Event::Emergency {
+        emergency_type: EmergencyType::FrontendHeartbeatLost
+    }));
+}
+
+/// Pre-charging that takes too long is aborted.
+#[test]
+fn precharge_times_out() {
+    let (timeout, fallback) = States::PreCharge
+        .timeout()
+        .expect("PreCharge has no timeout");
+    let (mut fsm, out) = fsm_in(States::Idle);
+    block_on(fsm.handle_events(Event::StartPreCharge));
+
+    fsm.clock.advance(Duration::from_millis(timeout));
+    block_on(fsm.check_timeouts());
+    assert_eq!(fsm.state, States::PreCharge);
+
+    fsm.clock.advance(Duration::from_millis(1));
+    block_on(fsm.check_timeouts());
+    assert_eq!(fsm.state, fallback);
+    assert!(drain(&out).contains(&Event::StateTimeout(States::PreCharge.to_index())));
+}
+
+/// Braking that never finishes is an emergency, which the other devices are
+/// told about.
+#[test]
+fn braking_times_out_into_an_emergency() {
+    let (timeout, fallback) = States::Braking.timeout().expect("Braking has no timeout");
+    assert_eq!(fallback, States::Fault);
+    let (mut fsm, out) = fsm_in(States::Accelerating);
+    block_on(fsm.handle_events(Event::Brake));
+    drain(&out);
+
+    fsm.clock.advance(Duration::from_millis(timeout + 1));
+    block_on(fsm.check_timeouts());
+    assert_eq!(fsm.state, States::Fault);
+    assert!(fsm.sdc.sdc_low);
+    let sent = drain(&out);
+    assert!(sent.contains(&Event::Emergency {
+        emergency_type: EmergencyType::EmergencyStateTimeout
+    }));
+    assert!(sent.contains(&Event::Discharge));
+}
+
+/// A levitation request that levi never acknowledges is reported to the GS.
+#[test]
+fn unacknowledged_request_is_reported() {
+    let (mut fsm, out) = fsm_in(States::Demo);
+    block_on(fsm.handle_events(Event::Levitate));
+    drain(&out);
+
+    fsm.clock.advance(Duration::from_secs(60));
+    block_on(fsm.check_timeouts());
+    assert_eq!(fsm.state, States::Demo);
+    assert_eq!(
+        drain(&out),
+        [Event::TransitionFail(States::Levitating.to_index())]
+    );
+
+    block_on(fsm.check_timeouts());
+    assert!(drain(&out).is_empty());
+}
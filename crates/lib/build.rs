@@ -17,13 +17,17 @@ use goose_utils::fmt::run_fmt;
 
 extern crate serde;
 
+use std::collections::BTreeMap;
 use std::env;
 use std::fs;
 use std::path::Path;
 use std::path::PathBuf;
 
 use anyhow::Result;
+use goose_utils::fsm_states::generate_state_timeouts;
+use goose_utils::fsm_states::generate_transitions;
 use goose_utils::fsm_states::FSMState;
+use goose_utils::fsm_states::Transition;
 use goose_utils::hash_config;
 use serde::Deserialize;
 /*
@@ -37,6 +41,7 @@ struct Config {
     gs: GS,
     pod: Pod,
     FSMState: Vec<FSMState>,
+    Transition: Vec<Transition>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -52,7 +57,11 @@ struct GS {
 struct Pod {
     net: NetConfig,
     internal: InternalConfig,
+    can: CanConfig,
     comm: Comm,
+    /// Sorted, so that the generated code is the same on every build
+    #[serde(default)]
+    heartbeats: BTreeMap<String, u64>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -76,12 +85,27 @@ struct InternalConfig {
     data_queue_size: usize,
     can_queue_size: usize,
     end_of_track_limit: u32,
+    heartbeat_grace_period: u64,
+    fsm_history_size: usize,
+    limit_debounce: u8,
+}
+
+#[derive(Debug, Deserialize)]
+struct CanConfig {
+    can1_bitrate: u32,
+    can1_data_bitrate: u32,
+    can2_bitrate: u32,
+    health_report_period: u64,
+    bus_off_timeout: u64,
+    retransmit_queue_size: usize,
 }
 
 /// Path to config file
 pub const CONFIG_PATH: &str = "../../config/config.toml";
 /// Path to dataflow file
 pub const DATAFLOW_PATH: &str = "../../config/dataflow.yaml";
+/// Path to the file defining the FSM events the dataflow can refer to
+pub const EVENTS_PATH: &str = "src/utils/data.rs";
 
 fn main() -> Result<()> {
     let out_dir = env::var("OUT_DIR")?;
@@ -92,8 +116,13 @@ fn main() -> Result<()> {
 
     let mut content = String::from("//@generated\n");
 
-    let df = std::fs::read_to_string(DATAFLOW_PATH)?;
-    let df = goose_utils::dataflow::parse_from(&df);
+    let df = goose_utils::dataflow::validate::load(DATAFLOW_PATH, EVENTS_PATH)?;
+    for warning in goose_utils::dataflow::validate::warnings(&df) {
+        println!(
+            "cargo::warning={DATAFLOW_PATH} ({}): {}",
+            warning.path, warning.message
+        );
+    }
 
     content.push_str(&goose_utils::logs::diy_ln());
     // content.push_str(&check_config(DATAFLOW_PATH, )?);
@@ -103,11 +132,16 @@ fn main() -> Result<()> {
     content.push_str(&configure_gs_ips(&config.gs.ips, config.gs.port));
     content.push_str(&configure_pod(&config));
     content.push_str(&configure_internal(&config));
+    content.push_str(&configure_can(&config));
+    content.push_str(&configure_heartbeats(&config));
     let commands = goose_utils::dataflow::collect_commands(&df);
     content.push_str(&goose_utils::commands::generate_commands_from_config(
         &commands, true,
     ));
+    content.push_str(&goose_utils::dataflow::payloads::make_payload_code(&df));
     content.push_str(&generate_fsm_states(&config));
+    content.push_str(&generate_state_timeouts(&config.FSMState));
+    content.push_str(&generate_transitions(&config.FSMState, &config.Transition));
     content.push_str(&goose_utils::info::generate_info(CONFIG_PATH, false)?);
     let dt = goose_utils::dataflow::collect_data_types(&df);
     let dt = goose_utils::datatypes::generate_data_types_from_config(&dt, false)?;
@@ -138,6 +172,7 @@ fn main() -> Result<()> {
 
     println!("cargo::rerun-if-changed={CONFIG_PATH}");
     println!("cargo::rerun-if-changed={DATAFLOW_PATH}");
+    println!("cargo::rerun-if-changed={EVENTS_PATH}");
 
     // By default, Cargo will re-run a build script whenever
     // any file in the project changes. By specifying `memory.x`
@@ -191,6 +226,28 @@ fn configure_pod(config: &Config) -> String {
     ) + &format!("pub const HEARTBEAT: u64 = {};\n", config.gs.heartbeat)
 }
 
+fn configure_can(config: &Config) -> String {
+    format!(
+        "pub const CAN1_BITRATE: u32 = {};\n",
+        config.pod.can.can1_bitrate
+    ) + &*format!(
+        "pub const CAN1_DATA_BITRATE: u32 = {};\n",
+        config.pod.can.can1_data_bitrate
+    ) + &*format!(
+        "pub const CAN2_BITRATE: u32 = {};\n",
+        config.pod.can.can2_bitrate
+    ) + &*format!(
+        "pub const CAN_HEALTH_REPORT_PERIOD: u64 = {};\n",
+        config.pod.can.health_report_period
+    ) + &*format!(
+        "pub const CAN_BUS_OFF_TIMEOUT: u64 = {};\n",
+        config.pod.can.bus_off_timeout
+    ) + &*format!(
+        "pub const CAN_RETRANSMIT_QUEUE_SIZE: usize = {};\n",
+        config.pod.can.retransmit_queue_size
+    )
+}
+
 fn configure_internal(config: &Config) -> String {
     format!(
         "pub const EVENT_QUEUE_SIZE: usize = {};\n",
@@ -204,6 +261,15 @@ fn configure_internal(config: &Config) -> String {
     ) + &*format!(
         "pub const END_OF_TRACK_LIMIT: u32 = {};\n",
         config.pod.internal.end_of_track_limit
+    ) + &*format!(
+        "pub const HEARTBEAT_GRACE_PERIOD: u64 = {};\n",
+        config.pod.internal.heartbeat_grace_period
+    ) + &*format!(
+        "pub const FSM_HISTORY_SIZE: usize = {};\n",
+        config.pod.internal.fsm_history_size
+    ) + &*format!(
+        "pub const LIMIT_DEBOUNCE: u8 = {};\n",
+        config.pod.internal.limit_debounce
     ) + &*format!(
         "pub const LV_IDS: [u16;{}] = [{}];\n",
         config.pod.comm.bms_lv_ids.len(),
@@ -269,6 +335,25 @@ fn configure_internal(config: &Config) -> String {
     )
 }
 
+fn configure_heartbeats(config: &Config) -> String {
+    format!(
+        "\n/// Timeout in milliseconds of the heartbeats in `[pod.heartbeats]`
+pub fn heartbeat_timeout(datatype: Datatype) -> Option<u64> {{
+    match datatype {{
+{}
+        _ => None,
+    }}
+}}\n",
+        config
+            .pod
+            .heartbeats
+            .iter()
+            .map(|(datatype, timeout)| format!("\t\tDatatype::{datatype} => Some({timeout}),"))
+            .collect::<Vec<String>>()
+            .join("\n")
+    )
+}
+
 fn generate_fsm_states(config: &Config) -> String {
     format!(
         "\n\n/// Enum representing the different states that the `MainFSM` will be in
@@ -291,6 +376,14 @@ impl States {{
 {}
         }}
     }}
+
+    /// Whether the frontend heartbeat is checked in this state
+    pub fn checks_heartbeat(&self) -> bool {{
+        match self {{
+{}
+            _ => false,
+        }}
+    }}
 }}",
         config
             .FSMState
@@ -310,7 +403,14 @@ impl States {{
             .iter()
             .map(|x| format!("\t\t\tStates::{} => {}", x.state, x.index))
             .collect::<Vec<String>>()
-            .join(",\n")
+            .join(",\n"),
+        config
+            .FSMState
+            .iter()
+            .filter(|x| x.check_heartbeat)
+            .map(|x| format!("\t\t\tStates::{} => true,", x.state))
+            .collect::<Vec<String>>()
+            .join("\n")
     )
 }
 
@@ -4,12 +4,18 @@ use embassy_stm32::can::frame::FdFrame;
 use embassy_time::Instant;
 use embedded_can::Id;
 
-/// CanEvnelope used for making CAN messages
+/// Envelope for CAN-FD messages
 #[derive(Debug, Clone)]
 pub struct CanEnvelope {
     pub envelope: embassy_stm32::can::frame::FdEnvelope,
 }
 
+impl defmt::Format for CanEnvelope {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "{:?}", &self.envelope.frame);
+    }
+}
+
 impl CanEnvelope {
     /// Makes a new `CanEnvelope` object from an `FdFrame`
     pub fn new_from_frame(frame: FdFrame) -> Self {
@@ -21,20 +27,28 @@ impl CanEnvelope {
         }
     }
 
-    /// Returns the ID of the envelope
-    pub fn id(&self) -> &Id {
+    /// Makes a `CanEnvelope` from an extended ID with the provided payload
+    pub fn new_with_id(id: u32, payload: &[u8]) -> Self {
+        Self::new_from_frame(FdFrame::new_extended(id, payload).unwrap())
+    }
+}
+
+impl super::CanEnvelope for CanEnvelope {
+    fn id(&self) -> &Id {
         self.envelope.frame.id()
     }
 
-    /// Returns the payload of the envelope
-    pub fn payload(&self) -> &[u8] {
+    fn payload(&self) -> &[u8] {
         self.envelope.frame.data()
     }
 
-    /// Returns the timestamp of the envelope
-    pub fn timestamp(&self) -> Instant {
+    fn timestamp(&self) -> Instant {
         self.envelope.ts
     }
+
+    fn is_fd(&self) -> bool {
+        self.envelope.frame.header().fdcan()
+    }
 }
 
 impl core::cmp::PartialEq for CanEnvelope {
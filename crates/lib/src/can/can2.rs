@@ -31,21 +31,24 @@ impl CanEnvelope {
     pub fn new_with_id(id: u16, payload: &[u8]) -> Self {
         Self::new_from_frame(Frame::new_standard(id, payload).unwrap())
     }
+}
 
-    /// Returns the ID of the envelope
-    pub fn id(&self) -> &Id {
+impl super::CanEnvelope for CanEnvelope {
+    fn id(&self) -> &Id {
         self.envelope.frame.id()
     }
 
-    /// Returns the payload of the envelope
-    pub fn payload(&self) -> &[u8] {
+    fn payload(&self) -> &[u8] {
         self.envelope.frame.data()
     }
 
-    /// Returns the timestamp of the envelope
-    pub fn timestamp(&self) -> Instant {
+    fn timestamp(&self) -> Instant {
         self.envelope.ts
     }
+
+    fn is_fd(&self) -> bool {
+        false
+    }
 }
 
 impl core::cmp::PartialEq for CanEnvelope {
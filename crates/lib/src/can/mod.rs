@@ -1,6 +1,81 @@
-//! two separate instances give type restrictions for which bus to use
+//! Envelopes for messages on the two CAN buses.
+//!
+//! Each bus has its own envelope type, which gives type restrictions for which
+//! bus to use: [`can1::CanEnvelope`] wraps a CAN-FD frame and
+//! [`can2::CanEnvelope`] a classic CAN frame. Code that only looks at the
+//! contents of a message can use the [`CanEnvelope`] trait, which both
+//! implement.
+pub mod can1;
 pub mod can2;
-pub mod fdcan;
-// todo:
-// + trait to unify FdEnvelope and Envelope
-// + trait to unify CAN and FDCAN
+
+use embassy_time::Duration;
+use embassy_time::Instant;
+use embedded_can::Id;
+
+/// A CAN message received from or sent over either bus
+pub trait CanEnvelope {
+    /// Returns the ID of the envelope
+    fn id(&self) -> &Id;
+
+    /// Returns the payload of the envelope
+    fn payload(&self) -> &[u8];
+
+    /// Returns the timestamp of the envelope
+    fn timestamp(&self) -> Instant;
+
+    /// Whether the frame is a CAN-FD frame
+    fn is_fd(&self) -> bool;
+
+    /// Whether the frame has an extended (29-bit) ID
+    fn is_extended(&self) -> bool {
+        matches!(self.id(), Id::Extended(_))
+    }
+
+    /// Returns the ID of the envelope as a number, which is how the IDs are
+    /// listed in the dataflow
+    fn raw_id(&self) -> u32 {
+        raw_id(self.id())
+    }
+}
+
+/// Returns a CAN ID as a number, which is how the IDs are listed in the
+/// dataflow
+pub fn raw_id(id: &Id) -> u32 {
+    match id {
+        Id::Standard(s) => s.as_raw() as u32,
+        Id::Extended(e) => e.as_raw(),
+    }
+}
+
+/// What to do when a frame is dropped from the transmit mailboxes before it
+/// was sent, as configured per command in the dataflow
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct RetransmitPolicy {
+    /// How many times the frame is sent again
+    pub retries: u8,
+    /// Time to wait before the first retry, doubled after every retry
+    pub backoff: Duration,
+    /// Whether the ground station is told when the frame could not be
+    /// delivered after all retries
+    pub must_ack: bool,
+}
+
+impl RetransmitPolicy {
+    /// Time to wait before retry number `attempt`, starting at 1.
+    pub fn delay(&self, attempt: u8) -> Duration {
+        self.backoff * (1 << attempt.saturating_sub(1).min(16))
+    }
+}
+
+/// The acknowledgements the main PCB waits for after sending a command, from
+/// the `ack` of the command in the dataflow.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ExpectedAcks {
+    /// ID of the command, as known by the ground station
+    pub command: u16,
+    /// CAN IDs of the acknowledgements, one per subsystem that should answer
+    pub ids: &'static [u32],
+    /// Time after sending the command within which every acknowledgement
+    /// should arrive
+    pub timeout: Duration,
+}
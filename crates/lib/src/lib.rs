@@ -1,5 +1,6 @@
-#![no_std]
+#![cfg_attr(not(test), no_std)]
 
+#[cfg(target_os = "none")]
 pub mod can;
 pub mod utils;
 
@@ -17,11 +18,35 @@ pub mod config {
 }
 
 // export these so they're visible under `lib::`
+pub use utils::data::DecodeError;
 pub use utils::data::EmergencyType;
 pub use utils::data::Event;
+pub use utils::data::PriorityClass;
 pub use utils::datapoint::Datapoint;
 pub use utils::event_types::EventChannel;
 pub use utils::event_types::EventReceiver;
 pub use utils::event_types::EventSender;
+pub use utils::history::FsmHistory;
+pub use utils::history::TransitionRecord;
 
 pub use crate::config::States;
+pub use crate::config::Transition;
+pub use crate::config::TransitionAction;
+pub use crate::config::TransitionGuard;
+
+/// defmt needs a global logger to link, the tests don't care about the output.
+#[cfg(test)]
+#[defmt::global_logger]
+struct NoopLogger;
+
+// SAFETY: the logger does nothing, so there is nothing to synchronise.
+#[cfg(test)]
+unsafe impl defmt::Logger for NoopLogger {
+    fn acquire() {}
+
+    unsafe fn flush() {}
+
+    unsafe fn release() {}
+
+    unsafe fn write(_bytes: &[u8]) {}
+}
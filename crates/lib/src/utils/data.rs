@@ -3,165 +3,385 @@
 
 /// Enum representing different types of events that the FSMs should handle.
 ///
-/// Since events are sent over a priority channel,
-/// they are prioritised by the order they're defined in here,
-/// with events defined earlier being higher priority.
-#[derive(Clone, PartialEq, Eq, Debug, Copy, defmt::Format, PartialOrd, Ord)]
+/// The discriminant of each variant is its tag in the binary encoding (see
+/// `Event::to_bytes`), and has to stay the same when variants are reordered or
+/// added. Events are sent over an `EventChannel` and prioritised by their
+/// `PriorityClass` (see `Event::priority`), not by the order they're defined in
+/// here.
+#[derive(Clone, PartialEq, Eq, Debug, Copy, defmt::Format)]
 #[repr(u8)]
 pub enum Event {
     /// Emergency event that must trigger the emergency braking system
     Emergency {
         /// The type of emergency
         emergency_type: EmergencyType,
-    },
+    } = 0,
     /// Triggered whenever the PTC goes into failure
-    PTCFailure,
+    PTCFailure = 1,
     /// Propulsion motor 2 failed the system check
-    Prop2SystemCheckFailure,
+    Prop2SystemCheckFailure = 2,
     /// Propulsion motor 1 failed the system check
-    Prop1SystemCheckFailure,
+    Prop1SystemCheckFailure = 3,
     /// Levi failed the system check
-    LeviSystemCheckFailure,
+    LeviSystemCheckFailure = 4,
     /// Event sent by the FSM whenever a transition fails
     /// - `u8`: The state in which the FSM didn't transition.
-    TransitionFail(u8),
+    TransitionFail(u8) = 5,
+    /// Event sent by the FSM when it stayed in a state for longer than the
+    /// `timeout` configured for it
+    /// - `u8`: The state that timed out.
+    StateTimeout(u8) = 6,
     /// Connection to the Ground Station has been established
-    ConnectToGS,
+    ConnectToGS = 7,
     /// Pressure readings indicate that the EBS should be deployed
-    EbsPressureDeployed,
+    EbsPressureDeployed = 8,
     /// Pressure readings indicate that the EBS should be retracted  
-    EbsPressureRetracted,
+    EbsPressureRetracted = 9,
     /// Start system check
-    StartSystemCheck,
+    StartSystemCheck = 10,
     /// Enters `Idle` state from `Discharge` state
-    EnterIdle,
+    EnterIdle = 11,
     /// Starts the pre-charging process
-    StartPreCharge,
+    StartPreCharge = 12,
     /// Will turn on high voltage while SDC is closed and brakes are deployed
-    HVOnAck,
+    HVOnAck = 13,
     /// Ack used after discharge to go back to idle
-    PTCIdleAck,
+    PTCIdleAck = 14,
     /// Enters the demo state armed brakes, SDC still closed
-    EnterDemo,
+    EnterDemo = 15,
     /// Event sent when the readings from the localization sensor are higher
     /// than the limit set in the autogenerated file
-    LocalizationLimitReached,
+    LocalizationLimitReached = 16,
     /// Command from the ground station to start levitating. Will wait for
     /// acknowledgement.
-    Levitate,
+    Levitate = 17,
     /// Command from the ground station to stop levitating. Will wait for
     /// acknowledgement.
-    StopLevitating,
+    StopLevitating = 18,
     /// Starts accelerating
-    Accelerate,
+    Accelerate = 19,
     /// Brakes with the motor
-    Brake,
+    Brake = 20,
     /// Used for transitioning from braking to levitating when the speed of the
     /// pod is 0
-    Stopped,
+    Stopped = 21,
     /// Starts discharging
-    Discharge,
+    Discharge = 22,
     /// Shuts down the pod (?)
-    ShutDown,
+    ShutDown = 23,
     /// Pod should start charging
-    Charge,
+    Charge = 24,
     /// Pod should stop charging
-    StopCharge,
+    StopCharge = 25,
     /// Resets the FSM to the `Boot` state
-    ResetFSM,
+    ResetFSM = 26,
     /// Used to transition from `Fault` to `SystemCheck` when the fault is fixed
     /// and no reboot is required
-    FaultFixed,
+    FaultFixed = 27,
     /// Event sent when transitioning. Used to send the `FSMUpdate` CAN message.
     /// - `u8`: State in which the FSM transitioned
-    FSMTransition(u8),
+    FSMTransition(u8) = 28,
     /// Event sent periodically to the ground station to indicate the state that
     /// we are in.
-    FSMHeartbeat(u8),
+    FSMHeartbeat(u8) = 29,
     /// Acknowledgement received from levi that we are levitating
-    LeviOnAck,
+    LeviOnAck = 30,
     /// Acknowledgement that levi is off (not levitating)
-    LeviOffAck,
+    LeviOffAck = 31,
     /// Acknowledgement received from the first propulsion motor that their FSM
     /// also transitioned to new state
-    PropulsionAck1,
+    PropulsionAck1 = 32,
     /// Acknowledgement received from the second propulsion motor that their FSM
     /// also transitioned to new state
-    PropulsionAck2,
+    PropulsionAck2 = 33,
     /// Acknowledgement received from powertrain that their FSM also
     /// transitioned to new state
-    PowertrainAck,
+    PowertrainAck = 34,
     /// Acknowledgement for levi fault clear
-    ClearFaultAckLevi,
+    ClearFaultAckLevi = 35,
     /// Acknowledgement that levi passed the system check
-    LeviSystemCheckSuccess,
+    LeviSystemCheckSuccess = 36,
     /// Acknowledgement that propulsion motor 1 passed the system check
-    Prop1SystemCheckSuccess,
+    Prop1SystemCheckSuccess = 37,
     /// Acknowledgement that propulsion motor 2 passed the system check
-    Prop2SystemCheckSuccess,
+    Prop2SystemCheckSuccess = 38,
     /// Override event for rearming the sdc (only used for testing)
-    OverrideRearmSdc,
+    OverrideRearmSdc = 39,
     /// <3
-    Heartbeat,
+    Heartbeat = 40,
     /// No event happened
-    NoEvent,
+    NoEvent = 41,
     /// Event used to resend the hashes to the ground station
-    SendHashes,
-
-    /// Used as upper bound when transmuting
-    #[doc(hidden)]
-    __GUARD,
+    SendHashes = 42,
+    /// Event used to send the transition history of the FSM to the ground
+    /// station
+    DumpFsmHistory = 43,
 }
 
-/// Enum for different types of emergencies
+/// Enum for different types of emergencies. The discriminant of each variant
+/// is how it's encoded, both in an encoded `Event` and in the `Emergency`
+/// datapoint sent to the ground station.
 #[derive(Clone, PartialEq, Eq, Debug, Copy, defmt::Format, PartialOrd, Ord)]
 #[repr(u8)]
 pub enum EmergencyType {
     /// General emergency
     GeneralEmergency = 0,
     /// Emergency triggered by propulsion
-    EmergencyPropulsion,
+    EmergencyPropulsion = 1,
     /// Emergency triggered by levitation
-    EmergencyLevitation,
+    EmergencyLevitation = 2,
     /// Emergency triggered by the powertrain controller
-    EmergencyPTC,
+    EmergencyPTC = 3,
     /// Emergency triggered by the BMS
-    EmergencyBMS,
+    EmergencyBMS = 4,
     /// Emergency triggered by SenseCon
-    EmergencySenseCon,
+    EmergencySenseCon = 5,
     /// Emergency triggered by the Sensor Hub
-    EmergencySensorHub,
+    EmergencySensorHub = 6,
     /// Emergency triggered when we lose connection to the main PCB
-    DisconnectionEmergency,
+    DisconnectionEmergency = 7,
     /// Emergency triggered when the EBS is in the wrong state, measured with
     /// low pressure.
-    EmergencyWrongEbsState,
+    EmergencyWrongEbsState = 8,
     /// Emergency triggered if one of the critical datapoints has been stale for
     /// more than one second
-    StaleCriticalDataEmergency,
+    StaleCriticalDataEmergency = 9,
+    /// Emergency triggered when the frontend stops sending heartbeats while the
+    /// connection to the ground station is still up
+    FrontendHeartbeatLost = 10,
+    /// Emergency triggered when a CAN bus stays bus-off for longer than
+    /// `CAN_BUS_OFF_TIMEOUT`
+    EmergencyCanBusOff = 11,
+    /// Emergency triggered when a datapoint crosses the `brake` threshold of
+    /// its limits for `LIMIT_DEBOUNCE` messages in a row
+    ValueOutOfBounds = 12,
+    /// Emergency triggered when a state whose `timeout_state` is `Fault` lasts
+    /// longer than its `timeout`, like braking that never finishes
+    EmergencyStateTimeout = 13,
+}
+
+/// The priority classes of events on the `EventChannel`, from most to least
+/// urgent. Events of a class are received before those of the classes below
+/// it, and in the order they were sent within a class.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, defmt::Format)]
+pub enum PriorityClass {
+    /// Emergencies, failures and readings that mean the pod has to brake
+    Safety,
+    /// Commands from the ground station and transitions of the FSM
+    Control,
+    /// Acknowledgements from the subsystems
+    Ack,
+    /// Heartbeats and other periodic or informational events. These are
+    /// dropped when the channel is full.
+    Telemetry,
+}
+
+/// Errors that can occur when decoding an `Event` or `EmergencyType`
+#[derive(Clone, Copy, PartialEq, Eq, Debug, defmt::Format)]
+pub enum DecodeError {
+    /// The buffer was encoded with another version of the encoding
+    UnsupportedVersion(u8),
+    /// No `Event` variant has this tag
+    UnknownTag(u8),
+    /// No `EmergencyType` variant has this value
+    UnknownEmergencyType(u8),
+    /// The payload isn't valid for the variant with this tag, e.g. a non-zero
+    /// payload for an event that doesn't carry any data
+    InvalidPayload {
+        /// The tag of the variant
+        tag: u8,
+        /// The payload that was read
+        payload: u8,
+    },
 }
 
 impl Event {
-    const fn guard_event_tag() -> u8 {
-        unsafe { core::mem::transmute::<Event, [u8; 2]>(Event::__GUARD)[0] }
+    /// Version of the binary encoding, bumped whenever the tag of a variant or
+    /// the layout of a payload changes
+    pub const ENCODING_VERSION: u8 = 1;
+
+    /// Size of an encoded event in bytes
+    pub const ENCODED_SIZE: usize = 3;
+
+    /// The tag of this event in the binary encoding, which is its discriminant.
+    pub fn tag(&self) -> u8 {
+        // SAFETY: `Event` is `repr(u8)`, so it starts with its discriminant as a `u8`.
+        unsafe { *(self as *const Self as *const u8) }
+    }
+
+    /// Encodes the event as the encoding version, its tag and its payload, or
+    /// 0 if it doesn't carry any data.
+    ///
+    /// # Returns:
+    /// - The encoded event, readable with `Event::from_bytes`
+    pub fn to_bytes(&self) -> [u8; Self::ENCODED_SIZE] {
+        let payload = match *self {
+            Event::Emergency { emergency_type } => emergency_type.to_byte(),
+            Event::TransitionFail(x)
+            | Event::StateTimeout(x)
+            | Event::FSMTransition(x)
+            | Event::FSMHeartbeat(x) => x,
+            _ => 0,
+        };
+        [Self::ENCODING_VERSION, self.tag(), payload]
     }
 
-    /// Reads from the provided buffer and returns an `Option` containing the
-    /// event or `None` if the element transmutes to a value higher than the
-    /// `Event` enum limit.
+    /// Decodes an event written by `Event::to_bytes`, checking the version, the
+    /// tag and the payload.
     ///
     /// # Parameters:
     /// - `buf`: the buffer to read from
     ///
     /// # Returns:
-    /// - `Option` containing a variant of the `Event` enum
-    pub fn read_from_buf(buf: [u8; 2]) -> Option<Self> {
-        if buf[0] >= Self::guard_event_tag() {
-            return None;
+    /// - The decoded event, or the reason why the buffer isn't a valid event
+    pub fn from_bytes(buf: [u8; Self::ENCODED_SIZE]) -> Result<Self, DecodeError> {
+        let [version, tag, payload] = buf;
+        if version != Self::ENCODING_VERSION {
+            return Err(DecodeError::UnsupportedVersion(version));
+        }
+
+        let event = match tag {
+            0 => Event::Emergency {
+                emergency_type: EmergencyType::from_byte(payload)?,
+            },
+            5 => Event::TransitionFail(payload),
+            6 => Event::StateTimeout(payload),
+            28 => Event::FSMTransition(payload),
+            29 => Event::FSMHeartbeat(payload),
+            // events without data
+            _ => {
+                let event = match tag {
+                    1 => Event::PTCFailure,
+                    2 => Event::Prop2SystemCheckFailure,
+                    3 => Event::Prop1SystemCheckFailure,
+                    4 => Event::LeviSystemCheckFailure,
+                    7 => Event::ConnectToGS,
+                    8 => Event::EbsPressureDeployed,
+                    9 => Event::EbsPressureRetracted,
+                    10 => Event::StartSystemCheck,
+                    11 => Event::EnterIdle,
+                    12 => Event::StartPreCharge,
+                    13 => Event::HVOnAck,
+                    14 => Event::PTCIdleAck,
+                    15 => Event::EnterDemo,
+                    16 => Event::LocalizationLimitReached,
+                    17 => Event::Levitate,
+                    18 => Event::StopLevitating,
+                    19 => Event::Accelerate,
+                    20 => Event::Brake,
+                    21 => Event::Stopped,
+                    22 => Event::Discharge,
+                    23 => Event::ShutDown,
+                    24 => Event::Charge,
+                    25 => Event::StopCharge,
+                    26 => Event::ResetFSM,
+                    27 => Event::FaultFixed,
+                    30 => Event::LeviOnAck,
+                    31 => Event::LeviOffAck,
+                    32 => Event::PropulsionAck1,
+                    33 => Event::PropulsionAck2,
+                    34 => Event::PowertrainAck,
+                    35 => Event::ClearFaultAckLevi,
+                    36 => Event::LeviSystemCheckSuccess,
+                    37 => Event::Prop1SystemCheckSuccess,
+                    38 => Event::Prop2SystemCheckSuccess,
+                    39 => Event::OverrideRearmSdc,
+                    40 => Event::Heartbeat,
+                    41 => Event::NoEvent,
+                    42 => Event::SendHashes,
+                    43 => Event::DumpFsmHistory,
+                    _ => return Err(DecodeError::UnknownTag(tag)),
+                };
+                if payload != 0 {
+                    return Err(DecodeError::InvalidPayload { tag, payload });
+                }
+                event
+            }
+        };
+
+        Ok(event)
+    }
+
+    /// The priority class of the event, which decides the order in which
+    /// events are received from the `EventChannel`.
+    pub fn priority(&self) -> PriorityClass {
+        match self {
+            Event::Emergency { .. }
+            | Event::PTCFailure
+            | Event::Prop2SystemCheckFailure
+            | Event::Prop1SystemCheckFailure
+            | Event::LeviSystemCheckFailure
+            | Event::EbsPressureDeployed
+            | Event::EbsPressureRetracted
+            | Event::LocalizationLimitReached => PriorityClass::Safety,
+            Event::TransitionFail(_)
+            | Event::StateTimeout(_)
+            | Event::ConnectToGS
+            | Event::StartSystemCheck
+            | Event::EnterIdle
+            | Event::StartPreCharge
+            | Event::EnterDemo
+            | Event::Levitate
+            | Event::StopLevitating
+            | Event::Accelerate
+            | Event::Brake
+            | Event::Discharge
+            | Event::ShutDown
+            | Event::Charge
+            | Event::StopCharge
+            | Event::ResetFSM
+            | Event::FaultFixed
+            | Event::FSMTransition(_)
+            | Event::OverrideRearmSdc => PriorityClass::Control,
+            Event::HVOnAck
+            | Event::PTCIdleAck
+            | Event::Stopped
+            | Event::LeviOnAck
+            | Event::LeviOffAck
+            | Event::PropulsionAck1
+            | Event::PropulsionAck2
+            | Event::PowertrainAck
+            | Event::ClearFaultAckLevi
+            | Event::LeviSystemCheckSuccess
+            | Event::Prop1SystemCheckSuccess
+            | Event::Prop2SystemCheckSuccess => PriorityClass::Ack,
+            Event::FSMHeartbeat(_)
+            | Event::Heartbeat
+            | Event::NoEvent
+            | Event::SendHashes
+            | Event::DumpFsmHistory => PriorityClass::Telemetry,
         }
+    }
+}
 
-        let event = unsafe { core::mem::transmute_copy::<[u8; 2], Event>(&buf) };
+impl EmergencyType {
+    /// Encodes the emergency type as its discriminant.
+    pub fn to_byte(self) -> u8 {
+        self as u8
+    }
 
-        Some(event)
+    /// Decodes an emergency type written by `EmergencyType::to_byte`.
+    pub fn from_byte(byte: u8) -> Result<Self, DecodeError> {
+        match byte {
+            0 => Ok(EmergencyType::GeneralEmergency),
+            1 => Ok(EmergencyType::EmergencyPropulsion),
+            2 => Ok(EmergencyType::EmergencyLevitation),
+            3 => Ok(EmergencyType::EmergencyPTC),
+            4 => Ok(EmergencyType::EmergencyBMS),
+            5 => Ok(EmergencyType::EmergencySenseCon),
+            6 => Ok(EmergencyType::EmergencySensorHub),
+            7 => Ok(EmergencyType::DisconnectionEmergency),
+            8 => Ok(EmergencyType::EmergencyWrongEbsState),
+            9 => Ok(EmergencyType::StaleCriticalDataEmergency),
+            10 => Ok(EmergencyType::FrontendHeartbeatLost),
+            11 => Ok(EmergencyType::EmergencyCanBusOff),
+            12 => Ok(EmergencyType::ValueOutOfBounds),
+            13 => Ok(EmergencyType::EmergencyStateTimeout),
+            _ => Err(DecodeError::UnknownEmergencyType(byte)),
+        }
     }
 }
+
+#[cfg(test)]
+#[path = "tests/data.rs"]
+mod tests;
@@ -1,46 +1,229 @@
 //! This module contains types used in the crate
 
+use core::cell::RefCell;
 use core::fmt::Debug;
 use core::fmt::Formatter;
+use core::future::poll_fn;
+use core::task::Context;
+use core::task::Poll;
 
+use embassy_sync::blocking_mutex::Mutex;
 use embassy_sync::blocking_mutex::raw::NoopRawMutex;
-use embassy_sync::priority_channel::PriorityChannel;
-use embassy_sync::priority_channel::Receiver;
-use embassy_sync::priority_channel::Sender;
-use heapless::binary_heap::Min;
+use embassy_sync::waitqueue::MultiWakerRegistration;
+use embassy_sync::waitqueue::WakerRegistration;
+use heapless::Vec;
 
 use crate::Event;
+use crate::utils::data::PriorityClass;
 
 /// Maximum number of events on the channel
 const MAX_EVENTS: usize = 32;
 
-/// Type alias for the `PriorityChannel` used for the normal event channel.
-pub type EventChannel =
-    PriorityChannel<NoopRawMutex, Event, embassy_sync::priority_channel::Min, MAX_EVENTS>;
+/// Maximum number of senders that can wait for space on the channel at the
+/// same time. If more are waiting, all of them are woken up to try again.
+const MAX_WAITING_SENDERS: usize = 8;
 
-/// Type alias for the event channel sender.
+/// An event on the channel, together with the order in which it arrived
+#[derive(Clone, Copy)]
+struct QueuedEvent {
+    /// The priority class of `event`
+    class: PriorityClass,
+    /// Number of events sent on the channel before this one
+    seq: u32,
+    /// The event itself
+    event: Event,
+}
+
+impl QueuedEvent {
+    /// Events are received by class, and in the order they arrived within a
+    /// class. Events are ordered by `seq` relative to each other, so that the
+    /// order stays correct when the counter wraps.
+    fn comes_before(&self, other: &Self) -> bool {
+        self.class < other.class
+            || self.class == other.class && (self.seq.wrapping_sub(other.seq) as i32) < 0
+    }
+}
+
+/// The state of the channel, behind a mutex
+struct ChannelState {
+    /// The events on the channel, in no particular order
+    queue: Vec<QueuedEvent, MAX_EVENTS>,
+    /// `seq` of the next event sent
+    next_seq: u32,
+    /// Number of events dropped because the channel was full, see
+    /// `EventChannel::dropped`
+    dropped: u32,
+    /// Woken up when an event is sent
+    receiver_waker: WakerRegistration,
+    /// Woken up when an event is received
+    sender_wakers: MultiWakerRegistration<MAX_WAITING_SENDERS>,
+}
+
+impl ChannelState {
+    /// Tries to put `event` on the channel. When the channel is full, events
+    /// in the `Telemetry` class are dropped to make space: either a queued one,
+    /// or `event` itself. A `Safety` event can replace any event of a lower
+    /// class. Returns the event back if it has to wait for space.
+    fn push(&mut self, event: Event) -> Result<(), Event> {
+        let queued = QueuedEvent {
+            class: event.priority(),
+            seq: self.next_seq,
+            event,
+        };
+
+        if self.queue.is_full() {
+            let (last, least) = self
+                .queue
+                .iter()
+                .enumerate()
+                .reduce(|a, b| if a.1.comes_before(b.1) { b } else { a })
+                .expect("a full queue isn't empty");
+
+            if queued.class < least.class
+                && (least.class == PriorityClass::Telemetry
+                    || queued.class == PriorityClass::Safety)
+            {
+                defmt::warn!("Event channel full, dropping {}", least.event);
+                self.queue.swap_remove(last);
+                self.dropped = self.dropped.wrapping_add(1);
+            } else if queued.class == PriorityClass::Telemetry {
+                self.dropped = self.dropped.wrapping_add(1);
+                return Ok(());
+            } else {
+                return Err(event);
+            }
+        }
+
+        // there is space now, so this can't fail
+        let _ = self.queue.push(queued);
+        self.next_seq = self.next_seq.wrapping_add(1);
+        self.receiver_waker.wake();
+        Ok(())
+    }
+
+    /// Takes the event of the highest class that arrived first.
+    fn pop(&mut self) -> Option<Event> {
+        let (first, _) = self
+            .queue
+            .iter()
+            .enumerate()
+            .reduce(|a, b| if b.1.comes_before(a.1) { b } else { a })?;
+        let queued = self.queue.swap_remove(first);
+        self.sender_wakers.wake();
+        Some(queued.event)
+    }
+}
+
+/// Channel used for sending events to and from the FSM.
+///
+/// Events are received by their `PriorityClass`, and in the order they were
+/// sent within a class. Sending never waits because of `Telemetry` events:
+/// those are dropped when the channel is full.
+pub struct EventChannel {
+    /// The events on the channel and the tasks waiting for it
+    state: Mutex<NoopRawMutex, RefCell<ChannelState>>,
+}
+
+impl EventChannel {
+    /// Creates an empty channel.
+    pub const fn new() -> Self {
+        Self {
+            state: Mutex::new(RefCell::new(ChannelState {
+                queue: Vec::new(),
+                next_seq: 0,
+                dropped: 0,
+                receiver_waker: WakerRegistration::new(),
+                sender_wakers: MultiWakerRegistration::new(),
+            })),
+        }
+    }
+
+    /// Returns an object used for send access to the channel.
+    pub fn sender(&'static self) -> EventSender {
+        EventSender(self)
+    }
+
+    /// Returns an object used for receive access to the channel.
+    pub fn receiver(&'static self) -> EventReceiver {
+        EventReceiver(self)
+    }
+
+    /// Number of events dropped so far because the channel was full: the
+    /// `Telemetry` events, and the `Control` and `Ack` events a `Safety` event
+    /// replaced.
+    pub fn dropped(&self) -> u32 {
+        self.state.lock(|s| s.borrow().dropped)
+    }
+
+    /// Sends `event`, or registers the task to be woken up once there is
+    /// space for it.
+    fn poll_send(&self, event: Event, cx: &mut Context<'_>) -> Poll<()> {
+        self.state.lock(|s| {
+            let mut s = s.borrow_mut();
+            match s.push(event) {
+                Ok(()) => Poll::Ready(()),
+                Err(_) => {
+                    s.sender_wakers.register(cx.waker());
+                    Poll::Pending
+                }
+            }
+        })
+    }
+
+    /// Receives an event, or registers the task to be woken up once one is
+    /// sent.
+    fn poll_receive(&self, cx: &mut Context<'_>) -> Poll<Event> {
+        self.state.lock(|s| {
+            let mut s = s.borrow_mut();
+            match s.pop() {
+                Some(event) => Poll::Ready(event),
+                None => {
+                    s.receiver_waker.register(cx.waker());
+                    Poll::Pending
+                }
+            }
+        })
+    }
+}
+
+impl Default for EventChannel {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Debug for EventChannel {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(f, "EventChannel {{ ... }}")
+    }
+}
+
+/// Object used for send access to an `EventChannel`.
 #[derive(Copy, Clone)]
-pub struct EventSender(
-    Sender<'static, NoopRawMutex, Event, embassy_sync::priority_channel::Min, MAX_EVENTS>,
-);
+pub struct EventSender(&'static EventChannel);
 
-/// Type alias for the event channel receiver.
+/// Object used for receive access to an `EventChannel`.
 #[derive(Copy, Clone)]
-pub struct EventReceiver(
-    Receiver<'static, NoopRawMutex, Event, embassy_sync::priority_channel::Min, MAX_EVENTS>,
-);
+pub struct EventReceiver(&'static EventChannel);
 
 impl EventReceiver {
-    /// Wrapper method for the `receive` method of the `EventReceiver`
+    /// Waits for the next event on the channel.
     pub async fn receive(&self) -> Event {
-        self.0.receive().await
+        poll_fn(|cx| self.0.poll_receive(cx)).await
+    }
+
+    /// Takes the next event from the channel. Returns `None` if the channel is
+    /// empty.
+    pub fn try_receive(&self) -> Option<Event> {
+        self.0.state.lock(|s| s.borrow_mut().pop())
     }
 }
 
 impl EventSender {
-    /// Wrapper method for the `send` method of the `EventSender`
+    /// Sends an event, waiting for space on the channel if it's full. Doesn't
+    /// wait for `Telemetry` events, they are dropped instead.
     pub async fn send(&self, event: Event) {
-        self.0.send(event).await
+        poll_fn(|cx| self.0.poll_send(event, cx)).await
     }
 }
 
@@ -56,18 +239,6 @@ impl Debug for EventReceiver {
     }
 }
 
-impl From<Receiver<'static, NoopRawMutex, Event, embassy_sync::priority_channel::Min, MAX_EVENTS>>
-    for EventReceiver
-{
-    fn from(receiver: Receiver<'static, NoopRawMutex, Event, Min, MAX_EVENTS>) -> Self {
-        EventReceiver(receiver)
-    }
-}
-
-impl From<Sender<'static, NoopRawMutex, Event, embassy_sync::priority_channel::Min, MAX_EVENTS>>
-    for EventSender
-{
-    fn from(sender: Sender<'static, NoopRawMutex, Event, Min, MAX_EVENTS>) -> Self {
-        EventSender(sender)
-    }
-}
+#[cfg(test)]
+#[path = "tests/event_types.rs"]
+mod tests;
@@ -0,0 +1,90 @@
+//! This module contains the transition history kept by the FSM, which the
+//! ground station can request with the `DumpFsmHistory` command.
+
+use core::cell::RefCell;
+
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+use embassy_time::Instant;
+use heapless::HistoryBuffer;
+use heapless::Vec;
+
+use crate::Event;
+use crate::States;
+use crate::config::FSM_HISTORY_SIZE;
+
+/// A single transition of the FSM
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct TransitionRecord {
+    /// The state the FSM was in
+    pub from: States,
+    /// The state the FSM transitioned to
+    pub to: States,
+    /// The event that caused the transition. For emergencies this also holds
+    /// the type of emergency.
+    pub event: Event,
+    /// When the transition happened
+    pub timestamp: Instant,
+}
+
+impl TransitionRecord {
+    /// Packs the record into the value of a `FsmHistoryEntry` datapoint:
+    /// - byte 0: index of `from`
+    /// - byte 1: index of `to`
+    /// - byte 2: tag of `event`
+    /// - byte 3: payload of `event`, as encoded by `Event::to_bytes`
+    /// - bytes 4-7: `timestamp` in milliseconds since boot
+    pub fn to_value(&self) -> u64 {
+        let [_version, tag, payload] = self.event.to_bytes();
+        self.from.to_index() as u64
+            | (self.to.to_index() as u64) << 8
+            | (tag as u64) << 16
+            | (payload as u64) << 24
+            | (self.timestamp.as_millis() as u32 as u64) << 32
+    }
+}
+
+/// Ring buffer with the last `FSM_HISTORY_SIZE` transitions of the FSM. Shared
+/// between the FSM, which records the transitions, and the task that sends them
+/// to the ground station.
+pub struct FsmHistory(
+    Mutex<NoopRawMutex, RefCell<HistoryBuffer<TransitionRecord, FSM_HISTORY_SIZE>>>,
+);
+
+impl FsmHistory {
+    /// Creates an empty history.
+    pub const fn new() -> Self {
+        Self(Mutex::new(RefCell::new(HistoryBuffer::new())))
+    }
+
+    /// Records a transition, overwriting the oldest one if the history is
+    /// full.
+    pub fn push(&self, record: TransitionRecord) {
+        self.0.lock(|h| h.borrow_mut().write(record));
+    }
+
+    /// The state the FSM is in according to the last recorded transition, or
+    /// `Boot` if it didn't transition yet.
+    pub fn current_state(&self) -> States {
+        self.0
+            .lock(|h| h.borrow().recent().map_or(States::Boot, |r| r.to))
+    }
+
+    /// Copies the recorded transitions, oldest first.
+    pub fn snapshot(&self) -> Vec<TransitionRecord, FSM_HISTORY_SIZE> {
+        self.0
+            .lock(|h| h.borrow().oldest_ordered().copied().collect())
+    }
+}
+
+impl Default for FsmHistory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl core::fmt::Debug for FsmHistory {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "FsmHistory {{ ... }}")
+    }
+}
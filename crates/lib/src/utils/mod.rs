@@ -2,3 +2,4 @@
 pub mod data;
 pub mod datapoint;
 pub mod event_types;
+pub mod history;
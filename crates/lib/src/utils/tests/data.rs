@@ -0,0 +1,98 @@
+//! Tests for the binary encoding of `Event` and `EmergencyType`.
+
+use crate::DecodeError;
+use crate::EmergencyType;
+use crate::Event;
+
+/// One instance of every event.
+const ALL_EVENTS: [Event; 44] = [
+    Event::Emergency {
+        emergency_type: EmergencyType::GeneralEmergency,
+    },
+    Event::PTCFailure,
+    Event::Prop2SystemCheckFailure,
+    Event::Prop1SystemCheckFailure,
+    Event::LeviSystemCheckFailure,
+    Event::TransitionFail(0),
+    Event::StateTimeout(0),
+    Event::ConnectToGS,
+    Event::EbsPressureDeployed,
+    Event::EbsPressureRetracted,
+    Event::StartSystemCheck,
+    Event::EnterIdle,
+    Event::StartPreCharge,
+    Event::HVOnAck,
+    Event::PTCIdleAck,
+    Event::EnterDemo,
+    Event::LocalizationLimitReached,
+    Event::Levitate,
+    Event::StopLevitating,
+    Event::Accelerate,
+    Event::Brake,
+    Event::Stopped,
+    Event::Discharge,
+    Event::ShutDown,
+    Event::Charge,
+    Event::StopCharge,
+    Event::ResetFSM,
+    Event::FaultFixed,
+    Event::FSMTransition(0),
+    Event::FSMHeartbeat(0),
+    Event::LeviOnAck,
+    Event::LeviOffAck,
+    Event::PropulsionAck1,
+    Event::PropulsionAck2,
+    Event::PowertrainAck,
+    Event::ClearFaultAckLevi,
+    Event::LeviSystemCheckSuccess,
+    Event::Prop1SystemCheckSuccess,
+    Event::Prop2SystemCheckSuccess,
+    Event::OverrideRearmSdc,
+    Event::Heartbeat,
+    Event::NoEvent,
+    Event::SendHashes,
+    Event::DumpFsmHistory,
+];
+
+/// Every event decodes to itself after encoding it.
+#[test]
+fn events_survive_encoding() {
+    for event in ALL_EVENTS {
+        assert_eq!(Event::from_bytes(event.to_bytes()), Ok(event));
+    }
+    for byte in 0..=EmergencyType::EmergencyStateTimeout.to_byte() {
+        let emergency = Event::Emergency {
+            emergency_type: EmergencyType::from_byte(byte).unwrap(),
+        };
+        assert_eq!(Event::from_bytes(emergency.to_bytes()), Ok(emergency));
+    }
+}
+
+/// Buffers that don't hold a valid event are rejected.
+#[test]
+fn invalid_encodings_are_rejected() {
+    let version = Event::ENCODING_VERSION;
+    assert_eq!(
+        Event::from_bytes([version + 1, Event::PTCFailure.tag(), 0]),
+        Err(DecodeError::UnsupportedVersion(version + 1))
+    );
+    assert_eq!(
+        Event::from_bytes([version, 200, 0]),
+        Err(DecodeError::UnknownTag(200))
+    );
+    assert_eq!(
+        Event::from_bytes([version, 200, 1]),
+        Err(DecodeError::UnknownTag(200))
+    );
+    assert_eq!(
+        Event::from_bytes([version, 0, 200]),
+        Err(DecodeError::UnknownEmergencyType(200))
+    );
+    assert_eq!(
+        Event::from_bytes([version, Event::PTCFailure.tag(), 1]),
+        Err(DecodeError::InvalidPayload {
+            tag: Event::PTCFailure.tag(),
+            payload: 1
+        })
+    );
+}
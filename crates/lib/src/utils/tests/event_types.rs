@@ -0,0 +1,109 @@
+//! Tests for the order in which the `EventChannel` hands out events, and what
+//! it drops when it is full.
+
+use std::vec::Vec;
+
+use embassy_futures::block_on;
+
+use super::MAX_EVENTS;
+use crate::EmergencyType;
+use crate::Event;
+use crate::EventChannel;
+use crate::EventReceiver;
+
+/// Empties the channel, returning everything that was on it.
+fn drain(receiver: &EventReceiver) -> Vec<Event> {
+    core::iter::from_fn(|| receiver.try_receive()).collect()
+}
+
+/// Events are received by priority class, and in the order they were sent
+/// within a class.
+#[test]
+fn events_are_received_by_class_then_in_order() {
+    let channel: &'static EventChannel = Box::leak(Box::new(EventChannel::new()));
+    let emergency = Event::Emergency {
+        emergency_type: EmergencyType::GeneralEmergency,
+    };
+    for event in [
+        Event::Heartbeat,
+        Event::FSMTransition(2),
+        Event::LeviOnAck,
+        Event::FSMTransition(1),
+        emergency,
+    ] {
+        block_on(channel.sender().send(event));
+    }
+    assert_eq!(
+        drain(&channel.receiver()),
+        [
+            emergency,
+            Event::FSMTransition(2),
+            Event::FSMTransition(1),
+            Event::LeviOnAck,
+            Event::Heartbeat
+        ]
+    );
+}
+
+/// A full channel drops telemetry instead of making safety events wait.
+#[test]
+fn full_channel_drops_telemetry() {
+    let channel: &'static EventChannel = Box::leak(Box::new(EventChannel::new()));
+    let (sender, receiver) = (channel.sender(), channel.receiver());
+    let emergency = Event::Emergency {
+        emergency_type: EmergencyType::GeneralEmergency,
+    };
+
+    // fill the channel with heartbeats, the last ones don't fit
+    for state in 0..40 {
+        block_on(sender.send(Event::FSMHeartbeat(state)));
+    }
+    assert_eq!(channel.dropped(), 8);
+
+    // an emergency or a command replaces the newest heartbeat
+    block_on(sender.send(emergency));
+    block_on(sender.send(Event::StartPreCharge));
+    assert_eq!(channel.dropped(), 10);
+    let received = drain(&receiver);
+    assert_eq!(
+        received[..3],
+        [emergency, Event::StartPreCharge, Event::FSMHeartbeat(0)]
+    );
+    assert_eq!(received.last(), Some(&Event::FSMHeartbeat(29)));
+
+    // heartbeats don't wait for commands to be received
+    for _ in 0..32 {
+        block_on(sender.send(Event::StartPreCharge));
+    }
+    block_on(sender.send(Event::Heartbeat));
+    assert_eq!(channel.dropped(), 11);
+
+    // but emergencies replace commands
+    block_on(sender.send(emergency));
+    assert_eq!(receiver.try_receive(), Some(emergency));
+}
+
+/// A safety event replaces the newest event of the lowest class on a full
+/// channel, even if that is a command, and the replaced event counts as
+/// dropped.
+#[test]
+fn safety_event_evicts_control_from_full_channel() {
+    let channel: &'static EventChannel = Box::leak(Box::new(EventChannel::new()));
+    let (sender, receiver) = (channel.sender(), channel.receiver());
+    let emergency = Event::Emergency {
+        emergency_type: EmergencyType::GeneralEmergency,
+    };
+
+    for _ in 0..MAX_EVENTS - 1 {
+        block_on(sender.send(Event::StartPreCharge));
+    }
+    block_on(sender.send(Event::EnterDemo));
+    assert_eq!(channel.dropped(), 0);
+
+    block_on(sender.send(emergency));
+    assert_eq!(channel.dropped(), 1);
+    let received = drain(&receiver);
+    assert_eq!(received.len(), MAX_EVENTS);
+    assert_eq!(received[0], emergency);
+    assert!(!received.contains(&Event::EnterDemo));
+}
@@ -0,0 +1,193 @@
+//! Tracking of the acknowledgements subsystems send for commands.
+//!
+//! Commands with an `ack` in the dataflow are answered by the subsystems with
+//! a message on the same bus. When the TX task of a bus sends such a command,
+//! it tells the [`AckTracker`] of the bus which acknowledgements to expect,
+//! and the RX task tells it about every message it receives. The outcome for
+//! every expected acknowledgement is sent to the ground station by
+//! [`crate::comms_tasks::report_command_acks`].
+
+use core::cell::RefCell;
+
+use defmt::*;
+use embassy_futures::select::select;
+use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::signal::Signal;
+use embassy_time::Instant;
+use embassy_time::Timer;
+use heapless::Deque;
+use heapless::Vec;
+use lib::can::ExpectedAcks;
+
+/// Maximum number of acknowledgements that are waited for at the same time
+const MAX_OUTSTANDING_ACKS: usize = 16;
+
+/// An acknowledgement that is waited for
+struct Outstanding {
+    /// ID of the command that was sent
+    command: u16,
+    /// CAN ID of the acknowledgement
+    ack_id: u32,
+    /// When the acknowledgement should have arrived
+    deadline: Instant,
+}
+
+/// The outcome of waiting for an acknowledgement
+#[derive(Clone, Copy, PartialEq, Eq, Debug, defmt::Format)]
+pub struct AckReport {
+    /// ID of the command that was sent
+    pub command: u16,
+    /// CAN ID of the acknowledgement
+    pub ack_id: u32,
+    /// Whether the acknowledgement arrived in time
+    pub acked: bool,
+}
+
+impl AckReport {
+    /// Packs the report into the value of a `CommandAcked` or
+    /// `CommandTimedOut` datapoint: the command ID in the lower 16 bits and
+    /// the CAN ID of the acknowledgement above that.
+    pub fn to_value(&self) -> u64 {
+        self.command as u64 | (self.ack_id as u64) << 16
+    }
+}
+
+/// State of the tracker, behind the mutex of [`AckTracker`]
+struct Acks {
+    /// The acknowledgements that are waited for, in the order the commands
+    /// were first sent
+    outstanding: Vec<Outstanding, MAX_OUTSTANDING_ACKS>,
+    /// The acknowledgements that arrived and weren't reported yet, oldest
+    /// first
+    acked: Deque<AckReport, MAX_OUTSTANDING_ACKS>,
+}
+
+/// Keeps track of the acknowledgements expected on a bus, shared between the
+/// tasks using the bus and the task reporting them to the ground station.
+pub struct AckTracker {
+    /// The expected and received acknowledgements
+    acks: Mutex<NoopRawMutex, RefCell<Acks>>,
+    /// Signalled when an acknowledgement is expected or arrives
+    changed: Signal<NoopRawMutex, ()>,
+}
+
+impl AckTracker {
+    /// Creates a tracker that doesn't wait for anything.
+    pub const fn new() -> Self {
+        Self {
+            acks: Mutex::new(RefCell::new(Acks {
+                outstanding: Vec::new(),
+                acked: Deque::new(),
+            })),
+            changed: Signal::new(),
+        }
+    }
+
+    /// Starts waiting for the acknowledgements of a command sent at `now`.
+    /// If the command was sent before and is still waited for, the deadline
+    /// starts over.
+    pub fn sent(&self, expected: ExpectedAcks, now: Instant) {
+        let deadline = now + expected.timeout;
+        self.acks.lock(|a| {
+            let mut a = a.borrow_mut();
+            for &ack_id in expected.ids {
+                if let Some(o) = a
+                    .outstanding
+                    .iter_mut()
+                    .find(|o| o.command == expected.command && o.ack_id == ack_id)
+                {
+                    o.deadline = deadline;
+                } else if a
+                    .outstanding
+                    .push(Outstanding {
+                        command: expected.command,
+                        ack_id,
+                        deadline,
+                    })
+                    .is_err()
+                {
+                    warn!(
+                        "Too many outstanding acknowledgements, not waiting for {} of command {}",
+                        ack_id, expected.command
+                    );
+                }
+            }
+        });
+        self.changed.signal(());
+    }
+
+    /// Resolves the oldest outstanding acknowledgement with the given CAN ID.
+    /// Called for every received message.
+    ///
+    /// An acknowledgement doesn't say which command it answers. Subsystems
+    /// answer commands in the order they receive them, so when several
+    /// commands wait for the same subsystem, every frame resolves only the
+    /// one that was sent first.
+    pub fn received(&self, id: u32) {
+        let resolved = self.acks.lock(|a| {
+            let mut a = a.borrow_mut();
+            let Some(i) = a.outstanding.iter().position(|o| o.ack_id == id) else {
+                return false;
+            };
+            let o = a.outstanding.remove(i);
+            let report = AckReport {
+                command: o.command,
+                ack_id: o.ack_id,
+                acked: true,
+            };
+            if a.acked.push_back(report).is_err() {
+                warn!("Too many acknowledgements to report, dropping {}", report);
+            }
+            true
+        });
+        if resolved {
+            self.changed.signal(());
+        }
+    }
+
+    /// Waits until an acknowledgement arrives or times out.
+    pub async fn next_report(&self) -> AckReport {
+        loop {
+            let now = Instant::now();
+            let (report, next_deadline) = self.acks.lock(|a| {
+                let mut a = a.borrow_mut();
+                if let Some(report) = a.acked.pop_front() {
+                    return (Some(report), None);
+                }
+                if let Some(i) = a.outstanding.iter().position(|o| o.deadline <= now) {
+                    let o = a.outstanding.remove(i);
+                    let report = AckReport {
+                        command: o.command,
+                        ack_id: o.ack_id,
+                        acked: false,
+                    };
+                    return (Some(report), None);
+                }
+                (None, a.outstanding.iter().map(|o| o.deadline).min())
+            });
+
+            if let Some(report) = report {
+                return report;
+            }
+            match next_deadline {
+                Some(deadline) => {
+                    select(self.changed.wait(), Timer::at(deadline)).await;
+                }
+                None => self.changed.wait().await,
+            }
+        }
+    }
+}
+
+impl Default for AckTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl core::fmt::Debug for AckTracker {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "AckTracker {{ ... }}")
+    }
+}
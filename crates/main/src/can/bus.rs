@@ -0,0 +1,317 @@
+//! The implementation shared by the CAN buses.
+//!
+//! [`super::can1`] and [`super::can2`] only differ in the type of their frames
+//! and envelopes, the methods used to read and write them, and the
+//! per-bus functions generated from the dataflow. Embassy tasks can't be
+//! generic, so `can_bus!` expands to the channels, the RX and TX tasks and the
+//! `CanInterface` of a bus in the module it is invoked from.
+
+/// Defines the channels, `can_rx_task`, `can_tx_task` and `CanInterface` of a
+/// CAN bus in the current module.
+///
+/// - `name`: the name of the bus in log messages, like `"CAN2"`
+/// - `envelope`: the envelope type of the bus, from `lib::can`
+/// - `frame`: the frame type inside the envelope
+/// - `read`, `write`: the methods of `CanRx` and `CanTx` for this frame type
+/// - `expected_acks`, `retransmit_policy`: the generated functions for the bus
+/// - `rx_subscribers`, `rx_publishers`: the size of the RX channel
+macro_rules! can_bus {
+    (
+        name: $name:literal,
+        envelope: $envelope:ty,
+        frame: $frame:ty,
+        read: $read:ident,
+        write: $write:ident,
+        expected_acks: $expected_acks:path,
+        retransmit_policy: $retransmit_policy:path,
+        rx_subscribers: $rx_subscribers:literal,
+        rx_publishers: $rx_publishers:literal $(,)?
+    ) => {
+        use defmt::*;
+        use embassy_executor::Spawner;
+        use embassy_futures::select::select;
+        use embassy_futures::select::Either;
+        use embassy_stm32::can::Can;
+        use embassy_stm32::can::CanRx;
+        use embassy_stm32::can::CanTx;
+        use embassy_stm32::can::Properties;
+        use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+        use embassy_sync::channel::Channel;
+        use embassy_sync::channel::Sender;
+        use embassy_sync::priority_channel::PriorityChannel;
+        use embassy_sync::priority_channel::{self};
+        use embassy_sync::pubsub::PubSubChannel;
+        use embassy_sync::pubsub::Publisher;
+        use embassy_sync::pubsub::Subscriber;
+        use embassy_time::Instant;
+        use embassy_time::Timer;
+        use lib::can::raw_id;
+        use lib::can::CanEnvelope as _;
+        use static_cell::StaticCell;
+
+        use crate::can::acks::AckTracker;
+        use crate::can::health::BusState;
+        use crate::can::health::CanHealth;
+        use crate::can::retransmit::RetryQueue;
+
+        /// The envelope of the frames on this bus
+        type CanEnvelope = $envelope;
+
+        /// Number of received messages kept for subscribers that are behind
+        const CAN_RX_CAPACITY: usize = 4;
+        /// Maximum number of tasks listening to the bus
+        const CAN_RX_SUBSCRIBERS: usize = $rx_subscribers;
+        /// Maximum number of publishers on the RX channel
+        const CAN_RX_PUBLISHERS: usize = $rx_publishers;
+
+        /// Channel the received messages are published on
+        type CanRxChannel = PubSubChannel<
+            NoopRawMutex,
+            CanEnvelope,
+            CAN_RX_CAPACITY,
+            CAN_RX_SUBSCRIBERS,
+            CAN_RX_PUBLISHERS,
+        >;
+        /// Subscriber object for receiving messages over the CAN bus
+        pub type CanRxSubscriber<'a> = Subscriber<
+            'a,
+            NoopRawMutex,
+            CanEnvelope,
+            CAN_RX_CAPACITY,
+            CAN_RX_SUBSCRIBERS,
+            CAN_RX_PUBLISHERS,
+        >;
+        /// Publisher object used by [`can_rx_task`]
+        type CanRxPublisher<'a> = Publisher<
+            'a,
+            NoopRawMutex,
+            CanEnvelope,
+            CAN_RX_CAPACITY,
+            CAN_RX_SUBSCRIBERS,
+            CAN_RX_PUBLISHERS,
+        >;
+
+        /// Task that listens for CAN messages sent over the CAN bus and
+        /// forwards them to the RX channel.
+        ///
+        /// Tasks interested in receiving CAN messages should subscribe to the
+        /// RX channel, which they can do through
+        /// [`CanInterface::new_subscriber`].
+        #[embassy_executor::task]
+        async fn can_rx_task(
+            mut can: CanRx<'static>,
+            publisher: CanRxPublisher<'static>,
+            health: &'static CanHealth,
+            acks: &'static AckTracker,
+        ) -> ! {
+            let mut error_counter: usize = 0;
+            loop {
+                trace!("reading stuff from {}", $name);
+                match can.$read().await {
+                    Ok(envelope) => {
+                        trace!("[{}] Envelope: {:?}", $name, &envelope);
+                        let envelope = CanEnvelope { envelope };
+                        health.record_rx(envelope.raw_id());
+                        acks.received(envelope.raw_id());
+                        publisher.publish(envelope).await;
+                    }
+                    Err(e) => {
+                        health.record_rx_error();
+                        if error_counter < 10 || error_counter % 2500 == 0 {
+                            error!(
+                                "[{}] Error reading from CAN bus (#{}): {:?}",
+                                $name, error_counter, e
+                            );
+                        }
+                        Timer::after_millis(500).await;
+                        error_counter = error_counter.wrapping_add(1);
+                    }
+                }
+            }
+        }
+
+        /// Number of messages that can wait to be sent
+        const CAN_TX_CAPACITY: usize = 32;
+        /// Messages with the lowest ID are sent first, like on the bus itself
+        type CanTxChannelKind = heapless::binary_heap::Min;
+        /// Channel the messages to send are put on
+        type CanTxChannel =
+            PriorityChannel<NoopRawMutex, CanEnvelope, CanTxChannelKind, CAN_TX_CAPACITY>;
+        /// Sender object for the priority channel used for transmitting
+        /// messages over the CAN bus.
+        pub type CanTxSender<'a> = priority_channel::Sender<
+            'a,
+            NoopRawMutex,
+            CanEnvelope,
+            CanTxChannelKind,
+            CAN_TX_CAPACITY,
+        >;
+        /// Receiver object used by [`can_tx_task`]
+        type CanTxReceiver<'a> = priority_channel::Receiver<
+            'a,
+            NoopRawMutex,
+            CanEnvelope,
+            CanTxChannelKind,
+            CAN_TX_CAPACITY,
+        >;
+
+        /// Maximum number of undelivered frames waiting to be reported
+        const UNDELIVERED_CAPACITY: usize = 8;
+        /// Channel with the IDs of frames that could not be delivered, for
+        /// commands whose retransmit policy has `must_ack`
+        type UndeliveredChannel = Channel<NoopRawMutex, u32, UNDELIVERED_CAPACITY>;
+        /// Sender object used by [`can_tx_task`] to report undelivered frames
+        type UndeliveredSender<'a> = Sender<'a, NoopRawMutex, u32, UNDELIVERED_CAPACITY>;
+
+        /// Task that sends CAN envelopes received from the TX channel over the
+        /// CAN bus.
+        ///
+        /// Frames dropped from the mailboxes are sent again according to the
+        /// retransmit policy of their command, see [`crate::can::retransmit`].
+        #[embassy_executor::task]
+        async fn can_tx_task(
+            mut can: CanTx<'static>,
+            rx: CanTxReceiver<'static>,
+            health: &'static CanHealth,
+            undelivered: UndeliveredSender<'static>,
+            acks: &'static AckTracker,
+        ) -> ! {
+            let mut retries = RetryQueue::<$frame>::new();
+            loop {
+                // retries that are due go first, so that a busy TX channel
+                // can't hold them back
+                let frame = if let Some(retry) = retries.pop_due(Instant::now()) {
+                    debug!(
+                        "[{}] Retry {} of frame with ID {}",
+                        $name, retry.attempt, retry.id
+                    );
+                    retry.frame
+                } else {
+                    let next = match retries.next_due() {
+                        Some(due) => select(rx.receive(), Timer::at(due)).await,
+                        None => Either::First(rx.receive().await),
+                    };
+                    match next {
+                        Either::First(envelope) => {
+                            trace!("sending stuff to {}: {:?}", $name, &envelope);
+                            retries.forget(envelope.raw_id());
+                            envelope.envelope.frame
+                        }
+                        // the retry that is due is sent in the next iteration
+                        Either::Second(()) => continue,
+                    }
+                };
+
+                // a frame with a lower priority may be dropped from the
+                // mailboxes to make space for this one
+                let dropped = can.$write(&frame).await;
+                if let Some(expected) = $expected_acks(raw_id(frame.id())) {
+                    acks.sent(expected, Instant::now());
+                }
+                let Some(dropped) = dropped else {
+                    continue;
+                };
+                let id = raw_id(dropped.id());
+                let policy = $retransmit_policy(id);
+                if let Err(reason) = retries.schedule(dropped, id, policy, Instant::now()) {
+                    warn!("[{}] Dropped frame with ID {}: {}", $name, id, reason);
+                    health.record_tx_dropped();
+                    if policy.is_some_and(|p| p.must_ack) && undelivered.try_send(id).is_err() {
+                        error!("[{}] Too many undelivered frames to report", $name);
+                    }
+                }
+            }
+        }
+
+        #[doc = concat!("Interface for communicating over the ", $name, " bus")]
+        #[allow(missing_debug_implementations)]
+        pub struct CanInterface {
+            /// Channel with the messages received from the bus
+            rx_channel: CanRxChannel,
+            /// Channel with the messages waiting to be sent over the bus
+            tx_channel: CanTxChannel,
+            /// Counters of what went wrong on the bus
+            health: CanHealth,
+            /// Gives access to the error state of the peripheral
+            properties: Properties,
+            /// IDs of frames that could not be delivered
+            undelivered: UndeliveredChannel,
+            /// Acknowledgements expected for the commands sent over the bus
+            acks: AckTracker,
+        }
+
+        impl CanInterface {
+            #[doc = concat!("Initializes the ", $name, " interface.")]
+            ///
+            /// This function should be called once at the beginning of the
+            /// program.
+            ///
+            /// It takes in the CAN peripheral, which should be initialized and
+            /// configured before calling this function, and a spawner, which is
+            /// used to spawn the RX and TX tasks.
+            pub fn new(can: Can<'static>, spawner: Spawner) -> &'static Self {
+                static CAN_INTERFACE: StaticCell<CanInterface> = StaticCell::new();
+
+                let (can_tx, can_rx, properties) = can.split();
+
+                let interface = CAN_INTERFACE.init(Self {
+                    rx_channel: CanRxChannel::new(),
+                    tx_channel: CanTxChannel::new(),
+                    health: CanHealth::new(),
+                    properties,
+                    undelivered: UndeliveredChannel::new(),
+                    acks: AckTracker::new(),
+                });
+
+                let publisher = unwrap!(interface.rx_channel.publisher());
+                let receiver = interface.tx_channel.receiver();
+
+                let health = &interface.health;
+                let acks = &interface.acks;
+                unwrap!(spawner.spawn(can_rx_task(can_rx, publisher, health, acks)));
+                let undelivered = interface.undelivered.sender();
+                unwrap!(spawner.spawn(can_tx_task(can_tx, receiver, health, undelivered, acks)));
+
+                interface
+            }
+
+            /// Adds a new subscriber to the RX channel.
+            ///
+            /// The subscriber will be notified about all the
+            /// CAN messages received from the CAN bus.
+            pub fn new_subscriber(&self) -> CanRxSubscriber<'_> {
+                unwrap!(self.rx_channel.subscriber())
+            }
+
+            /// Adds a new sender to the TX channel.
+            ///
+            /// The sender can be used to send messages on
+            /// the CAN bus.
+            pub fn new_sender(&self) -> CanTxSender<'_> {
+                self.tx_channel.sender()
+            }
+
+            /// The counters of what went wrong on the bus.
+            pub fn health(&self) -> &CanHealth {
+                &self.health
+            }
+
+            /// Waits for a frame that could not be delivered after all of its
+            /// retries, and returns its ID. Only reported for commands whose
+            /// retransmit policy has `must_ack`.
+            pub async fn next_undelivered(&self) -> u32 {
+                self.undelivered.receive().await
+            }
+
+            /// The acknowledgements expected for the commands sent over the bus.
+            pub fn acks(&self) -> &AckTracker {
+                &self.acks
+            }
+
+            /// Reads the error state of the bus from the peripheral.
+            pub fn bus_state(&self) -> BusState {
+                BusState::read(&self.properties)
+            }
+        }
+    };
+}
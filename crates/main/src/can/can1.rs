@@ -0,0 +1,27 @@
+//! Module that deals with communication to the CAN1 bus.
+//!
+//! The CAN1 bus is connected to the BMSs and the sensor hub. Unlike
+//! [`super::can2`], it is a CAN-FD bus: frames can carry up to 64 bytes and
+//! the data phase can run at a higher bitrate than the arbitration phase.
+//!
+//! The main type is [`CanInterface`], which works the same as the one for
+//! CAN2: [`CanInterface::new_subscriber`] gives a subscriber which receives
+//! CAN messages, and [`CanInterface::new_sender`] allows other parts of the
+//! code to send CAN messages over the bus.
+//!
+//! The received messages are listened for in [`can_rx_task`].
+//! The sent messages are forwarded to the CAN bus in [`can_tx_task`]. Both,
+//! like the rest of the module, come from the `can_bus!` macro in
+//! [`super::bus`].
+
+can_bus! {
+    name: "CAN1",
+    envelope: lib::can::can1::CanEnvelope,
+    frame: embassy_stm32::can::frame::FdFrame,
+    read: read_fd,
+    write: write_fd,
+    expected_acks: lib::config::expected_acks_can1,
+    retransmit_policy: lib::config::retransmit_policy_can1,
+    rx_subscribers: 2,
+    rx_publishers: 1,
+}
@@ -0,0 +1,29 @@
+//! Module that deals with communication to the CAN2 bus.
+//!
+//! The CAN2 bus is connected to the Levitation and Propulsion controllers.
+//! The bus itself is normal CAN, not CAN-FD like [`super::can1`].
+//!
+//! The main type is [`CanInterface`], which is used to
+//! expose an interface to the CAN implementation.
+//!
+//! The two main methods it exposes are [`CanInterface::new_subscriber`]
+//! to get a subscriber which receives CAN messages, and
+//! [`CanInterface::new_sender`] which allows other parts of the code to
+//! send CAN messages over the bus.
+//!
+//! The received messages are listened for in [`can_rx_task`].
+//! The sent messages are forwarded to the CAN bus in [`can_tx_task`]. Both,
+//! like the rest of the module, come from the `can_bus!` macro in
+//! [`super::bus`].
+
+can_bus! {
+    name: "CAN2",
+    envelope: lib::can::can2::CanEnvelope,
+    frame: embassy_stm32::can::frame::Frame,
+    read: read,
+    write: write,
+    expected_acks: lib::config::expected_acks_can2,
+    retransmit_policy: lib::config::retransmit_policy_can2,
+    rx_subscribers: 3,
+    rx_publishers: 2,
+}
@@ -0,0 +1,259 @@
+//! Health monitoring of the CAN buses.
+//!
+//! The RX and TX tasks of each [`super::can1::CanInterface`] and
+//! [`super::can2::CanInterface`] count what goes wrong on their bus in a
+//! [`CanHealth`]. The error state of the bus itself is read from the FDCAN
+//! peripheral as a [`BusState`]. Both are sent to the ground station
+//! periodically by [`crate::comms_tasks::report_can_health`], which uses a
+//! [`BusMonitor`] per bus to notice when a bus goes bus-off. The bus state is
+//! polled every [`BUS_OFF_POLL_PERIOD`], more often than it is reported, so
+//! that the bus-off emergency is raised close to `CAN_BUS_OFF_TIMEOUT`.
+
+use core::cell::RefCell;
+
+use defmt::*;
+use embassy_stm32::can::enums::BusErrorMode;
+use embassy_stm32::can::Properties;
+use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_time::Duration;
+use embassy_time::Instant;
+use heapless::Vec;
+use lib::config::Datatype;
+use lib::config::CAN_BUS_OFF_TIMEOUT;
+
+/// Maximum number of CAN IDs whose received messages are counted separately.
+/// Messages with other IDs are only counted in total.
+const MAX_TRACKED_IDS: usize = 64;
+
+/// Maximum number of datapoints [`BusMonitor::report`] gives for a single bus
+pub const MAX_HEALTH_DATAPOINTS: usize = MAX_TRACKED_IDS + 5;
+
+/// How often [`BusMonitor::check_bus_off`] should be called, in milliseconds.
+/// The bus-off emergency is raised at most this much later than
+/// `CAN_BUS_OFF_TIMEOUT`.
+pub const BUS_OFF_POLL_PERIOD: u64 = if CAN_BUS_OFF_TIMEOUT >= 10 {
+    CAN_BUS_OFF_TIMEOUT / 10
+} else {
+    1
+};
+
+/// Counters of a single bus, behind the mutex of [`CanHealth`]
+struct HealthCounters {
+    /// Number of errors returned when reading from the bus
+    rx_errors: u32,
+    /// Number of frames dropped from the TX mailboxes to make space for a
+    /// frame with a higher priority
+    tx_dropped: u32,
+    /// Number of messages subscribers missed because they fell behind
+    lagged: u32,
+    /// Number of received messages per CAN ID
+    rx_counts: Vec<(u32, u32), MAX_TRACKED_IDS>,
+    /// Number of received messages with an ID that didn't fit in `rx_counts`
+    untracked_rx: u32,
+}
+
+/// Counters of what went wrong on a CAN bus, shared between the tasks using
+/// the bus and the task reporting them to the ground station.
+pub struct CanHealth(Mutex<NoopRawMutex, RefCell<HealthCounters>>);
+
+impl CanHealth {
+    /// Creates a `CanHealth` with all counters at zero.
+    pub const fn new() -> Self {
+        Self(Mutex::new(RefCell::new(HealthCounters {
+            rx_errors: 0,
+            tx_dropped: 0,
+            lagged: 0,
+            rx_counts: Vec::new(),
+            untracked_rx: 0,
+        })))
+    }
+
+    /// Counts a message received with the given ID.
+    pub fn record_rx(&self, id: u32) {
+        self.0.lock(|c| {
+            let mut c = c.borrow_mut();
+            if let Some((_, count)) = c.rx_counts.iter_mut().find(|(i, _)| *i == id) {
+                *count = count.wrapping_add(1);
+            } else if c.rx_counts.push((id, 1)).is_err() {
+                c.untracked_rx = c.untracked_rx.wrapping_add(1);
+            }
+        });
+    }
+
+    /// Counts an error returned when reading from the bus.
+    pub fn record_rx_error(&self) {
+        self.0.lock(|c| {
+            let mut c = c.borrow_mut();
+            c.rx_errors = c.rx_errors.wrapping_add(1);
+        });
+    }
+
+    /// Counts a frame dropped from the TX mailboxes.
+    pub fn record_tx_dropped(&self) {
+        self.0.lock(|c| {
+            let mut c = c.borrow_mut();
+            c.tx_dropped = c.tx_dropped.wrapping_add(1);
+        });
+    }
+
+    /// Counts `missed` messages a subscriber didn't get because it lagged
+    /// behind.
+    pub fn record_lag(&self, missed: u64) {
+        self.0.lock(|c| {
+            let mut c = c.borrow_mut();
+            c.lagged = c.lagged.wrapping_add(missed as u32);
+        });
+    }
+
+    /// Calls `f` with the datatype and value of every datapoint describing
+    /// the counters. The number of messages per ID is sent as the ID in the
+    /// lower 32 bits and the count in the upper 32 bits, with ID `u32::MAX`
+    /// for the messages that aren't counted per ID.
+    fn for_each_datapoint(&self, datatypes: &HealthDatatypes, mut f: impl FnMut(Datatype, u64)) {
+        self.0.lock(|c| {
+            let c = c.borrow();
+            f(datatypes.rx_errors, c.rx_errors as u64);
+            f(datatypes.tx_dropped, c.tx_dropped as u64);
+            f(datatypes.lagged, c.lagged as u64);
+            for (id, count) in &c.rx_counts {
+                f(datatypes.rx_count, *id as u64 | (*count as u64) << 32);
+            }
+            if c.untracked_rx != 0 {
+                f(
+                    datatypes.rx_count,
+                    u32::MAX as u64 | (c.untracked_rx as u64) << 32,
+                );
+            }
+        });
+    }
+}
+
+impl Default for CanHealth {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl core::fmt::Debug for CanHealth {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "CanHealth {{ ... }}")
+    }
+}
+
+/// The error state of a bus, as kept by the FDCAN peripheral
+#[derive(Clone, Copy, PartialEq, Eq, Debug, defmt::Format)]
+pub struct BusState {
+    /// Whether the peripheral is error active, error passive or bus-off
+    pub mode: BusErrorMode,
+    /// Transmit error counter
+    pub tec: u8,
+    /// Receive error counter
+    pub rec: u8,
+}
+
+impl BusState {
+    /// Reads the error state from the peripheral.
+    pub fn read(properties: &Properties) -> Self {
+        Self {
+            mode: properties.bus_error_mode(),
+            tec: properties.tx_error_count(),
+            rec: properties.rx_error_count(),
+        }
+    }
+
+    /// Packs the state into the value of a `Can*BusState` datapoint:
+    /// - byte 0: 0 if error active, 1 if error passive, 2 if bus-off
+    /// - byte 1: transmit error counter
+    /// - byte 2: receive error counter
+    pub fn to_value(&self) -> u64 {
+        let mode = match self.mode {
+            BusErrorMode::ErrorActive => 0,
+            BusErrorMode::ErrorPassive => 1,
+            BusErrorMode::BusOff => 2,
+        };
+        mode | (self.tec as u64) << 8 | (self.rec as u64) << 16
+    }
+}
+
+/// The datatypes the health of a bus is sent to the ground station with
+#[derive(Clone, Copy, Debug)]
+pub struct HealthDatatypes {
+    /// Datatype for the [`BusState`]
+    pub bus_state: Datatype,
+    /// Datatype for the number of read errors
+    pub rx_errors: Datatype,
+    /// Datatype for the number of dropped TX frames
+    pub tx_dropped: Datatype,
+    /// Datatype for the number of messages missed by lagging subscribers
+    pub lagged: Datatype,
+    /// Datatype for the number of messages received per ID
+    pub rx_count: Datatype,
+}
+
+/// Keeps track of the error state of a bus between reports, to log its
+/// transitions and to tell when it has been bus-off for too long.
+#[derive(Debug)]
+pub struct BusMonitor {
+    /// Name of the bus, used in the logs
+    name: &'static str,
+    /// The datatypes the health of this bus is sent with
+    datatypes: HealthDatatypes,
+    /// Error mode at the last check
+    last_mode: BusErrorMode,
+    /// When the bus went bus-off, if it is
+    bus_off_since: Option<Instant>,
+    /// Whether the emergency for the current bus-off period was raised
+    emergency_raised: bool,
+}
+
+impl BusMonitor {
+    /// Creates a monitor for a bus that is error active.
+    pub fn new(name: &'static str, datatypes: HealthDatatypes) -> Self {
+        Self {
+            name,
+            datatypes,
+            last_mode: BusErrorMode::ErrorActive,
+            bus_off_since: None,
+            emergency_raised: false,
+        }
+    }
+
+    /// Calls `f` with every datapoint about the bus to send to the ground
+    /// station.
+    pub fn report(&self, state: BusState, health: &CanHealth, mut f: impl FnMut(Datatype, u64)) {
+        f(self.datatypes.bus_state, state.to_value());
+        health.for_each_datapoint(&self.datatypes, f);
+    }
+
+    /// Updates the monitor with the current state of the bus. Should be
+    /// called every [`BUS_OFF_POLL_PERIOD`].
+    ///
+    /// Returns `true` once the bus has been bus-off for longer than
+    /// `CAN_BUS_OFF_TIMEOUT`, and not again until it recovers.
+    pub fn check_bus_off(&mut self, state: BusState, now: Instant) -> bool {
+        if state.mode != self.last_mode {
+            match state.mode {
+                BusErrorMode::ErrorActive => info!("[{}] Bus recovered: {}", self.name, state),
+                BusErrorMode::ErrorPassive => warn!("[{}] Bus error passive: {}", self.name, state),
+                BusErrorMode::BusOff => error!("[{}] Bus off: {}", self.name, state),
+            }
+            self.last_mode = state.mode;
+        }
+
+        if state.mode != BusErrorMode::BusOff {
+            self.bus_off_since = None;
+            self.emergency_raised = false;
+            return false;
+        }
+
+        let since = *self.bus_off_since.get_or_insert(now);
+        if !self.emergency_raised
+            && now.duration_since(since) >= Duration::from_millis(CAN_BUS_OFF_TIMEOUT)
+        {
+            self.emergency_raised = true;
+            return true;
+        }
+        false
+    }
+}
@@ -0,0 +1,125 @@
+//! Retransmission of frames dropped from the transmit mailboxes.
+//!
+//! When a frame is written while all mailboxes are full, the FDCAN peripheral
+//! drops the pending frame with the lowest priority to make space, and gives it
+//! back. If the command of the dropped frame has a [`RetransmitPolicy`] in the
+//! dataflow, the TX task puts it in a [`RetryQueue`] to send it again later.
+
+use embassy_time::Instant;
+use heapless::Vec;
+use lib::can::RetransmitPolicy;
+use lib::config::CAN_RETRANSMIT_QUEUE_SIZE;
+
+/// A frame waiting to be sent again
+#[derive(Debug)]
+pub struct Retry<F> {
+    /// The frame itself
+    pub frame: F,
+    /// The CAN ID of the frame
+    pub id: u32,
+    /// Which retry this is, starting at 1
+    pub attempt: u8,
+    /// When to send the frame
+    due: Instant,
+}
+
+/// Why a dropped frame won't be sent again
+#[derive(Debug, PartialEq, Eq, defmt::Format)]
+pub enum GaveUp {
+    /// The command of the frame has no retransmit policy
+    NoPolicy,
+    /// The frame was already retried as often as its policy allows
+    OutOfRetries,
+    /// There is no space left in the retry queue
+    QueueFull,
+}
+
+/// Bounded queue of dropped frames waiting to be sent again, used by the TX
+/// task of a bus.
+#[derive(Debug)]
+pub struct RetryQueue<F> {
+    /// The frames waiting to be sent again, in no particular order
+    retries: Vec<Retry<F>, CAN_RETRANSMIT_QUEUE_SIZE>,
+    /// The attempt of the frames that were retried and may still be in the
+    /// mailboxes, by CAN ID. Used to continue counting when such a frame is
+    /// dropped again.
+    in_flight: Vec<(u32, u8), CAN_RETRANSMIT_QUEUE_SIZE>,
+}
+
+impl<F> RetryQueue<F> {
+    /// Creates an empty queue.
+    pub const fn new() -> Self {
+        Self {
+            retries: Vec::new(),
+            in_flight: Vec::new(),
+        }
+    }
+
+    /// Schedules a dropped frame to be sent again according to `policy`.
+    pub fn schedule(
+        &mut self,
+        frame: F,
+        id: u32,
+        policy: Option<RetransmitPolicy>,
+        now: Instant,
+    ) -> Result<(), GaveUp> {
+        let policy = policy.ok_or(GaveUp::NoPolicy)?;
+        let previous = self
+            .in_flight
+            .iter()
+            .position(|(i, _)| *i == id)
+            .map(|i| self.in_flight.swap_remove(i).1)
+            .unwrap_or(0);
+        let attempt = previous + 1;
+
+        if attempt > policy.retries {
+            return Err(GaveUp::OutOfRetries);
+        }
+
+        self.retries
+            .push(Retry {
+                frame,
+                id,
+                attempt,
+                due: now + policy.delay(attempt),
+            })
+            .map_err(|_| GaveUp::QueueFull)
+    }
+
+    /// Forgets the attempts of the frames with `id` that were retried before.
+    /// Called when a new frame with `id` is sent, so it gets all of its
+    /// retries.
+    pub fn forget(&mut self, id: u32) {
+        self.in_flight.retain(|(i, _)| *i != id);
+    }
+
+    /// When the next frame is due, if any are waiting.
+    pub fn next_due(&self) -> Option<Instant> {
+        self.retries.iter().map(|r| r.due).min()
+    }
+
+    /// Takes the frame that is due first, if it is due at `now`. The frame
+    /// counts as in flight until it is dropped again.
+    pub fn pop_due(&mut self, now: Instant) -> Option<Retry<F>> {
+        let (i, _) = self
+            .retries
+            .iter()
+            .enumerate()
+            .filter(|(_, r)| r.due <= now)
+            .min_by_key(|(_, r)| r.due)?;
+        let retry = self.retries.swap_remove(i);
+
+        if self.in_flight.is_full() {
+            self.in_flight.remove(0);
+        }
+        let _ = self.in_flight.push((retry.id, retry.attempt));
+
+        Some(retry)
+    }
+}
+
+impl<F> Default for RetryQueue<F> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -3,7 +3,9 @@
 
 use defmt::*;
 use embassy_futures::select::select;
+use embassy_futures::select::select3;
 use embassy_futures::select::Either;
+use embassy_futures::select::Either3;
 use embassy_futures::yield_now;
 use embassy_sync::blocking_mutex::raw::NoopRawMutex;
 use embassy_sync::pubsub::WaitResult;
@@ -15,7 +17,11 @@ use embassy_time::Timer;
 use embedded_can::Frame;
 use embedded_can::Id;
 use embedded_can::StandardId;
+use lib::can::CanEnvelope;
 use lib::config;
+use lib::config::match_cmd_to_event;
+use lib::config::match_event_to_can_envelope;
+use lib::config::match_event_to_datapoint;
 use lib::config::Command;
 use lib::config::Datatype;
 use lib::config::COMMAND_HASH;
@@ -25,26 +31,77 @@ use lib::config::DATA_HASH;
 use lib::Datapoint;
 use lib::EmergencyType;
 use lib::Event;
+use lib::EventChannel;
 use lib::EventReceiver;
 use lib::EventSender;
-
-use crate::can as can2;
+use lib::FsmHistory;
+
+use crate::can::can1;
+use crate::can::can2;
+use crate::can::health::BusMonitor;
+use crate::can::health::CanHealth;
+use crate::can::health::HealthDatatypes;
+use crate::can::health::BUS_OFF_POLL_PERIOD;
+use crate::can::health::MAX_HEALTH_DATAPOINTS;
 use crate::ethernet;
 use crate::ethernet::ticks;
 use crate::ethernet::types::PodToGsMessage;
-use crate::matching_methods::match_can_id_to_event;
-use crate::matching_methods::match_cmd_to_event;
-use crate::matching_methods::match_event_to_can_envelope;
-use crate::matching_methods::match_event_to_datapoint;
+use crate::limits::LimitMonitor;
+
+/// Forwards CAN1 datapoints to the ground station and FSM as datapoints or
+/// events
+#[embassy_executor::task]
+pub async fn forward_can1_datapoints(
+    gs_tx: ethernet::types::PodToGsPublisher<'static>,
+    event_sender: EventSender,
+    mut can_rx: can1::CanRxSubscriber<'static>,
+    health: &'static CanHealth,
+) {
+    let mut limits = LimitMonitor::new();
+    loop {
+        let envelope = match can_rx.next_message().await {
+            WaitResult::Message(envelope) => envelope,
+            WaitResult::Lagged(i) => {
+                warn!("[CAN1] Lagged {} messages", i);
+                health.record_lag(i);
+                yield_now().await;
+                continue;
+            }
+        };
+        let id = envelope.raw_id();
+
+        // Match a CAN ID and payload to an FSM event using the auto-generated
+        // method
+        let event = lib::config::event_for_can_1_id(id, envelope.payload());
+        if event != Event::NoEvent {
+            event_sender.send(event).await;
+        }
+
+        // Send the datapoint to the ground station, braking first if it is out
+        // of bounds
+        lib::config::parse_datapoints_can_1(id, envelope.payload(), |dp, check| {
+            let brake = limits.record(dp.datatype, check);
+            async move {
+                if brake {
+                    brake_for_value_out_of_bounds(event_sender, gs_tx, dp.datatype).await;
+                }
+                gs_tx.send(PodToGsMessage { dp }).await;
+            }
+        })
+        .await;
+    }
+}
 
-/// Forwards CAN datapoints to the ground station and FSM as datapoints or
+/// Forwards CAN2 datapoints to the ground station and FSM as datapoints or
 /// events
 #[embassy_executor::task]
-pub async fn forward_can_datapoints(
+pub async fn forward_can2_datapoints(
     gs_tx: ethernet::types::PodToGsPublisher<'static>,
     event_sender: EventSender,
     mut can_rx: can2::CanRxSubscriber<'static>,
+    health: &'static CanHealth,
 ) {
+    let mut limits = LimitMonitor::new();
     loop {
         let msg = can_rx.next_message().await;
 
@@ -52,46 +109,70 @@ pub async fn forward_can_datapoints(
             WaitResult::Message(envelope) => envelope,
             WaitResult::Lagged(i) => {
                 warn!("Lagged {} messages", i);
+                health.record_lag(i);
                 yield_now().await;
                 continue;
             }
         };
-        let id = match envelope.id() {
-            Id::Extended(e) => e.as_raw(),
-            Id::Standard(s) => s.as_raw() as u32,
-        };
+        let id = envelope.raw_id();
 
         let payload = envelope.payload();
 
-        // Manual match from a CAN ID to an FSM event
-        let event = match_can_id_to_event(id, payload);
+        // Match a CAN ID and payload to an FSM event using the auto-generated
+        // method
+        let event = lib::config::event_for_can_2_id(id, payload);
         // Send the event to the FSM
         if event != Event::NoEvent {
             event_sender.send(event).await;
         }
 
-        // Match a CAN ID to an FSM event using the auto-generated method
-        let event = lib::config::event_for_can_2_id(id);
-        // Send the event to the FSM
-        if event != fsm::Event::NoEvent {
-            event_sender.send(event).await;
-        }
-
-        // Send the datapoint to the ground station
-        lib::config::parse_datapoints_can_2(id, payload, |dp| async move {
-            gs_tx.send(PodToGsMessage { dp }).await;
+        // Send the datapoint to the ground station, braking first if it is out
+        // of bounds
+        lib::config::parse_datapoints_can_2(id, payload, |dp, check| {
+            let brake = limits.record(dp.datatype, check);
+            async move {
+                if brake {
+                    brake_for_value_out_of_bounds(event_sender, gs_tx, dp.datatype).await;
+                }
+                gs_tx.send(PodToGsMessage { dp }).await;
+            }
         })
         .await;
     }
 }
 
+/// Makes the pod brake because `datatype` stayed past its `brake` threshold,
+/// and tells the ground station which datatype caused it.
+async fn brake_for_value_out_of_bounds(
+    event_sender: EventSender,
+    gs_tx: ethernet::types::PodToGsPublisher<'static>,
+    datatype: Datatype,
+) {
+    error!("{} crossed its brake limit", datatype);
+    event_sender
+        .send(Event::Emergency {
+            emergency_type: EmergencyType::ValueOutOfBounds,
+        })
+        .await;
+    gs_tx
+        .send(PodToGsMessage {
+            dp: Datapoint::new(
+                Datatype::ValueCausedBraking,
+                datatype.to_id() as u64,
+                ticks(),
+            ),
+        })
+        .await;
+}
+
 /// Forwards ground station commands to the FSM and over CAN as events or
 /// commands
 #[embassy_executor::task]
 pub async fn forward_gs_commands(
     mut gs_rx: ethernet::types::GsToPodSubscriber<'static>,
     event_sender: EventSender,
-    can_tx: can2::CanTxSender<'static>,
+    can1_tx: can1::CanTxSender<'static>,
+    can2_tx: can2::CanTxSender<'static>,
 ) {
     loop {
         let msg = gs_rx.next_message_pure().await;
@@ -99,6 +180,18 @@ pub async fn forward_gs_commands(
 
         // Get the command sent and match it to an FSM event
         let command: Command = msg.command;
+
+        // Drop commands whose payload doesn't make sense, instead of sending it on
+        if let Err(e) = command.check_payload() {
+            error!("Dropping command {}: {}", command.to_str(), e);
+            continue;
+        }
+        // Test-only commands mock parts of the pod, so only test builds take them
+        #[cfg(not(feature = "test-commands"))]
+        if command.safety() == config::CommandSafety::TestOnly {
+            error!("Dropping test-only command {}", command.to_str());
+            continue;
+        }
         let event: Event = match_cmd_to_event(command);
 
         // Send the event to the FSM
@@ -107,12 +200,9 @@ pub async fn forward_gs_commands(
             _ => event_sender.send(event).await,
         }
 
-        if let Command::SendHashes(_) = command {
-            event_sender.send(Event::SendHashes).await;
-        }
-
-        // Forward the command to the CAN bus
-        config::gs_to_can2(command, |frame| can_tx.send(frame)).await;
+        // Forward the command to the CAN bus it is meant for
+        config::gs_to_can1(command, |frame| can1_tx.send(frame)).await;
+        config::gs_to_can2(command, |frame| can2_tx.send(frame)).await;
     }
 }
 
@@ -123,6 +213,7 @@ pub async fn forward_fsm_events(
     gs_tx: ethernet::types::PodToGsPublisher<'static>,
     can_tx: can2::CanTxSender<'static>,
     event_receiver: EventReceiver,
+    history: &'static FsmHistory,
 ) -> ! {
     loop {
         // Get the event from the FSM
@@ -153,6 +244,24 @@ pub async fn forward_fsm_events(
                 .await;
         }
 
+        // Send the transition history of the FSM to the ground station: first the
+        // number of entries, then the entries themselves, oldest first
+        if let Event::DumpFsmHistory = event {
+            let entries = history.snapshot();
+            gs_tx
+                .send(PodToGsMessage {
+                    dp: Datapoint::new(Datatype::FsmHistoryLength, entries.len() as u64, ticks()),
+                })
+                .await;
+            for entry in entries {
+                gs_tx
+                    .send(PodToGsMessage {
+                        dp: Datapoint::new(Datatype::FsmHistoryEntry, entry.to_value(), ticks()),
+                    })
+                    .await;
+            }
+        }
+
         // Match the event to a GroundStationToPod message and send it
         let message = match_event_to_datapoint(event);
         if let Some(message) = message {
@@ -165,23 +274,27 @@ pub async fn forward_fsm_events(
     }
 }
 
-/// Periodically checks if critical datapoints become stale. If so, send an
-/// emergency event to the FSM.
+/// Critical datatypes that were received, with when they were last received
+/// and whether going stale should still be reported to the ground station.
+type CriticalDatapoints = [(config::Datatype, Instant, bool); CRITICAL_DATATYPE_COUNT];
+
+/// Checks if critical datapoints become stale, each with the timeout and in
+/// the FSM states given in the dataflow. If so, send an emergency event to the
+/// FSM.
 #[embassy_executor::task]
 pub async fn check_critical_datapoints(
-    mut can_rx: can2::CanRxSubscriber<'static>,
+    mut can1_rx: can1::CanRxSubscriber<'static>,
+    mut can2_rx: can2::CanRxSubscriber<'static>,
     event_sender: EventSender,
     gs_tx: ethernet::types::PodToGsPublisher<'static>,
     signal: &'static Signal<NoopRawMutex, bool>,
+    history: &'static FsmHistory,
 ) {
     // Wait for the signal to indicate that you are connected to the ground station
     signal.wait().await;
 
-    // The time in milliseconds after which a datatype is considered stale
-    const STALE_DATA_TIMEOUT: u64 = 2_000;
-    // how often to check for stale data. in the worst case, this is how long the
-    // delay will be between a datatype truly going stale and us going into
-    // fault state.
+    // how often to send the emergency again while a datatype stays stale. a
+    // datatype going stale is noticed as soon as its timeout passes.
     let mut check_ticker = Ticker::every(Duration::from_millis(150));
 
     // Store the timestamps for each checked datapoint
@@ -190,23 +303,56 @@ pub async fn check_critical_datapoints(
     // same datatypes multiple times to avoid popups infinitely triggering on the
     // ground station. This value resets whenever we received the datapoint
     // again.
-    let mut critical_datapoints: [(config::Datatype, Instant, bool); CRITICAL_DATATYPE_COUNT] = [(
+    let mut critical_datapoints: CriticalDatapoints = [(
         config::Datatype::DefaultDatatype,
         Instant::from_ticks(0),
         false,
-    );
-        CRITICAL_DATATYPE_COUNT];
+    ); CRITICAL_DATATYPE_COUNT];
 
     loop {
-        match select(check_ticker.next(), can_rx.next_message_pure()).await {
-            Either::First(_check_timeout) => {
+        let state = history.current_state();
+
+        // the first moment a datatype that wasn't reported yet goes stale
+        let next_stale = critical_datapoints
+            .iter()
+            .take_while(|(dt, _, _)| *dt != Datatype::DefaultDatatype)
+            .filter(|(dt, _, should_send)| *should_send && dt.checks_staleness(state))
+            .filter_map(|(dt, last_seen, _)| {
+                Some(*last_seen + Duration::from_millis(dt.stale_timeout()?))
+            })
+            .min();
+        let check = async {
+            match next_stale {
+                Some(at) => {
+                    select(check_ticker.next(), Timer::at(at)).await;
+                }
+                None => check_ticker.next().await,
+            }
+        };
+
+        match select3(
+            check,
+            can1_rx.next_message_pure(),
+            can2_rx.next_message_pure(),
+        )
+        .await
+        {
+            Either3::First(()) => {
                 let now = Instant::now();
 
                 for (dt, last_seen, should_send) in critical_datapoints.iter_mut() {
                     if *dt == Datatype::DefaultDatatype {
                         break;
                     }
-                    if now.duration_since(*last_seen).as_millis() >= STALE_DATA_TIMEOUT {
+                    let Some(timeout) = dt.stale_timeout() else {
+                        continue;
+                    };
+                    // the state may have changed while waiting, also by the
+                    // emergencies sent for the datatypes before this one
+                    if !dt.checks_staleness(history.current_state()) {
+                        continue;
+                    }
+                    if now.duration_since(*last_seen).as_millis() >= timeout {
                         event_sender
                             .send(Event::Emergency {
                                 emergency_type: EmergencyType::StaleCriticalDataEmergency,
@@ -229,31 +375,37 @@ pub async fn check_critical_datapoints(
                     }
                 }
             }
-            Either::Second(can_frame) => {
-                let id = match can_frame.id() {
-                    Id::Standard(s) => s.as_raw() as u32,
-                    Id::Extended(e) => e.as_raw(),
-                };
-
-                // get the datatypes associated with the ID of the received CAN message
-                let received_datatypes = lib::config::match_can_to_datatypes(id);
-
-                // Check if the received datatypes are critical
-                for datatype in received_datatypes {
-                    if datatype == Datatype::DefaultDatatype {
-                        break;
-                    }
-                    if datatype.is_critical() {
-                        if let Some(slot) = critical_datapoints
-                            .iter_mut()
-                            .find(|(d, _, _)| *d == datatype || *d == Datatype::DefaultDatatype)
-                        {
-                            *slot = (datatype, Instant::now(), true);
-                        } else {
-                            error!("Didn't find the critical datatype!!");
-                        }
-                    }
-                }
+            Either3::Second(can_frame) => record_critical_datapoints(
+                &mut critical_datapoints,
+                lib::config::match_can_1_to_datatypes(can_frame.raw_id()),
+            ),
+            Either3::Third(can_frame) => record_critical_datapoints(
+                &mut critical_datapoints,
+                lib::config::match_can_2_to_datatypes(can_frame.raw_id()),
+            ),
+        }
+    }
+}
+
+/// Marks the critical datatypes among the datatypes of a received CAN message
+/// as just received.
+fn record_critical_datapoints(
+    critical_datapoints: &mut CriticalDatapoints,
+    received_datatypes: [Datatype; 8],
+) {
+    // Check if the received datatypes are critical
+    for datatype in received_datatypes {
+        if datatype == Datatype::DefaultDatatype {
+            break;
+        }
+        if datatype.is_critical() {
+            if let Some(slot) = critical_datapoints
+                .iter_mut()
+                .find(|(d, _, _)| *d == datatype || *d == Datatype::DefaultDatatype)
+            {
+                *slot = (datatype, Instant::now(), true);
+            } else {
+                error!("Didn't find the critical datatype!!");
             }
         }
     }
@@ -336,3 +488,148 @@ pub async fn gs_heartbeat(gs_tx: ethernet::types::PodToGsPublisher<'static>) {
         // random += 1;
     }
 }
+
+/// Sends the health of both CAN buses to the ground station every
+/// `CAN_HEALTH_REPORT_PERIOD` milliseconds, together with the number of events
+/// dropped by the channels to and from the FSM, and triggers an emergency when
+/// a bus stays bus-off for longer than `CAN_BUS_OFF_TIMEOUT` milliseconds. The
+/// bus states are checked for that every [`BUS_OFF_POLL_PERIOD`]
+/// milliseconds.
+#[embassy_executor::task]
+pub async fn report_can_health(
+    gs_tx: ethernet::types::PodToGsPublisher<'static>,
+    event_sender: EventSender,
+    can1: &'static can1::CanInterface,
+    can2: &'static can2::CanInterface,
+    events_in: &'static EventChannel,
+    events_out: &'static EventChannel,
+) -> ! {
+    let mut can1_monitor = BusMonitor::new(
+        "CAN1",
+        HealthDatatypes {
+            bus_state: Datatype::Can1BusState,
+            rx_errors: Datatype::Can1RxErrors,
+            tx_dropped: Datatype::Can1TxDropped,
+            lagged: Datatype::Can1RxLagged,
+            rx_count: Datatype::Can1RxCount,
+        },
+    );
+    let mut can2_monitor = BusMonitor::new(
+        "CAN2",
+        HealthDatatypes {
+            bus_state: Datatype::Can2BusState,
+            rx_errors: Datatype::Can2RxErrors,
+            tx_dropped: Datatype::Can2TxDropped,
+            lagged: Datatype::Can2RxLagged,
+            rx_count: Datatype::Can2RxCount,
+        },
+    );
+    let mut report_ticker = Ticker::every(Duration::from_millis(config::CAN_HEALTH_REPORT_PERIOD));
+    let mut poll_ticker = Ticker::every(Duration::from_millis(BUS_OFF_POLL_PERIOD));
+
+    loop {
+        let report = match select(report_ticker.next(), poll_ticker.next()).await {
+            Either::First(()) => true,
+            Either::Second(()) => false,
+        };
+
+        let now = Instant::now();
+        let buses = [
+            (&mut can1_monitor, can1.bus_state(), can1.health()),
+            (&mut can2_monitor, can2.bus_state(), can2.health()),
+        ];
+        for (monitor, state, health) in buses {
+            if monitor.check_bus_off(state, now) {
+                event_sender
+                    .send(Event::Emergency {
+                        emergency_type: EmergencyType::EmergencyCanBusOff,
+                    })
+                    .await;
+            }
+            if !report {
+                continue;
+            }
+
+            let mut datapoints = heapless::Vec::<_, MAX_HEALTH_DATAPOINTS>::new();
+            monitor.report(state, health, |datatype, value| {
+                let _ = datapoints.push((datatype, value));
+            });
+            for (datatype, value) in datapoints {
+                gs_tx
+                    .send(PodToGsMessage {
+                        dp: Datapoint::new(datatype, value, ticks()),
+                    })
+                    .await;
+            }
+        }
+        if !report {
+            continue;
+        }
+
+        let channels = [
+            (Datatype::FsmEventsInDropped, events_in),
+            (Datatype::FsmEventsOutDropped, events_out),
+        ];
+        for (datatype, channel) in channels {
+            gs_tx
+                .send(PodToGsMessage {
+                    dp: Datapoint::new(datatype, channel.dropped().into(), ticks()),
+                })
+                .await;
+        }
+    }
+}
+
+/// Tells the ground station about the frames of commands with `must_ack` that
+/// could not be delivered on either CAN bus, even after retrying.
+#[embassy_executor::task]
+pub async fn report_undelivered_frames(
+    gs_tx: ethernet::types::PodToGsPublisher<'static>,
+    can1: &'static can1::CanInterface,
+    can2: &'static can2::CanInterface,
+) -> ! {
+    loop {
+        let (datatype, id) = match select(can1.next_undelivered(), can2.next_undelivered()).await {
+            Either::First(id) => (Datatype::Can1TxUndelivered, id),
+            Either::Second(id) => (Datatype::Can2TxUndelivered, id),
+        };
+        error!("Frame with ID {} could not be delivered", id);
+
+        gs_tx
+            .send(PodToGsMessage {
+                dp: Datapoint::new(datatype, id as u64, ticks()),
+            })
+            .await;
+    }
+}
+
+/// Tells the ground station whether the subsystems acknowledged the commands
+/// sent to them on either CAN bus, see [`crate::can::acks`].
+#[embassy_executor::task]
+pub async fn report_command_acks(
+    gs_tx: ethernet::types::PodToGsPublisher<'static>,
+    can1: &'static can1::CanInterface,
+    can2: &'static can2::CanInterface,
+) -> ! {
+    loop {
+        let report = match select(can1.acks().next_report(), can2.acks().next_report()).await {
+            Either::First(report) => report,
+            Either::Second(report) => report,
+        };
+        let datatype = if report.acked {
+            Datatype::CommandAcked
+        } else {
+            warn!(
+                "Command {} was not acknowledged with ID {} in time",
+                report.command, report.ack_id
+            );
+            Datatype::CommandTimedOut
+        };
+
+        gs_tx
+            .send(PodToGsMessage {
+                dp: Datapoint::new(datatype, report.to_value(), ticks()),
+            })
+            .await;
+    }
+}
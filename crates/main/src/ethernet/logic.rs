@@ -46,6 +46,7 @@ use crate::ethernet::types::GsToPodPublisher;
 use crate::ethernet::types::PodToGsMessage;
 use crate::ethernet::types::PodToGsPublisher;
 use crate::ethernet::types::PodToGsSubscriber;
+use crate::ethernet::types::SequenceWindow;
 use crate::ethernet::RX_BUFFER;
 use crate::ethernet::SOCKET_KEEP_ALIVE;
 use crate::ethernet::TX_BUFFER;
@@ -75,6 +76,9 @@ pub struct GsMaster {
     connection_is_broken: bool,
     /// the last time the link was caught with its pants down
     last_link_down: Instant,
+    /// The sequence numbers of the commands accepted over the current
+    /// connection
+    sequence: SequenceWindow,
 }
 
 impl Debug for GsMaster {
@@ -186,6 +190,7 @@ impl GsMaster {
             should_reconnect: false,
             connection_is_broken: false,
             last_link_down,
+            sequence: SequenceWindow::default(),
         }
     }
 
@@ -426,6 +431,9 @@ impl GsMaster {
             );
         }
 
+        // The GS numbers the commands of every connection from the start
+        self.sequence = SequenceWindow::default();
+
         // Connect to the GS
         self.connect().await;
         self.should_reconnect = false;
@@ -470,7 +478,12 @@ impl GsMaster {
                 #[cfg(debug_assertions)]
                 defmt::panic!("wut happened? the GS crashed mid-transmission?!");
                 #[cfg(not(debug_assertions))]
-                defmt::error!("tcp read error: UnexpectedEof");
+                {
+                    defmt::error!("tcp read error: UnexpectedEof");
+                    // the frame is incomplete, and the GS is gone
+                    self.should_reconnect = true;
+                    return;
+                }
             }
             Err(
                 e @ embedded_io_async::ReadExactError::Other(
@@ -480,12 +493,51 @@ impl GsMaster {
                 defmt::error!("{}", e);
                 self.should_reconnect = true;
                 Timer::after_millis(100).await;
+                return;
             }
         };
 
-        self.rx_transmitter
-            .publish(GsToPodMessage::read_from_buf(&buf))
-            .await;
+        self.handle_frame(&buf).await;
         Timer::after_micros(500).await;
     }
+
+    /// Publishes the command in a frame from the GS to the GsToPodChannel if
+    /// it is intact and new, and tells the GS whether it was accepted with a
+    /// `GsCommandAccepted` or `GsCommandRejected` datapoint. Resent commands
+    /// that were already accepted are acknowledged again, but not published.
+    /// Heartbeats aren't numbered, so they are published without an answer.
+    async fn handle_frame(&mut self, buf: &[u8; GsToPodMessage::SIZE]) {
+        let accepted = match GsToPodMessage::read_from_buf(buf) {
+            Ok(msg) if msg.seq == 0 && msg.command.is_heartbeat() => {
+                self.rx_transmitter.publish(msg).await;
+                return;
+            }
+            read => read.and_then(|msg| self.sequence.accept(msg.seq).map(|new| (msg, new))),
+        };
+
+        let dp = match accepted {
+            Ok((msg, new)) => {
+                if new {
+                    self.rx_transmitter.publish(msg).await;
+                } else {
+                    warn!(
+                        "Dropping duplicate command {} ({})",
+                        msg.command.to_str(),
+                        msg.seq
+                    );
+                }
+                Datapoint::new(Datatype::GsCommandAccepted, msg.seq as u64, ticks())
+            }
+            Err(rejection) => {
+                let seq = GsToPodMessage::claimed_seq(buf);
+                warn!("Rejecting command frame {}: {}", seq, rejection);
+                // like `CommandTimedOut`, the value packs the reason above the
+                // sequence number
+                let value = (rejection as u64) << 32 | seq as u64;
+                Datapoint::new(Datatype::GsCommandRejected, value, ticks())
+            }
+        };
+
+        self.tx_transmitter.send(PodToGsMessage { dp }).await;
+    }
 }
@@ -24,6 +24,7 @@ use embassy_sync::pubsub::PubSubChannel;
 use embassy_sync::pubsub::Publisher;
 use embassy_sync::pubsub::Subscriber;
 use lib::config::Command;
+use lib::config::CommandRejection;
 use lib::Datapoint;
 
 use crate::ethernet::CAP;
@@ -107,6 +108,8 @@ impl GsComms {
 pub struct GsToPodMessage {
     /// The command sent
     pub command: Command,
+    /// The sequence number the ground station gave the command
+    pub seq: u32,
 }
 
 impl GsToPodMessage {
@@ -114,11 +117,61 @@ impl GsToPodMessage {
     pub(crate) const SIZE: usize = 20;
 
     /// read a new instance of [`GsToPodMessage`] from a byte slice of size
-    /// exactly [`GsToPodMessage::SIZE`].
-    pub fn read_from_buf(buf: &[u8; Self::SIZE]) -> Self {
-        let command = Command::from_bytes(buf);
+    /// exactly [`GsToPodMessage::SIZE`], if its frame isn't corrupt.
+    pub fn read_from_buf(buf: &[u8; Self::SIZE]) -> Result<Self, CommandRejection> {
+        let (seq, command) = Command::from_frame(buf)?;
 
-        Self { command }
+        Ok(Self { command, seq })
+    }
+
+    /// The sequence number in a frame, which can be wrong if the frame is
+    /// corrupt.
+    pub fn claimed_seq(buf: &[u8; Self::SIZE]) -> u32 {
+        u32::from_be_bytes([buf[11], buf[12], buf[13], buf[14]])
+    }
+}
+
+/// The sequence numbers of the last commands accepted from the ground station.
+/// Only commands newer than all the ones before are run, so commands are
+/// never run out of order. Older ones are either resends of a command that
+/// was already accepted, or arrived out of order.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SequenceWindow {
+    /// The highest sequence number accepted so far, 0 before the first one
+    last: u32,
+    /// Bit n is set if sequence number `last - n` was accepted
+    seen: u64,
+}
+
+impl SequenceWindow {
+    /// Checks sequence number `seq` of a command from the ground station.
+    ///
+    /// # Returns:
+    /// - `Ok(true)` if the command is newer than every command before it, and
+    ///   is now accepted
+    /// - `Ok(false)` if it was already accepted within the last 64 sequence
+    ///   numbers, so it's a resend of a command whose acknowledgement got lost
+    /// - `Err(CommandRejection::OutOfOrder)` if it is 0, or older than the last
+    ///   accepted command without having been accepted itself
+    pub fn accept(&mut self, seq: u32) -> Result<bool, CommandRejection> {
+        if seq > self.last {
+            let shift = seq - self.last;
+            self.seen = if shift >= u64::BITS {
+                0
+            } else {
+                self.seen << shift
+            };
+            self.seen |= 1;
+            self.last = seq;
+            return Ok(true);
+        }
+
+        let age = self.last - seq;
+        if seq != 0 && age < u64::BITS && self.seen & (1 << age) != 0 {
+            Ok(false)
+        } else {
+            Err(CommandRejection::OutOfOrder)
+        }
     }
 }
 
@@ -7,4 +7,4 @@
 pub mod can;
 pub mod comms_tasks;
 pub mod ethernet;
-pub mod matching_methods;
+pub mod limits;
@@ -0,0 +1,74 @@
+//! Enforcement of the `limits` of datapoints on the pod itself.
+//!
+//! The generated parsers check every datapoint with `limits` in the dataflow
+//! against its bounds. The tasks forwarding the datapoints keep a
+//! [`LimitMonitor`], which tells them when a datapoint stayed past its
+//! `brake` threshold for long enough that the pod should brake, without
+//! waiting for the ground station.
+
+use defmt::*;
+use heapless::Vec;
+use lib::config::Datatype;
+use lib::config::ValueCheckResult;
+use lib::config::LIMIT_DEBOUNCE;
+
+/// Maximum number of datatypes that can be past their `brake` threshold at the
+/// same time
+const MAX_OUT_OF_BOUNDS: usize = 32;
+
+/// Counts for how many messages in a row datatypes have been past their
+/// `brake` threshold.
+#[derive(Debug)]
+pub struct LimitMonitor {
+    /// The datatypes past their `brake` threshold, with the number of messages
+    /// in a row they have been
+    out_of_bounds: Vec<(Datatype, u8), MAX_OUT_OF_BOUNDS>,
+}
+
+impl LimitMonitor {
+    /// Creates a monitor for datatypes that are all within bounds.
+    pub const fn new() -> Self {
+        Self {
+            out_of_bounds: Vec::new(),
+        }
+    }
+
+    /// Records the outcome of checking a received datapoint against its
+    /// limits.
+    ///
+    /// Returns `true` when the datatype has been past its `brake` threshold
+    /// for `LIMIT_DEBOUNCE` messages in a row, and not again until it is back
+    /// within bounds.
+    pub fn record(&mut self, datatype: Datatype, result: ValueCheckResult) -> bool {
+        let position = self.out_of_bounds.iter().position(|(d, _)| *d == datatype);
+
+        if result != ValueCheckResult::BrakeNow {
+            if let Some(i) = position {
+                self.out_of_bounds.swap_remove(i);
+            }
+            return false;
+        }
+
+        let count = match position {
+            Some(i) => {
+                let (_, count) = &mut self.out_of_bounds[i];
+                *count = count.saturating_add(1);
+                *count
+            }
+            None => {
+                if self.out_of_bounds.push((datatype, 1)).is_err() {
+                    error!("Too many datatypes out of bounds to track {}", datatype);
+                    return true;
+                }
+                1
+            }
+        };
+        count == LIMIT_DEBOUNCE.max(1)
+    }
+}
+
+impl Default for LimitMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -17,16 +17,24 @@ use embassy_stm32::wdg::IndependentWatchdog;
 use embassy_sync::blocking_mutex::raw::NoopRawMutex;
 use embassy_sync::signal::Signal;
 use embassy_time::Timer;
+use fsm::EmbassyClock;
+use fsm::SdcPins;
 use fsm::FSM;
 use lib::EventChannel;
 use lib::EventReceiver;
 use lib::EventSender;
-use main::can as can2;
+use lib::FsmHistory;
+use main::can::can1;
+use main::can::can2;
 use main::comms_tasks::check_critical_datapoints;
-use main::comms_tasks::forward_can_datapoints;
+use main::comms_tasks::forward_can1_datapoints;
+use main::comms_tasks::forward_can2_datapoints;
 use main::comms_tasks::forward_fsm_events;
 use main::comms_tasks::forward_gs_commands;
 use main::comms_tasks::gs_heartbeat;
+use main::comms_tasks::report_can_health;
+use main::comms_tasks::report_command_acks;
+use main::comms_tasks::report_undelivered_frames;
 use main::ethernet::logic::GsMaster;
 use main::ethernet::types::EthPeripherals;
 use main::ethernet::types::GsComms;
@@ -57,6 +65,10 @@ static EVENT_CHANNEL_IN: static_cell::StaticCell<EventChannel> = static_cell::St
 /// to datapoints/commands)
 static EVENT_CHANNEL_OUT: static_cell::StaticCell<EventChannel> = static_cell::StaticCell::new();
 
+/// the last transitions of the FSM, shared between the FSM and the task that
+/// sends them to the ground station
+static FSM_HISTORY: StaticCell<FsmHistory> = StaticCell::new();
+
 /// struct that runs the ethernet stack for connecting to the ground station
 static GS_MASTER: StaticCell<GsMaster> = StaticCell::new();
 /// struct for the channels used for communicating with the GsMaster
@@ -71,8 +83,16 @@ async fn run_fsm(
     event_sender: EventSender,
     rearm_sdc_pin: Output<'static>,
     sdc_pin: Output<'static>,
+    history: &'static FsmHistory,
 ) -> ! {
-    let mut fsm = FSM::new(event_receiver, event_sender, rearm_sdc_pin, sdc_pin).await;
+    let mut fsm = FSM::new(
+        event_receiver,
+        event_sender,
+        SdcPins::new(rearm_sdc_pin, sdc_pin),
+        EmbassyClock,
+        history,
+    )
+    .await;
     fsm.run().await;
 }
 
@@ -133,10 +153,27 @@ async fn main(spawner: Spawner) -> ! {
 
     info!("Embassy initialized!");
 
+    // CAN1 is the CAN-FD bus, on FDCAN2. Its default pins (PB12, PB13) are used by
+    // ethernet, so it's on PB5 (RX) and PB6 (TX) instead.
+    let can1 = {
+        let mut configurator = can::CanConfigurator::new(p.FDCAN2, p.PB5, p.PB6, Irqs);
+
+        configurator.set_config(
+            configurator
+                .config()
+                .set_frame_transmit(can::config::FrameTransmissionConfig::AllowFdCanAndBRS),
+        );
+        configurator.set_bitrate(lib::config::CAN1_BITRATE);
+        configurator.set_fd_data_bitrate(lib::config::CAN1_DATA_BITRATE, true);
+        let can = configurator.into_normal_mode();
+
+        can1::CanInterface::new(can, spawner)
+    };
+
     let can2 = {
         let mut configurator = can::CanConfigurator::new(p.FDCAN1, p.PB8, p.PB9, Irqs);
 
-        configurator.set_bitrate(1_000_000);
+        configurator.set_bitrate(lib::config::CAN2_BITRATE);
         let can = configurator.into_normal_mode();
 
         can2::CanInterface::new(can, spawner)
@@ -161,13 +198,16 @@ async fn main(spawner: Spawner) -> ! {
     // Send events from the FSM to the task that translates them to gs datapoints or
     // CAN commands
     let event_channel_out_fsm = EVENT_CHANNEL_OUT.init(EventChannel::new());
+    // Transitions recorded by the FSM, dumped to the ground station on request
+    let fsm_history: &'static FsmHistory = FSM_HISTORY.init(FsmHistory::new());
 
     // launch the task for the embassy executor to take over
     unwrap!(spawner.spawn(run_fsm(
-        event_channel_in_fsm.receiver().into(),
-        event_channel_out_fsm.sender().into(),
+        event_channel_in_fsm.receiver(),
+        event_channel_out_fsm.sender(),
         rearm_sdc_pin,
         sdc_pin,
+        fsm_history,
     )));
 
     info!("FSM started!");
@@ -204,7 +244,7 @@ async fn main(spawner: Spawner) -> ! {
             gs_tx_receiver,
             gs_rx_transmitter,
             gs_tx_transmitter,
-            event_channel_in_fsm.sender().into(),
+            event_channel_in_fsm.sender(),
         )
         .await,
     );
@@ -216,29 +256,55 @@ async fn main(spawner: Spawner) -> ! {
 
     unwrap!(spawner.spawn(run_gs_master(gs_master, signal)));
 
-    unwrap!(spawner.spawn(forward_can_datapoints(
+    unwrap!(spawner.spawn(forward_can1_datapoints(
+        gs_comms.tx_publisher(),
+        event_channel_in_fsm.sender(),
+        can1.new_subscriber(),
+        can1.health(),
+    )));
+    unwrap!(spawner.spawn(forward_can2_datapoints(
         gs_comms.tx_publisher(),
-        event_channel_in_fsm.sender().into(),
+        event_channel_in_fsm.sender(),
         can2.new_subscriber(),
+        can2.health(),
     )));
     unwrap!(spawner.spawn(forward_fsm_events(
         gs_comms.tx_publisher(),
         can2.new_sender(),
-        event_channel_out_fsm.receiver().into(),
+        event_channel_out_fsm.receiver(),
+        fsm_history,
     )));
     unwrap!(spawner.spawn(forward_gs_commands(
         gs_comms.rx_receiver(),
-        event_channel_in_fsm.sender().into(),
+        event_channel_in_fsm.sender(),
+        can1.new_sender(),
         can2.new_sender(),
     )));
 
     unwrap!(spawner.spawn(gs_heartbeat(gs_comms.tx_publisher())));
 
+    unwrap!(spawner.spawn(report_can_health(
+        gs_comms.tx_publisher(),
+        event_channel_in_fsm.sender(),
+        can1,
+        can2,
+        event_channel_in_fsm,
+        event_channel_out_fsm,
+    )));
+    unwrap!(spawner.spawn(report_undelivered_frames(
+        gs_comms.tx_publisher(),
+        can1,
+        can2,
+    )));
+    unwrap!(spawner.spawn(report_command_acks(gs_comms.tx_publisher(), can1, can2)));
+
     unwrap!(spawner.spawn(check_critical_datapoints(
+        can1.new_subscriber(),
         can2.new_subscriber(),
-        event_channel_in_fsm.sender().into(),
+        event_channel_in_fsm.sender(),
         gs_comms.tx_publisher(),
-        signal
+        signal,
+        fsm_history,
     )));
 
     // unwrap!(spawner.spawn(log_can2_on_gs(
@@ -52,6 +52,7 @@ export const inStateCharging: Writable<boolean> = writable(false);
 export const inStateBraking: Writable<boolean> = writable(false);
 
 export const overrideDependencies: Writable<boolean> = writable(false);
+export const testModeActive: Writable<boolean> = writable(false);
 export const usingTestTrack: Writable<boolean> = writable(true);
 
 export const leftMotorTempsAcknowledged: Writable<boolean> = writable(true);
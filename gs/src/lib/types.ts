@@ -48,7 +48,8 @@ export type NamedCommand =
     | 'FailProp1SystemCheck'
     | 'FailProp2SystemCheck'
     | 'ReconnectEmergency'
-    | 'OverrideRearmSdc';
+    | 'OverrideRearmSdc'
+    | 'DumpFsmHistory';
 export const NamedCommandValues: NamedCommand[] = [
     'SendHashes',
     'LeviDropdown',
@@ -97,6 +98,7 @@ export const NamedCommandValues: NamedCommand[] = [
     'FailProp2SystemCheck',
     'ReconnectEmergency',
     'OverrideRearmSdc',
+    'DumpFsmHistory',
 ];
 
 export type NamedDatatype =
@@ -243,6 +245,7 @@ export type NamedDatatype =
     | 'SensorHubHeartbeat'
     | 'FrontendHeartbeating'
     | 'FSMState'
+    | 'FSMStateTimeout'
     | 'FSMTransitionFail'
     | 'Emergency'
     | 'LeviSystemCheckSuccess'
@@ -253,7 +256,27 @@ export type NamedDatatype =
     | 'Prop2SystemCheckFailure'
     | 'ResetFSM'
     | 'EmergencyStaleCriticalData'
-    | 'LocalizationLimitReached';
+    | 'LocalizationLimitReached'
+    | 'FsmHistoryLength'
+    | 'FsmHistoryEntry'
+    | 'Can1BusState'
+    | 'Can1RxErrors'
+    | 'Can1TxDropped'
+    | 'Can1RxLagged'
+    | 'Can1RxCount'
+    | 'Can2BusState'
+    | 'Can2RxErrors'
+    | 'Can2TxDropped'
+    | 'Can2RxLagged'
+    | 'Can2RxCount'
+    | 'Can1TxUndelivered'
+    | 'Can2TxUndelivered'
+    | 'CommandAcked'
+    | 'CommandTimedOut'
+    | 'GsCommandAccepted'
+    | 'GsCommandRejected'
+    | 'FsmEventsInDropped'
+    | 'FsmEventsOutDropped';
 
 export const NamedDatatypeValues = [
     'TempMotorLeft0',
@@ -399,6 +422,7 @@ export const NamedDatatypeValues = [
     'SensorHubHeartbeat',
     'FrontendHeartbeating',
     'FSMState',
+    'FSMStateTimeout',
     'FSMTransitionFail',
     'Emergency',
     'LeviSystemCheckSuccess',
@@ -410,6 +434,26 @@ export const NamedDatatypeValues = [
     'ResetFSM',
     'EmergencyStaleCriticalData',
     'LocalizationLimitReached',
+    'FsmHistoryLength',
+    'FsmHistoryEntry',
+    'Can1BusState',
+    'Can1RxErrors',
+    'Can1TxDropped',
+    'Can1RxLagged',
+    'Can1RxCount',
+    'Can2BusState',
+    'Can2RxErrors',
+    'Can2TxDropped',
+    'Can2RxLagged',
+    'Can2RxCount',
+    'Can1TxUndelivered',
+    'Can2TxUndelivered',
+    'CommandAcked',
+    'CommandTimedOut',
+    'GsCommandAccepted',
+    'GsCommandRejected',
+    'FsmEventsInDropped',
+    'FsmEventsOutDropped',
 ];
 /* END AUTO GENERATED TYPES */
 
@@ -324,8 +324,10 @@ export function registerSubscribers() {
             emergencyModalActive.set(true);
 
             // Stale critical data emergency should be handled in a different modal to
-            // also show the datapoint that cause the emergency. Therefore, it is missing
-            // from here. (Hint: check above)
+            // also show the datapoint that cause the emergency. Its entry here only keeps
+            // the indices in line with `EmergencyType`. (Hint: check above)
+            // The `emergency_sources_match_pod` test in the station checks
+            // that there is one entry per `EmergencyType`.
             const sources: string[] = [
                 'General',
                 'Propulsion',
@@ -336,6 +338,11 @@ export function registerSubscribers() {
                 'SensorHub',
                 'Disconnection',
                 'Wrong EBS State',
+                'Stale Critical Data',
+                'Frontend Heartbeat Lost',
+                'CAN Bus Off',
+                'Value Out Of Bounds',
+                'State Timeout',
             ];
 
             addEmergencySource(sources[store.value - 1]);
@@ -9,7 +9,9 @@ use std::path::PathBuf;
 use anyhow::Result;
 use goose_utils::commands::generate_commands_from_config;
 use goose_utils::datatypes::generate_data_types_from_config;
+use goose_utils::events::generate_pod_event_names;
 use goose_utils::fmt::run_fmt;
+use goose_utils::fsm_states::generate_state_timeout_infos;
 use goose_utils::fsm_states::FSMState;
 use goose_utils::hash_config;
 use serde::Deserialize;
@@ -47,6 +49,7 @@ struct GS {
 
 pub const CONFIG_PATH: &str = "../../config/config.toml";
 pub const DATAFLOW_PATH: &str = "../../config/dataflow.yaml";
+pub const POD_EVENTS_PATH: &str = "../../crates/lib/src/utils/data.rs";
 
 fn main() -> Result<()> {
     tauri_build::build();
@@ -63,14 +66,16 @@ fn main() -> Result<()> {
 
     content.push_str(&configure_gs(&config));
     content.push_str(&configure_gs_ips(&config.gs.ips, config.gs.port));
-    let df = fs::read_to_string(DATAFLOW_PATH)?;
-    let df = goose_utils::dataflow::parse_from(&df);
+    let df = goose_utils::dataflow::validate::load(DATAFLOW_PATH, POD_EVENTS_PATH)?;
     let dt = goose_utils::dataflow::collect_data_types(&df);
     let dt = generate_data_types_from_config(&dt, true)?;
     content.push_str(&dt);
     let commands = goose_utils::dataflow::collect_commands(&df);
     content.push_str(&generate_commands_from_config(&commands, false));
+    content.push_str(&goose_utils::dataflow::payloads::make_payload_code(&df));
     content.push_str(&generate_fsm_states(&config));
+    content.push_str(&generate_state_timeout_infos(&config.FSMState));
+    content.push_str(&generate_pod_event_names(POD_EVENTS_PATH)?);
     content.push_str(&configure_channels(&config));
     content.push_str(&goose_utils::info::generate_info(CONFIG_PATH, true)?);
     content.push_str(&goose_utils::dataflow::gs::make_gs_code(&df));
@@ -93,6 +98,7 @@ fn main() -> Result<()> {
 
     println!("cargo::rerun-if-changed={CONFIG_PATH}");
     println!("cargo::rerun-if-changed={DATAFLOW_PATH}");
+    println!("cargo::rerun-if-changed={POD_EVENTS_PATH}");
     println!("cargo::rerun-if-changed=build.rs");
     println!("cargo::rerun-if-changed=../../util");
 
@@ -1,8 +1,10 @@
 use std::path::PathBuf;
+use std::time::Duration;
 use std::time::Instant;
 
 use anyhow::anyhow;
 use gslib::Command;
+use gslib::CommandSafety;
 use gslib::Log;
 use gslib::Message;
 use gslib::ProcessedData;
@@ -15,6 +17,9 @@ use crate::CommandSender;
 use crate::MessageReceiver;
 use crate::MessageSender;
 
+/// How long an armed command waits for its confirmation
+pub const ARM_TIMEOUT: Duration = Duration::from_secs(10);
+
 pub struct Backend {
     pub server_handle: Option<AbortHandle>,
     pub message_transmitter: MessageSender,
@@ -24,6 +29,10 @@ pub struct Backend {
     pub _processed_data_receiver: DataReceiver,
     pub log: Log,
     pub should_log: bool,
+    /// Whether test-only commands can be sent
+    pub test_mode: bool,
+    /// The command armed to be confirmed, and when it was armed
+    armed: Option<(Command, Instant)>,
 }
 
 impl Default for Backend {
@@ -52,6 +61,8 @@ impl Backend {
             _processed_data_receiver,
             log: Log::now(),
             should_log: false,
+            test_mode: false,
+            armed: None,
         }
     }
 
@@ -75,7 +86,35 @@ impl Backend {
         }
     }
 
+    /// Arms `cmd`, so that sending it within [`ARM_TIMEOUT`] confirms it.
+    /// Arming another command disarms the previous one.
+    pub fn arm(&mut self, cmd: Command) {
+        self.info(format!("Armed {cmd:?}, send it again to confirm"));
+        self.armed = Some((cmd, Instant::now()));
+    }
+
+    /// Whether `cmd` can be sent: test-only commands need test mode, and
+    /// commands that require confirmation need to be armed first. Sending a
+    /// confirmed command uses up its arming.
+    fn authorize(&mut self, cmd: &Command) -> Result<(), String> {
+        match cmd.safety() {
+            CommandSafety::Normal => Ok(()),
+            CommandSafety::TestOnly if self.test_mode => Ok(()),
+            CommandSafety::TestOnly => {
+                Err(format!("Refusing test-only command {cmd:?} outside of test mode"))
+            },
+            CommandSafety::RequiresConfirmation => match self.armed.take() {
+                Some((armed, at)) if armed == *cmd && at.elapsed() <= ARM_TIMEOUT => Ok(()),
+                _ => Err(format!("Refusing command {cmd:?}, it has to be armed first")),
+            },
+        }
+    }
+
     pub fn send_command(&mut self, cmd: Command) -> bool {
+        if let Err(reason) = self.authorize(&cmd) {
+            self.warn(reason);
+            return false;
+        }
         // self.info(format!("[TRACE] enqueuing command {:?}", cmd));
         #[cfg(all(feature = "gui", not(feature = "tui")))]
         if cmd != Command::FrontendHeartbeat(0) && cmd != Command::Heartbeat(0) {
@@ -0,0 +1,108 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+use gslib::Command;
+use gslib::CommandRejection;
+use gslib::Message;
+
+/// How long the pod has to accept a command before it is sent again
+pub const ACK_TIMEOUT: Duration = Duration::from_millis(500);
+/// How many times a command is sent again before giving up on it
+pub const MAX_RESENDS: u32 = 3;
+
+/// The tracker shared by the tasks that write to and read from the pod
+pub type SharedCommandTracker = Arc<Mutex<CommandTracker>>;
+
+#[derive(Debug)]
+struct PendingCommand {
+    command: Command,
+    frame: [u8; 20],
+    sent_at: Instant,
+    resends: u32,
+}
+
+/// Numbers the commands sent to the pod over a connection, and keeps the ones
+/// the pod hasn't answered yet with a `GsCommandAccepted` or
+/// `GsCommandRejected` datapoint, to send them again when it doesn't in time.
+/// Heartbeats get sequence number 0 and aren't tracked, since the pod doesn't
+/// answer them.
+#[derive(Debug, Default)]
+pub struct CommandTracker {
+    last_seq: u32,
+    pending: BTreeMap<u32, PendingCommand>,
+}
+
+impl CommandTracker {
+    /// The frame of `command` with the next sequence number, tracked from
+    /// `now`, or with sequence number 0 if it is a heartbeat.
+    pub fn frame(&mut self, command: Command, now: Instant) -> [u8; 20] {
+        if command.is_heartbeat() {
+            return command.as_frame(0);
+        }
+        self.last_seq += 1;
+        let frame = command.as_frame(self.last_seq);
+        self.pending
+            .insert(self.last_seq, PendingCommand { command, frame, sent_at: now, resends: 0 });
+        frame
+    }
+
+    /// Marks command `seq` as delivered, with the message to show for it.
+    pub fn accepted(&mut self, seq: u32) -> Option<Message> {
+        let pending = self.pending.remove(&seq)?;
+        Some(Message::Info(format!("{} delivered", pending.command.to_str())))
+    }
+
+    /// Handles a rejection by the pod, where `value` has the reason above the
+    /// sequence number, with the message to show for it. Corrupt frames stay
+    /// pending to be sent again, commands rejected for any other reason are
+    /// given up on.
+    pub fn rejected(&mut self, value: u64) -> Option<Message> {
+        let seq = value as u32;
+        let reason = CommandRejection::from_id((value >> 32) as u8);
+        if reason == Some(CommandRejection::Corrupt) {
+            return Some(Message::Warning(match self.pending.get(&seq) {
+                Some(pending) => {
+                    format!("{} arrived corrupt, sending it again", pending.command.to_str())
+                },
+                None => "The pod received a corrupt command frame".to_string(),
+            }));
+        }
+        let pending = self.pending.remove(&seq)?;
+        Some(Message::Error(format!(
+            "{} rejected by the pod: {reason:?}",
+            pending.command.to_str()
+        )))
+    }
+
+    /// The frames of the commands that weren't answered within [`ACK_TIMEOUT`]
+    /// by `now`, to send again, and the messages about the commands that were
+    /// given up on after [`MAX_RESENDS`] resends.
+    pub fn resends(&mut self, now: Instant) -> (Vec<[u8; 20]>, Vec<Message>) {
+        let mut frames = vec![];
+        let mut messages = vec![];
+        self.pending.retain(|_, pending| {
+            if now.duration_since(pending.sent_at) < ACK_TIMEOUT {
+                return true;
+            }
+            if pending.resends == MAX_RESENDS {
+                messages.push(Message::Error(format!(
+                    "{} was not delivered to the pod",
+                    pending.command.to_str()
+                )));
+                return false;
+            }
+            pending.resends += 1;
+            pending.sent_at = now;
+            frames.push(pending.frame);
+            true
+        });
+        (frames, messages)
+    }
+}
+
+#[cfg(test)]
+#[path = "../tests/command_tracker.rs"]
+mod tests;
@@ -0,0 +1,60 @@
+use gslib::States;
+use gslib::POD_EMERGENCY_NAMES;
+use gslib::POD_EVENT_NAMES;
+
+/// Reassembles the FSM history the pod sends after a `DumpFsmHistory` command:
+/// a `FsmHistoryLength` datapoint followed by that many `FsmHistoryEntry`
+/// datapoints, oldest first.
+#[derive(Debug, Default)]
+pub struct FsmHistory {
+    expected: Option<usize>,
+    entries: Vec<u64>,
+}
+
+impl FsmHistory {
+    /// Starts a new dump of `len` entries, dropping any unfinished one.
+    /// Returns the timeline right away if the history is empty.
+    pub fn start(&mut self, len: u64) -> Option<Vec<String>> {
+        self.expected = Some(len as usize);
+        self.entries.clear();
+        self.timeline_if_complete()
+    }
+
+    /// Adds an entry to the dump. Returns the timeline once all entries arrived.
+    pub fn push(&mut self, entry: u64) -> Option<Vec<String>> {
+        self.expected?;
+        self.entries.push(entry);
+        self.timeline_if_complete()
+    }
+
+    fn timeline_if_complete(&mut self) -> Option<Vec<String>> {
+        if self.expected != Some(self.entries.len()) {
+            return None;
+        }
+        self.expected = None;
+
+        let mut timeline = vec![format!("FSM history ({} transitions):", self.entries.len())];
+        timeline.extend(self.entries.drain(..).map(describe));
+        Some(timeline)
+    }
+}
+
+/// Unpacks an entry in the layout of `TransitionRecord::to_value` on the pod.
+fn describe(entry: u64) -> String {
+    let from = States::from_index(entry as u8);
+    let to = States::from_index((entry >> 8) as u8);
+    let payload = (entry >> 24) as u8;
+    let millis = entry >> 32;
+
+    let event = POD_EVENT_NAMES.get((entry >> 16) as u8 as usize).copied().unwrap_or("Unknown");
+    let cause = match event {
+        "Emergency" => format!(
+            "Emergency ({})",
+            POD_EMERGENCY_NAMES.get(payload as usize).copied().unwrap_or("Unknown")
+        ),
+        "StateTimeout" => format!("StateTimeout ({:?})", States::from_index(payload)),
+        _ => event.to_string(),
+    };
+
+    format!("  [{:>9.3}s] {from:?} -> {to:?} on {cause}", millis as f64 / 1000.0)
+}
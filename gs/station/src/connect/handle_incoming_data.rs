@@ -1,18 +1,24 @@
 #![allow(clippy::single_match)]
 
+use gslib::Command;
 use gslib::Datapoint;
 use gslib::Datatype;
 use gslib::Info;
 use gslib::Message;
+use gslib::States;
 use gslib::COMMAND_HASH;
 use gslib::CONFIG_HASH;
 use gslib::DATA_HASH;
 
+use crate::connect::command_tracker::SharedCommandTracker;
+use crate::connect::fsm_history::FsmHistory;
 use crate::data::process::process;
 use crate::MessageSender;
 
 pub async fn handle_incoming_data(
     data: Datapoint,
+    fsm_history: &mut FsmHistory,
+    tracker: &SharedCommandTracker,
     msg_sender: MessageSender,
 ) -> anyhow::Result<()> {
     msg_sender.send(Message::Data(process(&data)))?;
@@ -60,6 +66,64 @@ pub async fn handle_incoming_data(
         Datatype::Prop2SystemCheckFailure => {
             msg_sender.send(Message::Error("Prop 2 System Check Failure".to_string()))?;
         },
+        Datatype::FSMStateTimeout => {
+            let state = States::from_index(data.value as u8);
+            msg_sender.send(Message::Warning(format!("{state:?} timed out")))?;
+            if let Some(info) = state.timeout_info() {
+                msg_sender.send(Message::Status(info))?;
+            }
+        },
+        Datatype::FsmHistoryLength => {
+            if let Some(timeline) = fsm_history.start(data.value) {
+                timeline.into_iter().try_for_each(|l| msg_sender.send(Message::Info(l)))?;
+            }
+        },
+        Datatype::FsmHistoryEntry => {
+            if let Some(timeline) = fsm_history.push(data.value) {
+                timeline.into_iter().try_for_each(|l| msg_sender.send(Message::Info(l)))?;
+            }
+        },
+        Datatype::Can1TxUndelivered | Datatype::Can2TxUndelivered => {
+            let bus = if data.datatype == Datatype::Can1TxUndelivered { "CAN1" } else { "CAN2" };
+            msg_sender.send(Message::Error(format!(
+                "Frame with id {:#x} could not be delivered on {bus}",
+                data.value
+            )))?;
+        },
+        Datatype::CommandAcked => {
+            let command = Command::from_id((data.value & 0xFFFF) as u16, 0);
+            msg_sender.send(Message::Info(format!(
+                "{} acknowledged by {:#x}",
+                command.to_str(),
+                data.value >> 16
+            )))?;
+        },
+        Datatype::CommandTimedOut => {
+            let command = Command::from_id((data.value & 0xFFFF) as u16, 0);
+            msg_sender.send(Message::Warning(format!(
+                "{} was not acknowledged by {:#x} in time",
+                command.to_str(),
+                data.value >> 16
+            )))?;
+        },
+        Datatype::GsCommandAccepted => {
+            let message = tracker.lock().unwrap().accepted(data.value as u32);
+            if let Some(message) = message {
+                msg_sender.send(message)?;
+            }
+        },
+        Datatype::GsCommandRejected => {
+            let message = tracker.lock().unwrap().rejected(data.value);
+            if let Some(message) = message {
+                msg_sender.send(message)?;
+            }
+        },
+        Datatype::ValueCausedBraking => {
+            let datatype = Datatype::from_id(data.value as u16);
+            msg_sender.send(Message::Error(format!(
+                "{datatype:?} crossed its brake limit! The pod is braking!"
+            )))?;
+        },
         Datatype::LocalizationLimitReached => {
             msg_sender.send(Message::Error(
                 "Localization limit reached! Transitioning to the braking state!".to_string(),
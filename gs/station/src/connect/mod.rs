@@ -1,3 +1,5 @@
+mod command_tracker;
+mod fsm_history;
 mod handle_incoming_data;
 mod queueing;
 mod tcp_reader;
@@ -12,6 +14,7 @@ use tokio::net::TcpListener;
 use tokio::net::TcpStream;
 use tokio::task::JoinHandle;
 
+use crate::connect::command_tracker::SharedCommandTracker;
 use crate::connect::tcp_reader::get_messages_from_tcp;
 use crate::connect::tcp_writer::transmit_commands_to_tcp;
 use crate::CommandReceiver;
@@ -47,9 +50,11 @@ async fn process_stream(
     command_receiver: CommandReceiver,
 ) -> Result<(JoinHandle<()>, JoinHandle<()>)> {
     let (reader, writer) = socket.into_split();
+    let tracker = SharedCommandTracker::default();
     let transmit = message_transmitter.clone();
+    let reader_tracker = tracker.clone();
     let a = tokio::spawn(async move {
-        match get_messages_from_tcp(reader, transmit.clone()).await {
+        match get_messages_from_tcp(reader, transmit.clone(), reader_tracker).await {
             Ok(_) => {
                 transmit
                     .send(Message::Warning(
@@ -68,7 +73,7 @@ async fn process_stream(
     });
     let transmit = message_transmitter.clone();
     let b = tokio::spawn(async move {
-        match transmit_commands_to_tcp(command_receiver, transmit.clone(), writer).await {
+        match transmit_commands_to_tcp(command_receiver, transmit.clone(), writer, tracker).await {
             Ok(_) => {
                 transmit
                     .send(Message::Warning(
@@ -2,6 +2,8 @@ use std::collections::VecDeque;
 
 use gslib::Datapoint;
 
+use crate::connect::command_tracker::SharedCommandTracker;
+use crate::connect::fsm_history::FsmHistory;
 use crate::connect::handle_incoming_data::handle_incoming_data;
 use crate::MessageSender;
 
@@ -16,6 +18,8 @@ use crate::MessageSender;
 /// ```
 pub async fn parse(
     parsing_buffer: &mut VecDeque<u8>,
+    fsm_history: &mut FsmHistory,
+    tracker: &SharedCommandTracker,
     msg_sender: MessageSender,
 ) -> anyhow::Result<()> {
     while let Some(p) = parsing_buffer.front() {
@@ -29,7 +33,13 @@ pub async fn parse(
                 // x.reverse();
                 // tx.send(Message::Info(format!("[TRACE] received: {:?}", x))).unwrap();
                 //msg_sender.send(Message::Data(Datapoint::from_bytes(&x)))?;
-                handle_incoming_data(Datapoint::from_bytes(&x), msg_sender.clone()).await?;
+                handle_incoming_data(
+                    Datapoint::from_bytes(&x),
+                    fsm_history,
+                    tracker,
+                    msg_sender.clone(),
+                )
+                .await?;
             }
         } else {
             parsing_buffer.pop_front();
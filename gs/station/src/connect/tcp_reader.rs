@@ -6,14 +6,18 @@ use gslib::NETWORK_BUFFER_SIZE;
 use tokio::io::AsyncReadExt;
 use tokio::net::tcp::OwnedReadHalf;
 
+use crate::connect::command_tracker::SharedCommandTracker;
+use crate::connect::fsm_history::FsmHistory;
 use crate::MessageSender;
 
 pub async fn get_messages_from_tcp(
     mut reader: OwnedReadHalf,
     message_transmitter: MessageSender,
+    tracker: SharedCommandTracker,
 ) -> anyhow::Result<()> {
     let mut buffer = [0; { NETWORK_BUFFER_SIZE }];
     let mut byte_queue: VecDeque<u8> = VecDeque::new();
+    let mut fsm_history = FsmHistory::default();
     loop {
         match reader.read(&mut buffer).await {
             Ok(0) => {
@@ -28,8 +32,13 @@ pub async fn get_messages_from_tcp(
                 let _ = &buffer[..n].iter().for_each(|x| {
                     byte_queue.push_back(*x);
                 });
-                crate::connect::queueing::parse(&mut byte_queue, message_transmitter.clone())
-                    .await?;
+                crate::connect::queueing::parse(
+                    &mut byte_queue,
+                    &mut fsm_history,
+                    &tracker,
+                    message_transmitter.clone(),
+                )
+                .await?;
             },
             Err(e) => {
                 message_transmitter
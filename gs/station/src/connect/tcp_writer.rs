@@ -1,3 +1,5 @@
+use std::time::Instant;
+
 use gslib::Info;
 use gslib::Message;
 use gslib::Message::Error;
@@ -5,6 +7,7 @@ use gslib::HEARTBEAT;
 use tokio::io::AsyncWriteExt;
 use tokio::net::tcp::OwnedWriteHalf;
 
+use crate::connect::command_tracker::SharedCommandTracker;
 use crate::Command;
 use crate::CommandReceiver;
 use crate::MessageSender;
@@ -13,12 +16,29 @@ pub async fn transmit_commands_to_tcp(
     mut command_receiver: CommandReceiver,
     status_transmitter: MessageSender,
     mut writer: OwnedWriteHalf,
+    tracker: SharedCommandTracker,
 ) -> anyhow::Result<()> {
     let mut last_send_timestamp = std::time::Instant::now();
     loop {
+        // send the commands the pod didn't accept in time again, with the same
+        // sequence number so it can drop them if it did get them
+        let (resends, messages) = tracker.lock().unwrap().resends(Instant::now());
+        for message in messages {
+            status_transmitter.send(message)?;
+        }
+        for frame in resends {
+            if let Err(e) = writer.write_all(&frame).await {
+                status_transmitter
+                    .send(Error(format!("Error resending command over tcp: {e:?}")))
+                    .expect("message channel closed");
+                status_transmitter.send(Message::Status(Info::ConnectionClosedByClient))?;
+                return Ok(());
+            }
+        }
         if last_send_timestamp.elapsed().as_millis() > (HEARTBEAT as u128) {
             last_send_timestamp = std::time::Instant::now();
-            match writer.write_all(&Command::as_bytes(&Command::Heartbeat(42))).await {
+            let frame = tracker.lock().unwrap().frame(Command::Heartbeat(42), Instant::now());
+            match writer.write_all(&frame).await {
                 Ok(_) => {
                     // status_transmitter
                     //     .send(Message::Info(
@@ -47,8 +67,8 @@ pub async fn transmit_commands_to_tcp(
                     writer.shutdown().await.unwrap();
                     break;
                 }
-                let bytes = command.as_bytes();
-                match writer.write_all(&bytes).await {
+                let frame = tracker.lock().unwrap().frame(command, Instant::now());
+                match writer.write_all(&frame).await {
                     Ok(_) => {
                         last_send_timestamp = std::time::Instant::now();
                         // status_transmitter
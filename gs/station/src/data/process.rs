@@ -8,7 +8,7 @@ pub fn process(datapoint: &Datapoint) -> ProcessedData {
     let value = gslib::process_input_datatype(datapoint.datatype, datapoint.value);
     let significant = (value * 1000.0).round() / 1000.0;
 
-    let style = match datapoint.datatype.check_bounds(significant as u64) {
+    let style = match datapoint.datatype.check_bounds(significant) {
         ValueCheckResult::Fine => "".to_string(),
         ValueCheckResult::Warn => "text-yellow-400".to_string(),
         ValueCheckResult::Error => "text-warning-400".to_string(),
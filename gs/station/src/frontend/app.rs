@@ -31,7 +31,10 @@ pub fn tauri_main(backend: Backend) {
         .invoke_handler(tauri::generate_handler![
             unload_buffer,
             send_command,
-            send_command_64_bits,
+            send_command_fields,
+            arm_command,
+            command_safety,
+            set_test_mode,
             generate_test_data,
             connect_to_pod,
             disconnect,
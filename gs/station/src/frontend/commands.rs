@@ -1,6 +1,9 @@
+use std::collections::HashMap;
 use std::path::PathBuf;
 
 use chrono::Local;
+use gslib::command_from_fields;
+use gslib::CommandSafety;
 use gslib::Datapoint;
 use gslib::Datatype;
 use gslib::Limit;
@@ -116,28 +119,64 @@ pub fn send_command(cmd_name: String, val: u64) -> bool {
     }
 }
 
-fn i32_to_u64(x: [i32; 2]) -> u64 {
-    let a = i32::to_be_bytes(x[0]);
-    let b = i32::to_be_bytes(x[1]);
-    u64::from_be_bytes([a[0], a[1], a[2], a[3], b[0], b[1], b[2], b[3]])
+/// Sends a command with payload fields, given by name as a JSON object. Fails
+/// without sending anything if a field doesn't exist or is out of its range.
+#[macro_export]
+#[allow(unused)]
+#[tauri::command]
+pub fn send_command_fields(cmd_name: String, fields: HashMap<String, f64>) -> Result<bool, String> {
+    let c = command_from_fields(&cmd_name, &fields)?;
+    let mut backend_mutex =
+        BACKEND.lock().map_err(|_| format!("Could not send {cmd_name}: the backend is down"))?;
+    let backend = unsafe { backend_mutex.assume_init_mut() };
+    if !matches!(c, Command::FrontendHeartbeat(_) | Command::Heartbeat(_)) {
+        let mut fields = fields.iter().map(|(name, v)| format!("{name}: {v}")).collect::<Vec<_>>();
+        fields.sort();
+        backend.info(format!("Sending command {cmd_name}({})", fields.join(", ")));
+    }
+    Ok(backend.send_command(c))
 }
 
+/// Arms a command that requires confirmation, so that sending it next
+/// confirms it
 #[macro_export]
 #[allow(unused)]
 #[tauri::command]
-pub fn send_command_64_bits(cmd_name: String, vals: [i32; 2]) -> bool {
-    let value = i32_to_u64(vals);
-    if cmd_name != "FrontendHeartbeat" {
-        eprintln!("[frontend] Sending command {}({}) [{}]", cmd_name, value, Local::now());
-    }
-    let c = Command::from_string(&cmd_name, value);
+pub fn arm_command(cmd_name: String, val: u64) -> bool {
+    let c = Command::from_string(&cmd_name, val);
     if let Ok(mut backend_mutex) = BACKEND.lock() {
-        unsafe { backend_mutex.assume_init_mut().send_command(c) }
+        unsafe { backend_mutex.assume_init_mut().arm(c) };
+        true
     } else {
         panic!("kys");
     }
 }
 
+/// The safety class of a command: "normal", "requires-confirmation" or
+/// "test-only"
+#[macro_export]
+#[allow(unused)]
+#[tauri::command]
+pub fn command_safety(cmd_name: String) -> String {
+    match Command::from_string(&cmd_name, 0).safety() {
+        CommandSafety::Normal => "normal",
+        CommandSafety::RequiresConfirmation => "requires-confirmation",
+        CommandSafety::TestOnly => "test-only",
+    }
+    .to_string()
+}
+
+#[macro_export]
+#[allow(unused)]
+#[tauri::command]
+pub fn set_test_mode(value: bool) {
+    if let Ok(mut backend_mutex) = BACKEND.lock() {
+        let backend = unsafe { backend_mutex.assume_init_mut() };
+        backend.test_mode = value;
+        backend.info(format!("Test mode {}", if value { "enabled" } else { "disabled" }));
+    }
+}
+
 #[macro_export]
 #[allow(unused)]
 #[tauri::command]
@@ -49,8 +49,8 @@ pub struct ProcessedData {
     pub timestamp: u64,
     pub style: String,
     pub units: String,
-    pub lower: Option<u64>,
-    pub upper: Option<u64>,
+    pub lower: Option<f64>,
+    pub upper: Option<f64>,
 }
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
@@ -1,6 +1,9 @@
 use std::path::PathBuf;
 use std::str::FromStr;
 
+use gslib::Command;
+use gslib::POD_EMERGENCY_NAMES;
+
 use crate::backend::Backend;
 
 #[test]
@@ -16,3 +19,47 @@ fn import_procedures() {
     assert_eq!(example[4], "Andreas\n");
     assert_eq!(example[5], "<p><input type=\"checkbox\"> 1. I refuse to elaborate.</p>\n<p><input type=\"checkbox\"> 2. :)</p>\n<p><input type=\"checkbox\"> 3. if in trouble just call me</p>\n<p><input type=\"checkbox\"> just text is also fine in a procedure.</p>");
 }
+
+#[test]
+fn test_only_commands_need_test_mode() {
+    let mut backend = Backend::new();
+
+    backend.send_command(Command::MockHVOn(0));
+    assert!(backend.command_receiver.try_recv().is_err());
+
+    backend.test_mode = true;
+    backend.send_command(Command::MockHVOn(0));
+    assert_eq!(backend.command_receiver.try_recv().unwrap(), Command::MockHVOn(0));
+}
+
+#[test]
+fn dangerous_commands_need_arming() {
+    let mut backend = Backend::new();
+
+    backend.send_command(Command::StartHV(0));
+    assert!(backend.command_receiver.try_recv().is_err());
+
+    // arming another value doesn't confirm this one
+    backend.arm(Command::StartHV(1));
+    backend.send_command(Command::StartHV(0));
+    assert!(backend.command_receiver.try_recv().is_err());
+
+    backend.arm(Command::StartHV(0));
+    backend.send_command(Command::StartHV(0));
+    assert_eq!(backend.command_receiver.try_recv().unwrap(), Command::StartHV(0));
+
+    // the arming is used up
+    backend.send_command(Command::StartHV(0));
+    assert!(backend.command_receiver.try_recv().is_err());
+}
+
+#[test]
+fn emergency_sources_match_pod() {
+    // the frontend names emergencies by indexing this array with their
+    // `EmergencyType`, so it needs an entry for every one of them
+    let subscribers = std::fs::read_to_string("../src/lib/util/subscribers.ts").unwrap();
+    let (_, rest) = subscribers.split_once("const sources: string[] = [").unwrap();
+    let (sources, _) = rest.split_once("];").unwrap();
+    let sources = sources.split(',').filter(|s| !s.trim().is_empty()).count();
+    assert_eq!(sources, POD_EMERGENCY_NAMES.len());
+}
@@ -0,0 +1,88 @@
+use std::time::Instant;
+
+use gslib::Command;
+use gslib::CommandRejection;
+use gslib::Message;
+
+use crate::connect::command_tracker::CommandTracker;
+use crate::connect::command_tracker::ACK_TIMEOUT;
+use crate::connect::command_tracker::MAX_RESENDS;
+
+#[test]
+fn frames_are_numbered_in_order() {
+    let mut tracker = CommandTracker::default();
+    let now = Instant::now();
+
+    let first = tracker.frame(Command::StartHV(0), now);
+    let heartbeat = tracker.frame(Command::Heartbeat(42), now);
+    let second = tracker.frame(Command::StopHV(0), now);
+    assert_eq!(Command::from_frame(&first), Ok((1, Command::StartHV(0))));
+    // heartbeats aren't numbered
+    assert_eq!(Command::from_frame(&heartbeat), Ok((0, Command::Heartbeat(42))));
+    assert_eq!(Command::from_frame(&second), Ok((2, Command::StopHV(0))));
+}
+
+#[test]
+fn accepted_commands_are_not_resent() {
+    let mut tracker = CommandTracker::default();
+    let now = Instant::now();
+
+    tracker.frame(Command::StartHV(0), now);
+    assert!(matches!(tracker.accepted(1), Some(Message::Info(_))));
+    assert!(tracker.accepted(1).is_none());
+
+    let (frames, messages) = tracker.resends(now + ACK_TIMEOUT);
+    assert!(frames.is_empty() && messages.is_empty());
+}
+
+#[test]
+fn heartbeats_are_not_tracked() {
+    let mut tracker = CommandTracker::default();
+    let now = Instant::now();
+
+    tracker.frame(Command::Heartbeat(42), now);
+    tracker.frame(Command::FrontendHeartbeat(0), now);
+    assert!(tracker.accepted(1).is_none());
+    assert!(tracker.resends(now + ACK_TIMEOUT).0.is_empty());
+}
+
+#[test]
+fn unanswered_commands_are_resent_then_given_up_on() {
+    let mut tracker = CommandTracker::default();
+    let mut now = Instant::now();
+
+    let frame = tracker.frame(Command::StartHV(0), now);
+    assert!(tracker.resends(now).0.is_empty());
+
+    for _ in 0..MAX_RESENDS {
+        now += ACK_TIMEOUT;
+        let (frames, messages) = tracker.resends(now);
+        // resent with the same sequence number, so the pod can drop duplicates
+        assert_eq!(frames, vec![frame]);
+        assert!(messages.is_empty());
+    }
+
+    now += ACK_TIMEOUT;
+    let (frames, messages) = tracker.resends(now);
+    assert!(frames.is_empty());
+    assert!(matches!(messages[..], [Message::Error(_)]));
+    assert!(tracker.accepted(1).is_none());
+}
+
+#[test]
+fn rejected_commands() {
+    let mut tracker = CommandTracker::default();
+    let now = Instant::now();
+    tracker.frame(Command::StartHV(0), now);
+    tracker.frame(Command::StopHV(0), now);
+
+    // corrupt frames stay pending, to be sent again
+    let corrupt = (CommandRejection::Corrupt as u64) << 32 | 1;
+    assert!(matches!(tracker.rejected(corrupt), Some(Message::Warning(_))));
+    assert_eq!(tracker.resends(now + ACK_TIMEOUT).0.len(), 2);
+
+    let out_of_order = (CommandRejection::OutOfOrder as u64) << 32 | 2;
+    assert!(matches!(tracker.rejected(out_of_order), Some(Message::Error(_))));
+    assert!(tracker.rejected(out_of_order).is_none());
+    assert!(tracker.accepted(2).is_none());
+}
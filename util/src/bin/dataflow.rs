@@ -0,0 +1,192 @@
+//! Looks things up in dataflow.yaml without building anything.
+//!
+//! Run with `cargo run --bin dataflow -- <command>` from `util`.
+
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+use anyhow::bail;
+use goose_utils::dataflow::dbc;
+use goose_utils::dataflow::inspect;
+use goose_utils::dataflow::validate;
+use goose_utils::dataflow::DataflowSpec;
+
+const USAGE: &str = "\
+usage: dataflow [--file <dataflow.yaml>] <command>
+
+commands:
+  lint                          check the dataflow like the build scripts do
+  test                          run the tests of the procedures
+  list datatypes|commands|can-ids
+  show <name>                   everything about a datatype, command or CAN message
+  free-ids [ids|can] [<near>] [<count>]
+                                the free datatype/command ids (default) or CAN2
+                                ids closest to <near>
+  diff <old.yaml> <new.yaml>    what changed between two dataflows, and whether
+                                boards built from them can still talk
+  dbc export                    the CAN messages and commands as a DBC file
+  dbc import <file.dbc>         message-processing entries for the messages of a
+                                DBC file that aren't in the dataflow yet";
+
+fn main() -> anyhow::Result<()> {
+    let project_root =
+        PathBuf::from(std::env!("CARGO_MANIFEST_DIR")).parent().unwrap().to_path_buf();
+    let events_path = project_root.join("crates/lib/src/utils/data.rs");
+    let events_path = events_path.to_str().unwrap();
+
+    let mut args = std::env::args().skip(1).collect::<Vec<_>>();
+    let mut df_path = project_root.join("config/dataflow.yaml").to_str().unwrap().to_string();
+    if args.first().map(String::as_str) == Some("--file") {
+        if args.len() < 2 {
+            usage("--file needs a path");
+        }
+        df_path = args.remove(1);
+        args.remove(0);
+    }
+    let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+
+    match args.as_slice() {
+        [] => usage("no command given"),
+        ["lint"] => {
+            let df = validate::load(&df_path, events_path)?;
+            for warning in validate::warnings(&df) {
+                println!("warning: {} ({})", warning.message, warning.path);
+            }
+            println!("{df_path}: ok");
+        },
+        ["test"] => test(&load(&df_path)?)?,
+        ["list", what] => list(&load(&df_path)?, what)?,
+        ["show", name] => show(&load(&df_path)?, name)?,
+        ["free-ids", rest @ ..] => free_ids(&load(&df_path)?, rest)?,
+        ["diff", old, new] => {
+            let changes = inspect::diff(&load(old)?, &load(new)?);
+            for change in &changes {
+                println!("{change}");
+            }
+            let incompatible = changes.iter().filter(|c| c.wire_incompatible).count();
+            println!("{} change(s), {incompatible} wire-incompatible", changes.len());
+        },
+        ["dbc", "export"] => print!("{}", dbc::export(&load(&df_path)?)),
+        ["dbc", "import", file] => {
+            let source = std::fs::read_to_string(file).map_err(|e| anyhow!("{file}: {e}"))?;
+            let yaml =
+                dbc::import(&load(&df_path)?, &source).map_err(|e| anyhow!("{file}: {e}"))?;
+            print!("{yaml}");
+        },
+        [command, ..] => usage(&format!("unknown command or arguments for `{command}`")),
+    }
+    Ok(())
+}
+
+/// Prints `problem` and the usage, and exits with the status of a wrong
+/// invocation.
+fn usage(problem: &str) -> ! {
+    eprintln!("{problem}\n\n{USAGE}");
+    std::process::exit(2)
+}
+
+fn load(path: &str) -> anyhow::Result<DataflowSpec> {
+    let source = std::fs::read_to_string(path).map_err(|e| anyhow!("{path}: {e}"))?;
+    goose_utils::dataflow::try_parse_from(&source).map_err(|e| anyhow!("{path}: {e}"))
+}
+
+fn test(df: &DataflowSpec) -> anyhow::Result<()> {
+    let mut procedures =
+        df.procedures.iter().filter(|(_, p)| !p.tests.is_empty()).collect::<Vec<_>>();
+    procedures.sort_by_key(|(name, _)| *name);
+
+    let mut failed = 0;
+    for (name, procedure) in procedures {
+        let failures = procedure.run_tests().map_err(|e| anyhow!("formula of {name}: {e}"))?;
+        if failures.is_empty() {
+            println!("ok      {name} ({} test(s))", procedure.tests.len());
+        } else {
+            println!("FAILED  {name}");
+            for failure in &failures {
+                println!("          {failure}");
+            }
+            failed += failures.len();
+        }
+    }
+    if failed > 0 {
+        bail!("{failed} test(s) failed");
+    }
+    Ok(())
+}
+
+fn list(df: &DataflowSpec, what: &str) -> anyhow::Result<()> {
+    match what {
+        "datatypes" => {
+            for d in inspect::datapoints(df) {
+                println!("{:#05x}  {}", d.datapoint.id, d.datapoint.name);
+            }
+        },
+        "commands" => {
+            for c in &df.commands {
+                println!("{:#05x}  {}", c.id, c.name);
+            }
+        },
+        "can-ids" => {
+            for (bus, id, what) in inspect::can_ids(df) {
+                println!("can{bus} {id:#05x}  {what}");
+            }
+        },
+        _ => usage("can only list datatypes, commands or can-ids"),
+    }
+    Ok(())
+}
+
+fn show(df: &DataflowSpec, name: &str) -> anyhow::Result<()> {
+    let datapoints = inspect::datapoints(df);
+    if let Some(d) = datapoints.iter().find(|d| d.datapoint.name == name) {
+        println!("{}", inspect::describe_datapoint(df, d));
+    } else if let Some(c) = df.commands.iter().find(|c| c.name == name) {
+        println!("{}", inspect::describe_command(df, c));
+    } else if let Some(mp) = df.message_processing.iter().find(|mp| mp.name == name) {
+        // a message is shown as all the datapoints read from it
+        let descriptions = datapoints
+            .iter()
+            .filter(|d| d.source.is_some_and(|(m, _)| std::ptr::eq(m, mp)))
+            .map(|d| inspect::describe_datapoint(df, d))
+            .collect::<Vec<_>>();
+        println!("{}", descriptions.join("\n\n"));
+    } else {
+        bail!("no datatype, command or CAN message is called {name}");
+    }
+    Ok(())
+}
+
+fn free_ids(df: &DataflowSpec, args: &[&str]) -> anyhow::Result<()> {
+    let (used, args) = match args {
+        ["can", rest @ ..] => (
+            inspect::can_ids(df)
+                .into_iter()
+                .filter(|(bus, ..)| *bus == 2)
+                .map(|(_, id, _)| id as u16)
+                .collect(),
+            rest,
+        ),
+        ["ids", rest @ ..] => (inspect::used_ids(df), rest),
+        rest => (inspect::used_ids(df), rest),
+    };
+    let number = |s: &str| {
+        let number = match s.strip_prefix("0x") {
+            Some(hex) => usize::from_str_radix(hex, 16),
+            None => s.parse(),
+        };
+        number.unwrap_or_else(|_| usage(&format!("`{s}` is not a number")))
+    };
+    let (near, count) = match args {
+        [] => (0, 10),
+        [near] => (number(near), 10),
+        [near, count] => (number(near), number(count)),
+        _ => usage("free-ids takes at most <near> and <count>"),
+    };
+    if near >= 1 << 11 {
+        bail!("ids are 11 bits, {near:#x} is too large");
+    }
+    for id in inspect::free_ids(&used, near as u16, count) {
+        println!("{id:#05x}");
+    }
+    Ok(())
+}
@@ -17,6 +17,31 @@ pub struct Config {
 pub struct Command {
     pub name: String,
     pub id: u16,
+    #[serde(default)]
+    pub safety: CommandSafety,
+}
+
+/// How careful the ground station and the pod are with sending a command
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Hash)]
+#[serde(rename_all = "kebab-case")]
+pub enum CommandSafety {
+    #[default]
+    Normal,
+    /// The ground station only sends it after it was armed
+    RequiresConfirmation,
+    /// Only for testing: the ground station only sends it in test mode, and
+    /// the pod only handles it when built with the `test-commands` feature
+    TestOnly,
+}
+
+impl CommandSafety {
+    fn variant(self) -> &'static str {
+        match self {
+            Self::Normal => "Normal",
+            Self::RequiresConfirmation => "RequiresConfirmation",
+            Self::TestOnly => "TestOnly",
+        }
+    }
 }
 
 fn get_command_config(path: &str) -> Result<Config> {
@@ -50,6 +75,7 @@ pub fn generate_commands_from_config(config: &Config, drv: bool) -> String {
     let mut names = String::new();
     let mut name_list = Vec::new();
     let mut to_idx = String::new();
+    let mut safety = String::new();
     for (i, command) in config.Command.iter().enumerate() {
         enum_definitions.push_str(&format!("    {}(u64),\n", command.name));
         match_to_id
@@ -67,6 +93,13 @@ pub fn generate_commands_from_config(config: &Config, drv: bool) -> String {
             &command.name, &command.name
         ));
         to_idx.push_str(&format!("            Command::{}(_) => {i},\n", &command.name));
+        if command.safety != CommandSafety::Normal {
+            safety.push_str(&format!(
+                "            Command::{}(_) => CommandSafety::{},\n",
+                command.name,
+                command.safety.variant()
+            ));
+        }
     }
 
     format!(
@@ -120,6 +153,24 @@ impl Command {{
     pub fn to_str(&self) -> &str {{
         COMMANDS_LIST[self.to_idx()]
     }}
+    pub fn safety(&self) -> CommandSafety {{
+        #[allow(unreachable_patterns)]
+        match *self {{
+{safety}
+            _ => CommandSafety::Normal,
+        }}
+    }}
+}}
+/// How careful the ground station and the pod are with sending a command
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(target_os = \"none\", derive(defmt::Format))]
+pub enum CommandSafety {{
+    Normal,
+    /// The ground station only sends it after it was armed
+    RequiresConfirmation,
+    /// Only for testing: the ground station only sends it in test mode, and
+    /// the pod only handles it when built with the `test-commands` feature
+    TestOnly,
 }}
 pub const COMMAND_IDS: [u16; {}] = [{}];
 pub const COMMANDS_LIST: [&str; {}] = [{}];
@@ -128,4 +179,86 @@ pub const COMMANDS_LIST: [&str; {}] = [{}];
         ids.len(), ids.join(", "), name_list.len(), name_list.join(", ")
     )
     + &format!("\npub const COMMAND_HASH: u64 = {hash};")
+        + FRAME_CODE
+}
+
+/// The framing of the commands the ground station sends to the pod, shared by
+/// both sides
+const FRAME_CODE: &str = r#"
+impl Command {
+    /// The frame of the command with sequence number `seq`, for the link from
+    /// the ground station to the pod:
+    /// ```text
+    /// 0: 0xFF flag byte
+    /// 1-2: ID
+    /// 3-10: Value
+    /// 11-14: Sequence number
+    /// 15-18: CRC-32 of bytes 1 to 14
+    /// 19: 0xFF flag byte
+    /// ```
+    /// Heartbeats have sequence number 0: they aren't numbered, and the pod
+    /// doesn't acknowledge them.
+    pub fn as_frame(&self, seq: u32) -> [u8; 20] {
+        let mut buf = self.as_bytes();
+        buf[11..15].copy_from_slice(&seq.to_be_bytes());
+        let crc = crc32(&buf[1..15]);
+        buf[15..19].copy_from_slice(&crc.to_be_bytes());
+        buf
+    }
+
+    /// The sequence number and command of a frame made by
+    /// [`Command::as_frame`], or [`CommandRejection::Corrupt`] if its flag
+    /// bytes or checksum are wrong
+    pub fn from_frame(buf: &[u8; 20]) -> Result<(u32, Self), CommandRejection> {
+        let crc = u32::from_be_bytes([buf[15], buf[16], buf[17], buf[18]]);
+        if buf[0] != 0xff || buf[19] != 0xff || crc != crc32(&buf[1..15]) {
+            return Err(CommandRejection::Corrupt);
+        }
+        let seq = u32::from_be_bytes([buf[11], buf[12], buf[13], buf[14]]);
+        Ok((seq, Command::from_bytes(buf)))
+    }
+
+    /// Whether the command is a heartbeat, which isn't numbered or acknowledged
+    pub fn is_heartbeat(&self) -> bool {
+        matches!(self, Command::Heartbeat(_) | Command::FrontendHeartbeat(_))
+    }
+}
+
+/// Why the pod rejected a command frame from the ground station
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(target_os = "none", derive(defmt::Format))]
+pub enum CommandRejection {
+    /// The flag bytes or the checksum were wrong
+    Corrupt = 1,
+    /// The sequence number is older than a command the pod already ran,
+    /// without being a resend of one it accepted, or is 0 while the command
+    /// isn't a heartbeat
+    OutOfOrder = 2,
+}
+
+impl CommandRejection {
+    pub fn from_id(id: u8) -> Option<Self> {
+        match id {
+            1 => Some(CommandRejection::Corrupt),
+            2 => Some(CommandRejection::OutOfOrder),
+            _ => None,
+        }
+    }
+}
+
+/// The CRC-32 (IEEE) of `bytes`
+pub const fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    let mut i = 0;
+    while i < bytes.len() {
+        crc ^= bytes[i] as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+            bit += 1;
+        }
+        i += 1;
+    }
+    !crc
 }
+"#;
@@ -0,0 +1,509 @@
+//! Conversion between the CAN messages of a dataflow and DBC files, the CAN
+//! database format the tooling of the other subteams understands.
+//!
+//! The export describes every message the main PCB reads, with a signal per
+//! datapoint, and every command it sends. Procedures that are linear in `x`
+//! become the factor and offset of the signal; other ones are exported as the
+//! raw value with a comment naming the procedure.
+//!
+//! The import goes the other way, into `message-processing` entries (and the
+//! procedures they need) to paste into dataflow.yaml and finish by hand.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use anyhow::anyhow;
+use anyhow::bail;
+
+use crate::dataflow::expr::BinOp;
+use crate::dataflow::expr::Block;
+use crate::dataflow::expr::Expr;
+use crate::dataflow::expr::ExprKind;
+use crate::dataflow::expr::Number;
+use crate::dataflow::expr::UnOp;
+use crate::dataflow::*;
+
+/// The node the main PCB is in the DBC
+const MAIN_PCB: &str = "MainPCB";
+/// The node DBC tools use for a sender or receiver that isn't known
+const UNKNOWN_NODE: &str = "Vector__XXX";
+/// Bit set in the id of a `BO_` for extended (29-bit) CAN ids
+const EXTENDED_ID_FLAG: u32 = 0x8000_0000;
+
+/// A value in a formula, `factor * x + offset`
+#[derive(Debug, Clone, Copy)]
+struct Linear {
+    factor: f64,
+    offset: f64,
+    /// Whether the value is a float, so that dividing it is exact
+    float: bool,
+}
+
+impl Linear {
+    fn constant(value: f64, float: bool) -> Self { Self { factor: 0.0, offset: value, float } }
+
+    fn scale(self, by: f64) -> Self {
+        Self { factor: self.factor * by, offset: self.offset * by, ..self }
+    }
+}
+
+/// The value of `expr` as `factor * x + offset`, if it only adds, subtracts,
+/// multiplies and divides `x` by constants and casts it between numbers
+fn linear(expr: &Expr, input: Ty, vars: &[(String, Linear)]) -> Option<Linear> {
+    Some(match &expr.kind {
+        ExprKind::Literal(l) => match l.value {
+            Number::Int(v) => Linear::constant(v as f64, l.suffix.is_some_and(Ty::is_float)),
+            Number::Float(v) => Linear::constant(v, true),
+        },
+        ExprKind::X => Linear { factor: 1.0, offset: 0.0, float: input.is_float() },
+        ExprKind::Var(name) => vars.iter().rev().find(|(n, _)| n == name)?.1,
+        ExprKind::Unary(UnOp::Neg, value) => linear(value, input, vars)?.scale(-1.0),
+        ExprKind::Block(block) => linear_block(block, input, vars)?,
+        ExprKind::Cast(value, ty) => {
+            let value = linear(value, input, vars)?;
+            match ty {
+                _ if ty.is_float() => Linear { float: true, ..value },
+                // truncating a float is not linear
+                _ if value.float && value.factor != 0.0 => return None,
+                _ => Linear { float: false, ..value },
+            }
+        },
+        ExprKind::Binary(op, lhs, rhs) => {
+            let (l, r) = (linear(lhs, input, vars)?, linear(rhs, input, vars)?);
+            let float = l.float || r.float;
+            match op {
+                BinOp::Add => {
+                    Linear { factor: l.factor + r.factor, offset: l.offset + r.offset, float }
+                },
+                BinOp::Sub => {
+                    Linear { factor: l.factor - r.factor, offset: l.offset - r.offset, float }
+                },
+                // one of the sides has to be a constant
+                BinOp::Mul if r.factor == 0.0 => Linear { float, ..l.scale(r.offset) },
+                BinOp::Mul if l.factor == 0.0 => Linear { float, ..r.scale(l.offset) },
+                // integer division rounds, which a factor can't describe
+                BinOp::Div if r.factor == 0.0 && r.offset != 0.0 && float => {
+                    Linear { float, ..l.scale(1.0 / r.offset) }
+                },
+                _ => return None,
+            }
+        },
+        _ => return None,
+    })
+}
+
+fn linear_block(block: &Block, input: Ty, vars: &[(String, Linear)]) -> Option<Linear> {
+    let mut vars = vars.to_vec();
+    for binding in &block.lets {
+        let mut value = linear(&binding.value, input, &vars)?;
+        value.float |= binding.ty.is_some_and(Ty::is_float);
+        vars.push((binding.name.clone(), value));
+    }
+    linear(&block.value, input, &vars)
+}
+
+/// The factor and offset of procedure `name`, if its output is `factor * x +
+/// offset`
+fn linear_procedure(df: &DataflowSpec, name: &str) -> Option<(f64, f64)> {
+    let procedure = df.procedures.get(name)?;
+    let block = procedure.compile().ok()?;
+    let value = linear_block(&block, procedure.input, &[])?;
+    Some((value.factor, value.offset))
+}
+
+/// The lowest and highest threshold of the limits, the range DBC tools
+/// expect the value to stay in. `0|0` means no range.
+fn limits_range(limits: Option<&LimitsSpec>) -> (f64, f64) {
+    let Some(limits) = limits else { return (0.0, 0.0) };
+    (
+        limits.lower.thresholds().reduce(f64::min).unwrap_or(0.0),
+        limits.upper.thresholds().reduce(f64::max).unwrap_or(0.0),
+    )
+}
+
+/// Start bit, size and byte order/sign (like `@0+`) of a signal read with
+/// `getter`, or `None` if DBC can't describe it
+fn signal_layout(getter: &GetterSpec) -> Option<(usize, usize, &'static str)> {
+    // the bit numbering of `bits` getters is the one of little-endian signals
+    if let Some(bits) = &getter.bits {
+        return Some((bits.start, bits.len(), "@1+"));
+    }
+    let start = getter.can_payload_range.start * 8;
+    let size = getter.ty.ty_size() * 8;
+    if matches!(getter.ty, Ty::U8Arr(n) if n > 8) {
+        return None;
+    }
+    // half-precision floats are exported as their bits, DBC has no type for them
+    let signed = getter.ty.is_signed() && getter.ty.big_endian() != Ty::F16;
+    // big-endian signals start at their most significant bit, which is the
+    // highest bit of their first byte
+    Some(match (getter.ty.is_little_endian(), signed) {
+        (true, false) => (start, size, "@1+"),
+        (true, true) => (start, size, "@1-"),
+        (false, false) => (start + 7, size, "@0+"),
+        (false, true) => (start + 7, size, "@0-"),
+    })
+}
+
+/// Writes the dataflow's CAN messages and commands as a DBC file.
+pub fn export(df: &DataflowSpec) -> String {
+    let mut dbc = String::new();
+    let mut comments = String::new();
+    let mut value_types = String::new();
+
+    writeln!(dbc, "VERSION \"\"\n\nNS_ :\n\tCM_\n\tSIG_VALTYPE_\n\nBS_:\n\nBU_: {MAIN_PCB}\n")
+        .unwrap();
+
+    for mp in &df.message_processing {
+        let (id, size) = match mp.can {
+            // CAN-FD messages are only as long as the data read from them
+            CanSpec::Can1 { id } => (
+                if id > 0x7FF { id | EXTENDED_ID_FLAG } else { id },
+                mp.datapoint_conversion
+                    .iter()
+                    .map(|dpc| dpc.getter.can_payload_range.end)
+                    .max()
+                    .unwrap_or(0),
+            ),
+            CanSpec::Can2 { id, .. } => (id, 8),
+        };
+        writeln!(dbc, "BO_ {id} {}: {size} {UNKNOWN_NODE}", mp.name).unwrap();
+
+        for dpc in &mp.datapoint_conversion {
+            let name = &dpc.datapoint.name;
+            let Some((start, length, layout)) = signal_layout(&dpc.getter) else {
+                writeln!(
+                    comments,
+                    "CM_ BO_ {id} \"{name} is read as {}, which doesn't fit in a signal\";",
+                    dpc.getter.ty
+                )
+                .unwrap();
+                continue;
+            };
+            let proc_name = &dpc.can_conversion.proc_name;
+            let (factor, offset) = linear_procedure(df, proc_name).unwrap_or_else(|| {
+                writeln!(
+                    comments,
+                    "CM_ SG_ {id} {name} \"Raw value, converted with procedure {proc_name} of dataflow.yaml\";"
+                )
+                .unwrap();
+                (1.0, 0.0)
+            });
+            let (min, max) = limits_range(dpc.limits.as_ref());
+            let unit = dpc.display_units.as_deref().unwrap_or("");
+            writeln!(
+                dbc,
+                " SG_ {name} : {start}|{length}{layout} ({factor},{offset}) [{min}|{max}] \"{unit}\" {MAIN_PCB}"
+            )
+            .unwrap();
+            match dpc.getter.ty.big_endian() {
+                Ty::F32 => writeln!(value_types, "SIG_VALTYPE_ {id} {name} : 1;").unwrap(),
+                Ty::F64 => writeln!(value_types, "SIG_VALTYPE_ {id} {name} : 2;").unwrap(),
+                _ => {},
+            }
+        }
+        dbc.push('\n');
+    }
+
+    // the commands the main PCB sends, with the value the GS sent, or its
+    // payload fields, as signals
+    for command in &df.commands {
+        let Some(can) = &command.can else { continue };
+        let id = match can.can {
+            CanSpec::Can1 { id } if id > 0x7FF => id | EXTENDED_ID_FLAG,
+            CanSpec::Can1 { id } | CanSpec::Can2 { id, .. } => id,
+        };
+        let size = 8 - can.trim.0;
+        writeln!(dbc, "BO_ {id} {}: {size} {MAIN_PCB}", command.name).unwrap();
+        match can.conversion.as_deref() {
+            None | Some("default_command_process") if !command.payload.is_empty() => {
+                // the fields, moved to the front by the trimmed bytes
+                for field in &command.payload {
+                    let Some((start, length, layout)) = signal_layout(&field.getter) else {
+                        continue;
+                    };
+                    let (min, max) = field.range.map_or((0.0, 0.0), |[min, max]| (min, max));
+                    writeln!(
+                        dbc,
+                        " SG_ {} : {}|{length}{layout} (1,0) [{min}|{max}] \"{}\" {UNKNOWN_NODE}",
+                        field.name,
+                        start - can.trim.0 * 8,
+                        field.unit.as_deref().unwrap_or("")
+                    )
+                    .unwrap();
+                    match field.getter.ty.big_endian() {
+                        Ty::F32 => {
+                            writeln!(value_types, "SIG_VALTYPE_ {id} {} : 1;", field.name).unwrap()
+                        },
+                        Ty::F64 => {
+                            writeln!(value_types, "SIG_VALTYPE_ {id} {} : 2;", field.name).unwrap()
+                        },
+                        _ => {},
+                    }
+                }
+            },
+            None | Some("default_command_process") if size > 0 => {
+                // the big-endian value without the trimmed leading bytes
+                writeln!(dbc, " SG_ Value : 7|{}@0+ (1,0) [0|0] \"\" {UNKNOWN_NODE}", size * 8)
+                    .unwrap();
+            },
+            None | Some("default_command_process") => {},
+            Some(conversion) => writeln!(
+                comments,
+                "CM_ BO_ {id} \"Payload made with procedure {conversion} of dataflow.yaml\";"
+            )
+            .unwrap(),
+        }
+        dbc.push('\n');
+    }
+
+    dbc.push_str(&comments);
+    dbc.push_str(&value_types);
+    dbc
+}
+
+/// A signal of a DBC message
+#[derive(Debug)]
+struct Signal {
+    name: String,
+    start: usize,
+    size: usize,
+    little_endian: bool,
+    signed: bool,
+    factor: f64,
+    offset: f64,
+    unit: String,
+}
+
+/// A message of a DBC file
+#[derive(Debug)]
+struct Message {
+    id: u32,
+    extended: bool,
+    name: String,
+    signals: Vec<Signal>,
+}
+
+/// Parses a `SG_` line, without the `SG_`.
+fn parse_signal(line: &str) -> anyhow::Result<Signal> {
+    let (name, rest) = line.split_once(':').ok_or_else(|| anyhow!("missing `:`"))?;
+    // multiplexed signals have an `M` or `m<n>` after the name
+    let name = name.split_whitespace().next().ok_or_else(|| anyhow!("missing name"))?;
+    let mut parts = rest.split_whitespace();
+
+    let layout = parts.next().ok_or_else(|| anyhow!("missing layout"))?;
+    let (start, rest) = layout.split_once('|').ok_or_else(|| anyhow!("missing `|`"))?;
+    let (size, rest) = rest.split_once('@').ok_or_else(|| anyhow!("missing `@`"))?;
+    let scaling = parts.next().ok_or_else(|| anyhow!("missing factor and offset"))?;
+    let (factor, offset) = scaling
+        .trim_start_matches('(')
+        .trim_end_matches(')')
+        .split_once(',')
+        .ok_or_else(|| anyhow!("missing `,` in {scaling}"))?;
+    let unit = rest_after(line, '"').and_then(|u| u.split_once('"')).map_or("", |(u, _)| u);
+
+    Ok(Signal {
+        name: name.to_string(),
+        start: start.parse()?,
+        size: size.parse()?,
+        little_endian: rest.starts_with('1'),
+        signed: rest.ends_with('-'),
+        factor: factor.parse()?,
+        offset: offset.parse()?,
+        unit: unit.to_string(),
+    })
+}
+
+fn rest_after(s: &str, c: char) -> Option<&str> { s.split_once(c).map(|(_, rest)| rest) }
+
+/// Parses the messages and signals of a DBC file.
+fn parse_dbc(dbc: &str) -> anyhow::Result<Vec<Message>> {
+    let mut messages: Vec<Message> = vec![];
+    for (n, line) in dbc.lines().enumerate() {
+        let line = line.trim();
+        let context = |e: anyhow::Error| anyhow!("line {}: {e}", n + 1);
+        if let Some(rest) = line.strip_prefix("BO_ ") {
+            let mut parts = rest.split_whitespace();
+            let id: u32 = parts
+                .next()
+                .ok_or_else(|| anyhow!("missing id"))
+                .and_then(|id| Ok(id.parse()?))
+                .map_err(context)?;
+            let name =
+                parts.next().ok_or_else(|| context(anyhow!("missing name")))?.trim_end_matches(':');
+            messages.push(Message {
+                id: id & !EXTENDED_ID_FLAG,
+                extended: id & EXTENDED_ID_FLAG != 0,
+                name: name.to_string(),
+                signals: vec![],
+            });
+        } else if let Some(rest) = line.strip_prefix("SG_ ") {
+            let Some(message) = messages.last_mut() else {
+                bail!("line {}: signal outside of a message", n + 1);
+            };
+            message.signals.push(parse_signal(rest).map_err(context)?);
+        }
+    }
+    Ok(messages)
+}
+
+/// The getter reading `signal`, or why there can't be one
+fn getter_for(signal: &Signal) -> Result<GetterSpec, String> {
+    let Signal { start, size, little_endian, signed, .. } = *signal;
+    // see `signal_layout`
+    let first_bit = if little_endian { start } else { start.wrapping_sub(7) };
+    let ty = match (size, signed, little_endian) {
+        (8, false, false) => Some(Ty::U8),
+        (8, false, true) => Some(Ty::U8LE),
+        (8, true, false) => Some(Ty::I8),
+        (8, true, true) => Some(Ty::I8LE),
+        (16, false, false) => Some(Ty::U16),
+        (16, false, true) => Some(Ty::U16LE),
+        (16, true, false) => Some(Ty::I16),
+        (16, true, true) => Some(Ty::I16LE),
+        (32, false, false) => Some(Ty::U32),
+        (32, false, true) => Some(Ty::U32LE),
+        (32, true, false) => Some(Ty::I32),
+        (32, true, true) => Some(Ty::I32LE),
+        (64, false, false) => Some(Ty::U64),
+        (64, false, true) => Some(Ty::U64LE),
+        (64, true, false) => Some(Ty::I64),
+        (64, true, true) => Some(Ty::I64LE),
+        _ => None,
+    };
+    let getter = match ty {
+        Some(ty) if first_bit % 8 == 0 => {
+            format!("{ty}[{}..{}]", first_bit / 8, (first_bit + size) / 8)
+        },
+        // packed fields, of which `bits` reads the unsigned little-endian ones
+        // and the big-endian ones that don't cross a byte boundary
+        _ if !signed && little_endian => format!("bits[{start}..{}]", start + size),
+        _ if !signed && size <= start % 8 + 1 => {
+            format!("bits[{}..{}]", start + 1 - size, start + 1)
+        },
+        _ => {
+            return Err(format!(
+                "there is no getter for {} {size}-bit {} values starting at bit {start}",
+                if little_endian { "little-endian" } else { "big-endian" },
+                if signed { "signed" } else { "unsigned" },
+            ))
+        },
+    };
+    getter.parse()
+}
+
+fn snake_case(name: &str) -> String {
+    let mut snake = String::new();
+    for (i, c) in name.chars().enumerate() {
+        if c.is_uppercase() && i > 0 {
+            snake.push('_');
+        }
+        snake.push(c.to_ascii_lowercase());
+    }
+    snake
+}
+
+/// Turns the messages of a DBC file that `df` doesn't read yet into
+/// `message-processing` entries, with the procedures they need and free
+/// datatype ids. Signals a getter can't read are left as comments.
+pub fn import(df: &DataflowSpec, dbc: &str) -> anyhow::Result<String> {
+    let messages = parse_dbc(dbc)?;
+    let known = crate::dataflow::inspect::can_ids(df);
+    let signal_count = messages.iter().map(|m| m.signals.len()).sum();
+    let mut free_ids = crate::dataflow::inspect::free_ids(
+        &crate::dataflow::inspect::used_ids(df),
+        0,
+        signal_count,
+    )
+    .into_iter();
+
+    // the procedures of the dataflow that already scale like a signal
+    let mut procedures = df
+        .procedures
+        .iter()
+        .filter_map(|(name, p)| {
+            let (factor, offset) = linear_procedure(df, name)?;
+            Some((
+                (p.input.to_string(), p.output.to_string(), factor.to_bits(), offset.to_bits()),
+                name.clone(),
+            ))
+        })
+        .collect::<BTreeMap<_, _>>();
+    let mut new_procedures = String::new();
+    let mut entries = String::new();
+
+    for message in &messages {
+        let bus = if message.extended || message.id > 0x7FF { 1 } else { 2 };
+        if let Some((_, _, what)) = known.iter().find(|(b, id, _)| *b == bus && *id == message.id) {
+            writeln!(entries, "  # {} ({:#x}) is already the {what}\n", message.name, message.id)
+                .unwrap();
+            continue;
+        }
+        writeln!(entries, "  - name: \"{}\"", message.name).unwrap();
+        writeln!(entries, "    can:\n      id: 0x{:X}\n      bus: can{bus}", message.id).unwrap();
+        writeln!(entries, "    datapoint-conversion:").unwrap();
+
+        for signal in &message.signals {
+            let getter = match getter_for(signal) {
+                Ok(getter) => getter,
+                Err(reason) => {
+                    writeln!(entries, "      # TODO {}: {reason}", signal.name).unwrap();
+                    continue;
+                },
+            };
+            let input = getter.ty.to_string();
+            let scaled = signal.factor != 1.0 || signal.offset != 0.0;
+            let output =
+                if scaled { "f32".to_string() } else { expr::value_type(getter.ty).to_string() };
+            let key =
+                (input.clone(), output.clone(), signal.factor.to_bits(), signal.offset.to_bits());
+            let procedure = procedures
+                .entry(key)
+                .or_insert_with(|| {
+                    let name = if scaled {
+                        format!("decode_{}", snake_case(&signal.name))
+                    } else {
+                        format!("identity_{input}")
+                    };
+                    let formula = if !scaled {
+                        "x".to_string()
+                    } else if signal.offset == 0.0 {
+                        format!("(x as f32) * {:?}", signal.factor as f32)
+                    } else {
+                        format!(
+                            "(x as f32) * {:?} + {:?}",
+                            signal.factor as f32, signal.offset as f32
+                        )
+                    };
+                    writeln!(
+                        new_procedures,
+                        "  {name}:\n    input: \"{input}\"\n    output: \"{output}\"\n    formula: \"{formula}\"\n"
+                    )
+                    .unwrap();
+                    name
+                })
+                .clone();
+            let gs = if output == "f32" {
+                "gs_2p_float:f32".to_string()
+            } else if df.procedures.contains_key(&format!("dump-gs_{output}")) {
+                format!("gs_{output}:{output}")
+            } else {
+                format!("TODO:{output}")
+            };
+            let id = free_ids.next().ok_or_else(|| anyhow!("there are no more free ids"))?;
+
+            writeln!(entries, "      - datapoint:").unwrap();
+            writeln!(entries, "          name: \"{}\"\n          id: 0x{id:03X}", signal.name)
+                .unwrap();
+            writeln!(entries, "        getter: \"{getter}\"").unwrap();
+            writeln!(entries, "        can-conversion: \"{procedure}:{input}->{output}\"").unwrap();
+            if !signal.unit.is_empty() {
+                writeln!(entries, "        display-units: \"{}\"", signal.unit).unwrap();
+            }
+            writeln!(entries, "        gs:\n          conversion: \"{gs}\"").unwrap();
+        }
+        entries.push('\n');
+    }
+
+    Ok(format!("procedures:\n{new_procedures}\nmessage-processing:\n{entries}"))
+}
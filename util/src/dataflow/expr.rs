@@ -0,0 +1,1074 @@
+//! The language the formulas of procedures are written in.
+//!
+//! It is the part of Rust the formulas need: `x`, number literals, arithmetic,
+//! comparisons, bit operations, `as` casts, `if`/`else`, `let` bindings,
+//! `logf` (or `log_32::logf`) and `to_be_bytes`/`to_le_bytes`. Formulas are
+//! parsed and type-checked here, so that mistakes are reported against
+//! dataflow.yaml instead of the generated code. They are then compiled to Rust
+//! for the pod and the ground station, or evaluated on the host to run the
+//! `tests` of the procedures.
+
+use std::fmt::Display;
+use std::fmt::Formatter;
+
+use crate::dataflow::Ty;
+
+/// A problem with a formula, at byte `offset` of it
+#[derive(Debug, Clone)]
+pub struct ExprError {
+    pub offset: usize,
+    pub message: String,
+}
+
+impl ExprError {
+    fn new(offset: usize, message: impl Into<String>) -> Self {
+        Self { offset, message: message.into() }
+    }
+
+    /// Line and column, both starting at 1, of the problem in `formula`
+    pub fn position(&self, formula: &str) -> (usize, usize) {
+        let before = &formula[..self.offset.min(formula.len())];
+        let line = before.matches('\n').count() + 1;
+        let column = before.len() - before.rfind('\n').map_or(0, |i| i + 1) + 1;
+        (line, column)
+    }
+}
+
+impl Display for ExprError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result { write!(f, "{}", self.message) }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnOp {
+    Neg,
+    Not,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    And,
+    Or,
+    Xor,
+    Shl,
+    Shr,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    LogicAnd,
+    LogicOr,
+}
+
+impl BinOp {
+    /// How the operator is written
+    pub fn symbol(self) -> &'static str {
+        match self {
+            BinOp::Add => "+",
+            BinOp::Sub => "-",
+            BinOp::Mul => "*",
+            BinOp::Div => "/",
+            BinOp::Rem => "%",
+            BinOp::And => "&",
+            BinOp::Or => "|",
+            BinOp::Xor => "^",
+            BinOp::Shl => "<<",
+            BinOp::Shr => ">>",
+            BinOp::Eq => "==",
+            BinOp::Ne => "!=",
+            BinOp::Lt => "<",
+            BinOp::Le => "<=",
+            BinOp::Gt => ">",
+            BinOp::Ge => ">=",
+            BinOp::LogicAnd => "&&",
+            BinOp::LogicOr => "||",
+        }
+    }
+
+    /// Binding strength, like in Rust
+    fn precedence(self) -> u8 {
+        match self {
+            BinOp::LogicOr => 1,
+            BinOp::LogicAnd => 2,
+            BinOp::Eq | BinOp::Ne | BinOp::Lt | BinOp::Le | BinOp::Gt | BinOp::Ge => 3,
+            BinOp::Or => 4,
+            BinOp::Xor => 5,
+            BinOp::And => 6,
+            BinOp::Shl | BinOp::Shr => 7,
+            BinOp::Add | BinOp::Sub => 8,
+            BinOp::Mul | BinOp::Div | BinOp::Rem => 9,
+        }
+    }
+
+    fn from_symbol(symbol: &str) -> Option<Self> {
+        Some(match symbol {
+            "+" => BinOp::Add,
+            "-" => BinOp::Sub,
+            "*" => BinOp::Mul,
+            "/" => BinOp::Div,
+            "%" => BinOp::Rem,
+            "&" => BinOp::And,
+            "|" => BinOp::Or,
+            "^" => BinOp::Xor,
+            "<<" => BinOp::Shl,
+            ">>" => BinOp::Shr,
+            "==" => BinOp::Eq,
+            "!=" => BinOp::Ne,
+            "<" => BinOp::Lt,
+            "<=" => BinOp::Le,
+            ">" => BinOp::Gt,
+            ">=" => BinOp::Ge,
+            "&&" => BinOp::LogicAnd,
+            "||" => BinOp::LogicOr,
+            _ => return None,
+        })
+    }
+
+    fn is_comparison(self) -> bool { self.precedence() == 3 }
+}
+
+/// Precedence of casts, above every binary operator
+const CAST_PRECEDENCE: u8 = 10;
+/// Precedence of `-` and `!`
+const UNARY_PRECEDENCE: u8 = 11;
+/// Precedence of literals, variables, calls and parenthesized expressions
+const ATOM_PRECEDENCE: u8 = 12;
+
+/// A number literal, with the text it was written as
+#[derive(Debug, Clone, PartialEq)]
+pub struct Literal {
+    pub text: String,
+    pub value: Number,
+    /// The type of the suffix, like `f32` in `4150f32`
+    pub suffix: Option<Ty>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Number {
+    Int(i128),
+    Float(f64),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExprKind {
+    Literal(Literal),
+    /// The input of the procedure
+    X,
+    Var(String),
+    Unary(UnOp, Box<Expr>),
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+    Cast(Box<Expr>, Ty),
+    If(Box<Expr>, Box<Block>, Box<Block>),
+    Logf(Box<Expr>),
+    /// `to_be_bytes` if the flag is set, `to_le_bytes` otherwise
+    ToBytes(Box<Expr>, bool),
+    Block(Box<Block>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expr {
+    pub kind: ExprKind,
+    /// Byte offset of the expression in the formula
+    pub offset: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Let {
+    pub name: String,
+    pub ty: Option<Ty>,
+    pub value: Expr,
+}
+
+/// `let` bindings followed by the value of the block
+#[derive(Debug, Clone, PartialEq)]
+pub struct Block {
+    pub lets: Vec<Let>,
+    pub value: Expr,
+}
+
+/// Symbols of two characters, which are matched before those of one
+const LONG_SYMBOLS: [&str; 9] = ["==", "!=", "<=", ">=", "<<", ">>", "&&", "||", "::"];
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(String),
+    Ident(String),
+    Symbol(String),
+}
+
+fn tokenize(formula: &str) -> Result<Vec<(Token, usize)>, ExprError> {
+    let mut tokens = vec![];
+    let bytes = formula.as_bytes();
+    let mut i = 0;
+    while i < formula.len() {
+        let c = formula[i..].chars().next().unwrap();
+        let start = i;
+        if c.is_whitespace() {
+            i += c.len_utf8();
+        } else if formula[i..].starts_with("//") {
+            i = formula[i..].find('\n').map_or(formula.len(), |n| i + n);
+        } else if c.is_ascii_digit() {
+            while i < formula.len() {
+                let b = bytes[i];
+                let exponent_sign = (b == b'-' || b == b'+')
+                    && matches!(bytes[i - 1], b'e' | b'E')
+                    && !formula[start..].starts_with("0x");
+                // `1.0` is a number, but `1.max(2)` and `1..2` aren't
+                let point = b == b'.' && bytes.get(i + 1).is_some_and(u8::is_ascii_digit);
+                if b.is_ascii_alphanumeric() || b == b'_' || point || exponent_sign {
+                    i += 1;
+                } else {
+                    break;
+                }
+            }
+            tokens.push((Token::Number(formula[start..i].to_string()), start));
+        } else if c.is_alphabetic() || c == '_' {
+            while i < formula.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'_') {
+                i += 1;
+            }
+            tokens.push((Token::Ident(formula[start..i].to_string()), start));
+        } else {
+            let symbol = LONG_SYMBOLS
+                .iter()
+                .find(|s| formula[i..].starts_with(*s))
+                .map_or_else(|| c.to_string(), |s| s.to_string());
+            if !symbol.starts_with(|c| "+-*/%&|^!<>=(){};:.,".contains(c)) {
+                return Err(ExprError::new(start, format!("unexpected character `{c}`")));
+            }
+            i += symbol.len();
+            tokens.push((Token::Symbol(symbol), start));
+        }
+    }
+    Ok(tokens)
+}
+
+fn parse_literal(text: &str, offset: usize) -> Result<Literal, ExprError> {
+    let invalid = || ExprError::new(offset, format!("invalid number `{text}`"));
+    let digits = text.replace('_', "");
+    let (digits, radix) = match digits.strip_prefix("0x") {
+        Some(hex) => (hex.to_string(), 16),
+        None => match digits.strip_prefix("0b") {
+            Some(bin) => (bin.to_string(), 2),
+            None => (digits, 10),
+        },
+    };
+    // hex digits include `f`, so only integer suffixes can follow them
+    let suffix_start = ["u8", "u16", "u32", "u64", "i8", "i16", "i32", "i64", "f32", "f64"]
+        .iter()
+        .filter(|s| digits.ends_with(*s) && (radix == 10 || !s.starts_with('f')))
+        .map(|s| digits.len() - s.len())
+        .min();
+    let (digits, suffix) = match suffix_start {
+        Some(i) => (&digits[..i], Some(digits[i..].parse::<Ty>().map_err(|_| invalid())?)),
+        None => (digits.as_str(), None),
+    };
+    let float = matches!(suffix, Some(Ty::F32 | Ty::F64))
+        || (radix == 10 && digits.contains(['.', 'e', 'E']));
+    let value = if float {
+        Number::Float(digits.parse().map_err(|_| invalid())?)
+    } else {
+        Number::Int(i128::from_str_radix(digits, radix).map_err(|_| invalid())?)
+    };
+    Ok(Literal { text: text.to_string(), value, suffix })
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    next: usize,
+    /// Length of the formula, where errors at its end are reported
+    end: usize,
+}
+
+impl Parser {
+    fn offset(&self) -> usize { self.tokens.get(self.next).map_or(self.end, |(_, o)| *o) }
+
+    fn peek(&self) -> Option<&Token> { self.tokens.get(self.next).map(|(t, _)| t) }
+
+    fn peek_symbol(&self) -> Option<&str> {
+        match self.peek() {
+            Some(Token::Symbol(s)) => Some(s),
+            _ => None,
+        }
+    }
+
+    fn eat_symbol(&mut self, symbol: &str) -> bool {
+        let eaten = self.peek_symbol() == Some(symbol);
+        if eaten {
+            self.next += 1;
+        }
+        eaten
+    }
+
+    fn eat_ident(&mut self, ident: &str) -> bool {
+        let eaten = matches!(self.peek(), Some(Token::Ident(i)) if i == ident);
+        if eaten {
+            self.next += 1;
+        }
+        eaten
+    }
+
+    fn expect_symbol(&mut self, symbol: &str) -> Result<(), ExprError> {
+        if self.eat_symbol(symbol) {
+            Ok(())
+        } else {
+            Err(ExprError::new(self.offset(), format!("expected `{symbol}`")))
+        }
+    }
+
+    fn ident(&mut self) -> Result<String, ExprError> {
+        match self.peek() {
+            Some(Token::Ident(i)) => {
+                let i = i.clone();
+                self.next += 1;
+                Ok(i)
+            },
+            _ => Err(ExprError::new(self.offset(), "expected a name")),
+        }
+    }
+
+    fn ty(&mut self) -> Result<Ty, ExprError> {
+        let offset = self.offset();
+        let name = self.ident()?;
+        match name.parse::<Ty>() {
+            Ok(ty) if !matches!(ty, Ty::U8Arr(_)) && value_type(ty) == ty => Ok(ty),
+            _ => Err(ExprError::new(offset, format!("`{name}` is not a number type"))),
+        }
+    }
+
+    /// block := ('let' name (':' type)? '=' expr ';')* expr
+    fn block(&mut self) -> Result<Block, ExprError> {
+        let mut lets = vec![];
+        while self.eat_ident("let") {
+            let name = self.ident()?;
+            let ty = if self.eat_symbol(":") { Some(self.ty()?) } else { None };
+            self.expect_symbol("=")?;
+            let value = self.expr(0)?;
+            self.expect_symbol(";")?;
+            lets.push(Let { name, ty, value });
+        }
+        Ok(Block { lets, value: self.expr(0)? })
+    }
+
+    fn braced_block(&mut self) -> Result<Block, ExprError> {
+        self.expect_symbol("{")?;
+        let block = self.block()?;
+        self.expect_symbol("}")?;
+        Ok(block)
+    }
+
+    /// Binary operators binding at least as strongly as `min_precedence`
+    fn expr(&mut self, min_precedence: u8) -> Result<Expr, ExprError> {
+        let mut lhs = self.cast()?;
+        while let Some(op) = self.peek_symbol().and_then(BinOp::from_symbol) {
+            if op.precedence() < min_precedence {
+                break;
+            }
+            let offset = self.offset();
+            self.next += 1;
+            let rhs = self.expr(op.precedence() + 1)?;
+            if op.is_comparison()
+                && matches!(&rhs.kind, ExprKind::Binary(o, ..) if o.is_comparison())
+            {
+                return Err(ExprError::new(offset, "comparisons can't be chained"));
+            }
+            lhs = Expr { kind: ExprKind::Binary(op, Box::new(lhs), Box::new(rhs)), offset };
+            if op.is_comparison()
+                && self.peek_symbol().and_then(BinOp::from_symbol).is_some_and(BinOp::is_comparison)
+            {
+                return Err(ExprError::new(self.offset(), "comparisons can't be chained"));
+            }
+        }
+        Ok(lhs)
+    }
+
+    /// cast := unary ('as' type)*
+    fn cast(&mut self) -> Result<Expr, ExprError> {
+        let mut value = self.unary()?;
+        while self.eat_ident("as") {
+            let offset = value.offset;
+            value = Expr { kind: ExprKind::Cast(Box::new(value), self.ty()?), offset };
+        }
+        Ok(value)
+    }
+
+    /// unary := ('-' | '!') unary | postfix
+    fn unary(&mut self) -> Result<Expr, ExprError> {
+        let offset = self.offset();
+        let op = if self.eat_symbol("-") {
+            UnOp::Neg
+        } else if self.eat_symbol("!") {
+            UnOp::Not
+        } else {
+            return self.postfix();
+        };
+        Ok(Expr { kind: ExprKind::Unary(op, Box::new(self.unary()?)), offset })
+    }
+
+    /// postfix := atom ('.' ('to_be_bytes' | 'to_le_bytes') '(' ')')*
+    fn postfix(&mut self) -> Result<Expr, ExprError> {
+        let mut value = self.atom()?;
+        while self.eat_symbol(".") {
+            let offset = self.offset();
+            let big_endian = match self.ident()?.as_str() {
+                "to_be_bytes" => true,
+                "to_le_bytes" => false,
+                method => return Err(ExprError::new(offset, format!("unknown method `{method}`"))),
+            };
+            self.expect_symbol("(")?;
+            self.expect_symbol(")")?;
+            value = Expr { kind: ExprKind::ToBytes(Box::new(value), big_endian), offset };
+        }
+        Ok(value)
+    }
+
+    /// atom := number | 'x' | name | 'if' ... | logf '(' expr ')' | '(' expr ')'
+    ///       | '{' block '}'
+    fn atom(&mut self) -> Result<Expr, ExprError> {
+        let offset = self.offset();
+        let kind = match self.peek().cloned() {
+            Some(Token::Number(text)) => {
+                self.next += 1;
+                ExprKind::Literal(parse_literal(&text, offset)?)
+            },
+            Some(Token::Symbol(s)) if s == "(" => {
+                self.next += 1;
+                let value = self.expr(0)?;
+                self.expect_symbol(")")?;
+                return Ok(value);
+            },
+            Some(Token::Symbol(s)) if s == "{" => ExprKind::Block(Box::new(self.braced_block()?)),
+            Some(Token::Ident(i)) if i == "if" => {
+                self.next += 1;
+                let condition = self.expr(0)?;
+                let then = self.braced_block()?;
+                if !self.eat_ident("else") {
+                    return Err(ExprError::new(self.offset(), "`if` needs an `else`"));
+                }
+                let otherwise = if matches!(self.peek(), Some(Token::Ident(i)) if i == "if") {
+                    let value = self.atom()?;
+                    Block { lets: vec![], value }
+                } else {
+                    self.braced_block()?
+                };
+                ExprKind::If(Box::new(condition), Box::new(then), Box::new(otherwise))
+            },
+            Some(Token::Ident(i)) => {
+                self.next += 1;
+                if i == "log_32" {
+                    self.expect_symbol("::")?;
+                    if !self.eat_ident("logf") {
+                        return Err(ExprError::new(self.offset(), "expected `logf`"));
+                    }
+                }
+                if i == "log_32" || i == "logf" {
+                    self.expect_symbol("(")?;
+                    let argument = self.expr(0)?;
+                    self.expect_symbol(")")?;
+                    ExprKind::Logf(Box::new(argument))
+                } else if i == "x" {
+                    ExprKind::X
+                } else if self.peek_symbol() == Some("(") || self.peek_symbol() == Some("::") {
+                    return Err(ExprError::new(offset, format!("unknown function `{i}`")));
+                } else {
+                    ExprKind::Var(i)
+                }
+            },
+            Some(Token::Symbol(s)) => {
+                return Err(ExprError::new(offset, format!("unexpected `{s}`")))
+            },
+            None => return Err(ExprError::new(offset, "unexpected end of the formula")),
+        };
+        Ok(Expr { kind, offset })
+    }
+}
+
+/// Parses a formula.
+pub fn parse(formula: &str) -> Result<Block, ExprError> {
+    let mut parser = Parser { tokens: tokenize(formula)?, next: 0, end: formula.len() };
+    let block = parser.block()?;
+    if parser.next < parser.tokens.len() {
+        return Err(ExprError::new(parser.offset(), "expected the end of the formula"));
+    }
+    Ok(block)
+}
+
+/// The type of an expression while checking a formula
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Type {
+    Ty(Ty),
+    /// An integer literal without suffix, whose type comes from where it's used
+    Int,
+    /// A float literal without suffix
+    Float,
+    Bool,
+}
+
+impl Display for Type {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Type::Ty(ty) => write!(f, "{ty}"),
+            Type::Int => write!(f, "integer"),
+            Type::Float => write!(f, "float"),
+            Type::Bool => write!(f, "bool"),
+        }
+    }
+}
+
+impl Type {
+    fn is_integer(self) -> bool {
+        match self {
+            Type::Ty(Ty::U8Arr(_)) => false,
+            Type::Ty(ty) => !ty.is_float(),
+            Type::Int => true,
+            Type::Float | Type::Bool => false,
+        }
+    }
+
+    fn is_number(self) -> bool {
+        match self {
+            Type::Ty(ty) => !matches!(ty, Ty::U8Arr(_)),
+            Type::Int | Type::Float => true,
+            Type::Bool => false,
+        }
+    }
+
+    /// The type both `self` and `other` can be, if there is one
+    fn unify(self, other: Type) -> Option<Type> {
+        match (self, other) {
+            _ if self == other => Some(self),
+            (Type::Int, t) | (t, Type::Int) if t.is_integer() => Some(t),
+            (Type::Float, Type::Ty(ty)) | (Type::Ty(ty), Type::Float) if ty.is_float() => {
+                Some(Type::Ty(ty))
+            },
+            _ => None,
+        }
+    }
+}
+
+/// The type values of `ty` have in formulas: the byte order of a getter
+/// doesn't matter once the value is read, and half-precision floats are read
+/// as `f32`, since there is no `f16` on stable.
+pub fn value_type(ty: Ty) -> Ty {
+    match ty.big_endian() {
+        Ty::F16 => Ty::F32,
+        ty => ty,
+    }
+}
+
+struct Checker {
+    input: Ty,
+    vars: Vec<(String, Type)>,
+}
+
+impl Checker {
+    fn block(&mut self, block: &Block) -> Result<Type, ExprError> {
+        let scope = self.vars.len();
+        for binding in &block.lets {
+            let mut ty = self.expr(&binding.value)?;
+            if let Some(declared) = binding.ty {
+                ty = ty.unify(Type::Ty(declared)).ok_or_else(|| {
+                    ExprError::new(
+                        binding.value.offset,
+                        format!("`{}` is declared as {declared}, but is given {ty}", binding.name),
+                    )
+                })?;
+            }
+            self.vars.push((binding.name.clone(), ty));
+        }
+        let ty = self.expr(&block.value);
+        self.vars.truncate(scope);
+        ty
+    }
+
+    fn expr(&mut self, expr: &Expr) -> Result<Type, ExprError> {
+        let error = |message: String| Err(ExprError::new(expr.offset, message));
+        match &expr.kind {
+            ExprKind::Literal(l) => Ok(match (l.suffix, l.value) {
+                (Some(ty), _) => Type::Ty(ty),
+                (None, Number::Int(_)) => Type::Int,
+                (None, Number::Float(_)) => Type::Float,
+            }),
+            ExprKind::X => Ok(Type::Ty(value_type(self.input))),
+            ExprKind::Var(name) => match self.vars.iter().rev().find(|(n, _)| n == name) {
+                Some((_, ty)) => Ok(*ty),
+                None => error(format!("there is no `{name}`")),
+            },
+            ExprKind::Unary(op, operand) => {
+                let ty = self.expr(operand)?;
+                match op {
+                    UnOp::Neg if ty.is_number() && !matches!(ty, Type::Ty(t) if !t.is_signed()) => {
+                        Ok(ty)
+                    },
+                    UnOp::Neg => error(format!("can't negate {ty}")),
+                    UnOp::Not if ty.is_integer() || ty == Type::Bool => Ok(ty),
+                    UnOp::Not => error(format!("can't apply `!` to {ty}")),
+                }
+            },
+            ExprKind::Binary(op, lhs, rhs) => {
+                let (l, r) = (self.expr(lhs)?, self.expr(rhs)?);
+                let mismatch = || {
+                    Err(ExprError::new(
+                        expr.offset,
+                        format!("can't apply `{}` to {l} and {r}", op.symbol()),
+                    ))
+                };
+                match op {
+                    BinOp::Shl | BinOp::Shr if l.is_integer() && r.is_integer() => Ok(l),
+                    BinOp::Shl | BinOp::Shr => mismatch(),
+                    BinOp::LogicAnd | BinOp::LogicOr if l == Type::Bool && r == Type::Bool => {
+                        Ok(Type::Bool)
+                    },
+                    BinOp::LogicAnd | BinOp::LogicOr => mismatch(),
+                    _ => {
+                        let Some(ty) = l.unify(r).filter(|t| t.is_number()) else {
+                            return mismatch();
+                        };
+                        match op {
+                            _ if op.is_comparison() => Ok(Type::Bool),
+                            BinOp::And | BinOp::Or | BinOp::Xor if !ty.is_integer() => mismatch(),
+                            _ => Ok(ty),
+                        }
+                    },
+                }
+            },
+            ExprKind::Cast(value, ty) => {
+                let from = self.expr(value)?;
+                if !from.is_number() && from != Type::Bool {
+                    return error(format!("can't cast {from} to {ty}"));
+                }
+                if from == Type::Bool && ty.is_float() {
+                    return error(format!("can't cast bool to {ty}"));
+                }
+                Ok(Type::Ty(*ty))
+            },
+            ExprKind::If(condition, then, otherwise) => {
+                let c = self.expr(condition)?;
+                if c != Type::Bool {
+                    return Err(ExprError::new(
+                        condition.offset,
+                        format!("the condition is {c} instead of bool"),
+                    ));
+                }
+                let (t, o) = (self.block(then)?, self.block(otherwise)?);
+                match t.unify(o) {
+                    Some(ty) => Ok(ty),
+                    None => error(format!("one branch gives {t} and the other {o}")),
+                }
+            },
+            ExprKind::Logf(argument) => {
+                let ty = self.expr(argument)?;
+                match ty.unify(Type::Ty(Ty::F32)) {
+                    Some(ty) => Ok(ty),
+                    None => error(format!("`logf` takes f32, not {ty}")),
+                }
+            },
+            ExprKind::Block(block) => self.block(block),
+            ExprKind::ToBytes(value, _) => match self.expr(value)? {
+                Type::Ty(ty) if !matches!(ty, Ty::U8Arr(_)) => {
+                    Ok(Type::Ty(Ty::U8Arr(ty.ty_size())))
+                },
+                ty => error(format!("can't get the bytes of {ty}, cast it to a type first")),
+            },
+        }
+    }
+}
+
+/// Checks that `block` gives a value of type `output` for an `x` of type
+/// `input`.
+pub fn check(block: &Block, input: Ty, output: Ty) -> Result<(), ExprError> {
+    let mut checker = Checker { input, vars: vec![] };
+    let ty = checker.block(block)?;
+    match ty.unify(Type::Ty(value_type(output))) {
+        Some(_) => Ok(()),
+        None => Err(ExprError::new(
+            block.value.offset,
+            format!("the formula gives {ty}, but the procedure should give {output}"),
+        )),
+    }
+}
+
+/// Parses and checks a formula.
+pub fn compile(formula: &str, input: Ty, output: Ty) -> Result<Block, ExprError> {
+    let block = parse(formula)?;
+    check(&block, input, output)?;
+    Ok(block)
+}
+
+fn precedence(expr: &Expr) -> u8 {
+    match &expr.kind {
+        ExprKind::Binary(op, ..) => op.precedence(),
+        ExprKind::Cast(..) => CAST_PRECEDENCE,
+        ExprKind::Unary(..) => UNARY_PRECEDENCE,
+        // an `if` in an operand is always parenthesized
+        ExprKind::If(..) => 0,
+        _ => ATOM_PRECEDENCE,
+    }
+}
+
+fn expr_to_rust(expr: &Expr, min_precedence: u8, out: &mut String) {
+    if precedence(expr) < min_precedence {
+        out.push('(');
+        expr_to_rust(expr, 0, out);
+        out.push(')');
+        return;
+    }
+    match &expr.kind {
+        ExprKind::Literal(l) => out.push_str(&l.text),
+        ExprKind::X => out.push('x'),
+        ExprKind::Var(name) => out.push_str(name),
+        ExprKind::Unary(op, operand) => {
+            out.push(if *op == UnOp::Neg { '-' } else { '!' });
+            expr_to_rust(operand, UNARY_PRECEDENCE, out);
+        },
+        ExprKind::Binary(op, lhs, rhs) => {
+            // comparisons don't chain, so both of their sides bind stronger
+            let left = if op.is_comparison() { op.precedence() + 1 } else { op.precedence() };
+            expr_to_rust(lhs, left, out);
+            out.push_str(&format!(" {} ", op.symbol()));
+            expr_to_rust(rhs, op.precedence() + 1, out);
+        },
+        ExprKind::Cast(value, ty) => {
+            expr_to_rust(value, CAST_PRECEDENCE, out);
+            out.push_str(&format!(" as {ty}"));
+        },
+        ExprKind::If(condition, then, otherwise) => {
+            out.push_str("if ");
+            // a struct literal can't be the condition, so this is unambiguous
+            expr_to_rust(condition, 1, out);
+            out.push_str(" {\n");
+            block_to_rust(then, out);
+            out.push_str("\n} else {\n");
+            block_to_rust(otherwise, out);
+            out.push_str("\n}");
+        },
+        ExprKind::Logf(argument) => {
+            out.push_str("log_32::logf(");
+            expr_to_rust(argument, 0, out);
+            out.push(')');
+        },
+        ExprKind::Block(block) => {
+            out.push_str("{\n");
+            block_to_rust(block, out);
+            out.push_str("\n}");
+        },
+        ExprKind::ToBytes(value, big_endian) => {
+            expr_to_rust(value, ATOM_PRECEDENCE, out);
+            out.push_str(if *big_endian { ".to_be_bytes()" } else { ".to_le_bytes()" });
+        },
+    }
+}
+
+fn block_to_rust(block: &Block, out: &mut String) {
+    for binding in &block.lets {
+        out.push_str(&format!("let {}", binding.name));
+        if let Some(ty) = binding.ty {
+            out.push_str(&format!(": {ty}"));
+        }
+        out.push_str(" = ");
+        expr_to_rust(&binding.value, 0, out);
+        out.push_str(";\n");
+    }
+    expr_to_rust(&block.value, 0, out);
+}
+
+/// The Rust code of a formula, for the body of the function of its
+/// procedure
+pub fn to_rust(block: &Block) -> String {
+    let mut out = String::new();
+    block_to_rust(block, &mut out);
+    out
+}
+
+/// A value while evaluating a formula
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    /// An integer, with its type if it has one yet
+    Int(i128, Option<Ty>),
+    Float(f64, Option<Ty>),
+    Bool(bool),
+    Bytes(Vec<u8>),
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Int(v, _) => write!(f, "{v}"),
+            Value::Float(v, Some(Ty::F32)) => write!(f, "{:?}", *v as f32),
+            Value::Float(v, _) => write!(f, "{v:?}"),
+            Value::Bool(v) => write!(f, "{v}"),
+            Value::Bytes(v) => write!(f, "{v:?}"),
+        }
+    }
+}
+
+fn int_range(ty: Ty) -> (i128, i128) {
+    let bits = ty.ty_size() as u32 * 8;
+    if ty.is_signed() {
+        (-(1 << (bits - 1)), (1 << (bits - 1)) - 1)
+    } else {
+        (0, (1 << bits) - 1)
+    }
+}
+
+/// `v` cut to the bits of `ty`, like `as` does
+fn wrap(v: i128, ty: Ty) -> i128 {
+    let bits = ty.ty_size() as u32 * 8;
+    let v = v & ((1 << bits) - 1);
+    if ty.is_signed() && v >> (bits - 1) == 1 {
+        v - (1 << bits)
+    } else {
+        v
+    }
+}
+
+fn round_float(v: f64, ty: Option<Ty>) -> f64 {
+    match ty {
+        Some(Ty::F32) => v as f32 as f64,
+        _ => v,
+    }
+}
+
+/// Converts `value` to `ty`, like `value as ty` in Rust
+fn cast(value: Value, ty: Ty) -> Value {
+    if ty.is_float() {
+        let v = match value {
+            Value::Int(v, _) if ty == Ty::F64 => v as f64,
+            Value::Int(v, _) => v as f32 as f64,
+            Value::Float(v, _) => v,
+            Value::Bool(v) => v as u8 as f64,
+            Value::Bytes(_) => unreachable!("checked"),
+        };
+        return Value::Float(round_float(v, Some(ty)), Some(ty));
+    }
+    let v = match value {
+        Value::Int(v, _) => wrap(v, ty),
+        // floats saturate, and NaN becomes 0
+        Value::Float(v, _) => {
+            let (min, max) = int_range(ty);
+            if v.is_nan() {
+                0
+            } else {
+                (v.trunc().clamp(min as f64, max as f64) as i128).clamp(min, max)
+            }
+        },
+        Value::Bool(v) => v as i128,
+        Value::Bytes(_) => unreachable!("checked"),
+    };
+    Value::Int(v, Some(ty))
+}
+
+struct Evaluator {
+    x: Value,
+    vars: Vec<(String, Value)>,
+}
+
+impl Evaluator {
+    fn block(&mut self, block: &Block) -> Result<Value, ExprError> {
+        let scope = self.vars.len();
+        for binding in &block.lets {
+            let mut value = self.expr(&binding.value)?;
+            if let Some(ty) = binding.ty {
+                value = typed(value, ty, binding.value.offset)?;
+            }
+            self.vars.push((binding.name.clone(), value));
+        }
+        let value = self.expr(&block.value);
+        self.vars.truncate(scope);
+        value
+    }
+
+    fn expr(&mut self, expr: &Expr) -> Result<Value, ExprError> {
+        let error = |message: String| Err(ExprError::new(expr.offset, message));
+        Ok(match &expr.kind {
+            ExprKind::Literal(l) => match l.value {
+                Number::Int(v) if l.suffix.is_some_and(Ty::is_float) => {
+                    Value::Float(round_float(v as f64, l.suffix), l.suffix)
+                },
+                Number::Int(v) => match l.suffix {
+                    Some(ty) => typed(Value::Int(v, None), ty, expr.offset)?,
+                    None => Value::Int(v, None),
+                },
+                Number::Float(v) => Value::Float(round_float(v, l.suffix), l.suffix),
+            },
+            ExprKind::X => self.x.clone(),
+            ExprKind::Var(name) => {
+                self.vars.iter().rev().find(|(n, _)| n == name).unwrap().1.clone()
+            },
+            ExprKind::Unary(op, operand) => match (op, self.expr(operand)?) {
+                (UnOp::Neg, Value::Int(v, ty)) => checked(-v, ty, expr.offset)?,
+                (UnOp::Neg, Value::Float(v, ty)) => Value::Float(-v, ty),
+                (UnOp::Not, Value::Int(v, ty)) => Value::Int(ty.map_or(!v, |ty| wrap(!v, ty)), ty),
+                (UnOp::Not, Value::Bool(v)) => Value::Bool(!v),
+                _ => unreachable!("checked"),
+            },
+            ExprKind::Binary(op, lhs, rhs) => {
+                // `&&` and `||` don't evaluate their right side if they don't
+                // have to
+                if let BinOp::LogicAnd | BinOp::LogicOr = op {
+                    let Value::Bool(l) = self.expr(lhs)? else { unreachable!("checked") };
+                    if l == (*op == BinOp::LogicOr) {
+                        return Ok(Value::Bool(l));
+                    }
+                    return self.expr(rhs);
+                }
+                let (l, r) = (self.expr(lhs)?, self.expr(rhs)?);
+                binary(*op, l, r, expr.offset)?
+            },
+            ExprKind::Cast(value, ty) => cast(self.expr(value)?, *ty),
+            ExprKind::If(condition, then, otherwise) => match self.expr(condition)? {
+                Value::Bool(true) => self.block(then)?,
+                Value::Bool(false) => self.block(otherwise)?,
+                _ => unreachable!("checked"),
+            },
+            ExprKind::Logf(argument) => match self.expr(argument)? {
+                // the pod has its own `logf`, which can differ from this one in
+                // the last bit
+                Value::Float(v, _) => Value::Float((v as f32).ln() as f64, Some(Ty::F32)),
+                _ => unreachable!("checked"),
+            },
+            ExprKind::Block(block) => self.block(block)?,
+            ExprKind::ToBytes(value, big_endian) => {
+                let bytes = match self.expr(value)? {
+                    Value::Int(v, Some(ty)) => {
+                        let size = ty.ty_size();
+                        (v as u128).to_be_bytes()[16 - size..].to_vec()
+                    },
+                    Value::Float(v, Some(Ty::F32)) => (v as f32).to_be_bytes().to_vec(),
+                    Value::Float(v, Some(Ty::F64)) => v.to_be_bytes().to_vec(),
+                    v => return error(format!("can't get the bytes of {v}")),
+                };
+                let mut bytes = bytes;
+                if !big_endian {
+                    bytes.reverse();
+                }
+                Value::Bytes(bytes)
+            },
+        })
+    }
+}
+
+/// `v` as a value of type `ty`, failing if it doesn't fit like Rust does
+fn checked(v: i128, ty: Option<Ty>, offset: usize) -> Result<Value, ExprError> {
+    match ty {
+        Some(ty) => {
+            let (min, max) = int_range(ty);
+            if v < min || v > max {
+                return Err(ExprError::new(offset, format!("{v} doesn't fit in {ty}")));
+            }
+            Ok(Value::Int(v, Some(ty)))
+        },
+        None => Ok(Value::Int(v, None)),
+    }
+}
+
+/// Gives a value without a type yet the type `ty`.
+fn typed(value: Value, ty: Ty, offset: usize) -> Result<Value, ExprError> {
+    let ty = value_type(ty);
+    match value {
+        Value::Int(v, None) if ty.is_float() => {
+            Ok(Value::Float(round_float(v as f64, Some(ty)), Some(ty)))
+        },
+        Value::Int(v, None) => checked(v, Some(ty), offset),
+        Value::Float(v, None) if ty.is_float() => {
+            Ok(Value::Float(round_float(v, Some(ty)), Some(ty)))
+        },
+        Value::Float(v, None) => Err(ExprError::new(offset, format!("{v:?} is not a {ty}"))),
+        value => Ok(value),
+    }
+}
+
+fn binary(op: BinOp, l: Value, r: Value, offset: usize) -> Result<Value, ExprError> {
+    let error = |message: String| Err(ExprError::new(offset, message));
+    // a value without a type takes the type of the other side
+    let (l, r) = match (&l, &r) {
+        (Value::Int(_, Some(ty)) | Value::Float(_, Some(ty)), _)
+            if !matches!(op, BinOp::Shl | BinOp::Shr) =>
+        {
+            (l.clone(), typed(r, *ty, offset)?)
+        },
+        (_, Value::Int(_, Some(ty)) | Value::Float(_, Some(ty)))
+            if !matches!(op, BinOp::Shl | BinOp::Shr) =>
+        {
+            (typed(l, *ty, offset)?, r.clone())
+        },
+        _ => (l, r),
+    };
+
+    Ok(match (l, r) {
+        (Value::Int(a, ty), Value::Int(b, _)) => {
+            let overflow = || ExprError::new(offset, format!("`{}` overflows", op.symbol()));
+            let v = match op {
+                BinOp::Add => a.checked_add(b).ok_or_else(overflow)?,
+                BinOp::Sub => a.checked_sub(b).ok_or_else(overflow)?,
+                BinOp::Mul => a.checked_mul(b).ok_or_else(overflow)?,
+                BinOp::Div | BinOp::Rem if b == 0 => return error("division by zero".into()),
+                BinOp::Div => a / b,
+                BinOp::Rem => a % b,
+                BinOp::And => a & b,
+                BinOp::Or => a | b,
+                BinOp::Xor => a ^ b,
+                BinOp::Shl | BinOp::Shr => {
+                    let bits = ty.map_or(32, |ty| ty.ty_size() as i128 * 8);
+                    if b < 0 || b >= bits {
+                        return error(format!("can't shift by {b}"));
+                    }
+                    let v = if op == BinOp::Shl { a << b } else { a >> b };
+                    return Ok(Value::Int(ty.map_or(v, |ty| wrap(v, ty)), ty));
+                },
+                BinOp::Eq => return Ok(Value::Bool(a == b)),
+                BinOp::Ne => return Ok(Value::Bool(a != b)),
+                BinOp::Lt => return Ok(Value::Bool(a < b)),
+                BinOp::Le => return Ok(Value::Bool(a <= b)),
+                BinOp::Gt => return Ok(Value::Bool(a > b)),
+                BinOp::Ge => return Ok(Value::Bool(a >= b)),
+                BinOp::LogicAnd | BinOp::LogicOr => unreachable!("checked"),
+            };
+            match op {
+                BinOp::And | BinOp::Or | BinOp::Xor => Value::Int(v, ty),
+                _ => checked(v, ty, offset)?,
+            }
+        },
+        (Value::Float(a, ty), Value::Float(b, _)) => {
+            let v = match op {
+                BinOp::Add => a + b,
+                BinOp::Sub => a - b,
+                BinOp::Mul => a * b,
+                BinOp::Div => a / b,
+                BinOp::Rem => a % b,
+                BinOp::Eq => return Ok(Value::Bool(a == b)),
+                BinOp::Ne => return Ok(Value::Bool(a != b)),
+                BinOp::Lt => return Ok(Value::Bool(a < b)),
+                BinOp::Le => return Ok(Value::Bool(a <= b)),
+                BinOp::Gt => return Ok(Value::Bool(a > b)),
+                BinOp::Ge => return Ok(Value::Bool(a >= b)),
+                _ => unreachable!("checked"),
+            };
+            Value::Float(round_float(v, ty), ty)
+        },
+        (Value::Bool(a), Value::Bool(b)) => match op {
+            BinOp::Eq => Value::Bool(a == b),
+            BinOp::Ne => Value::Bool(a != b),
+            _ => unreachable!("checked"),
+        },
+        _ => unreachable!("checked"),
+    })
+}
+
+/// Evaluates a checked formula for `x`, which is of type `input`, into a
+/// value of type `output`.
+pub fn eval(block: &Block, input: Ty, output: Ty, x: Value) -> Result<Value, ExprError> {
+    let x = typed(x, input, 0)?;
+    let mut evaluator = Evaluator { x, vars: vec![] };
+    let value = evaluator.block(block)?;
+    typed(value, output, block.value.offset)
+}
+
+#[cfg(test)]
+#[path = "tests/expr.rs"]
+mod tests;
@@ -0,0 +1,17 @@
+// Part of the code generated for the main PCB, in a file of its own so that
+// it can be tested on the host
+
+/// The value of the half-precision float with bits `bits`, since there is no
+/// `f16` on stable
+fn f16_to_f32(bits: u16) -> f32 {
+    let sign = ((bits & 0x8000) as u32) << 16;
+    let exponent = ((bits >> 10) & 0x1F) as u32;
+    let mantissa = (bits & 0x3FF) as u32;
+    match exponent {
+        // zero and subnormals: mantissa * 2^-24
+        0 => f32::from_bits(sign | (mantissa as f32 / 16_777_216.0).to_bits()),
+        // infinities and NaNs
+        0x1F => f32::from_bits(sign | 0x7F80_0000 | (mantissa << 13)),
+        _ => f32::from_bits(sign | ((exponent + 127 - 15) << 23) | (mantissa << 13)),
+    }
+}
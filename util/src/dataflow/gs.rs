@@ -1,3 +1,4 @@
+use crate::dataflow::payloads::make_command_from_fields;
 use crate::dataflow::procedures::make_procedures;
 use crate::dataflow::*;
 
@@ -18,6 +19,7 @@ pub fn process_input_datatype(datatype: Datatype, data: u64) -> f64 {
         writeln!(&mut code, "Datatype::{dtn} => {x}(data),").unwrap();
     }
     writeln!(&mut code, "_ => data as f64,}}}}").unwrap();
+    code.push_str(&make_command_from_fields(df));
 
     code
 }
@@ -0,0 +1,399 @@
+//! Queries on a dataflow and comparisons between two of them, used by the
+//! `dataflow` tool.
+
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::fmt::Formatter;
+
+use crate::commands::CommandSafety;
+use crate::dataflow::*;
+
+/// A datatype with where its values come from, if they come from CAN
+pub struct DatapointInfo<'a> {
+    pub datapoint: &'a DatapointSpec,
+    /// The message and the conversion of the datapoint in it
+    pub source: Option<(&'a MessageProcessingSpec, &'a DatapointConversionSpec)>,
+}
+
+/// Every datatype of the dataflow, in the order they are defined
+pub fn datapoints(df: &DataflowSpec) -> Vec<DatapointInfo<'_>> {
+    let from_can = df.message_processing.iter().flat_map(|mp| {
+        mp.datapoint_conversion
+            .iter()
+            .map(move |dpc| DatapointInfo { datapoint: &dpc.datapoint, source: Some((mp, dpc)) })
+    });
+    let standard = df
+        .standard_datapoints
+        .iter()
+        .map(|sd| DatapointInfo { datapoint: &sd.datapoint, source: None });
+    from_can.chain(standard).collect()
+}
+
+/// Every CAN id in the dataflow as (bus, id, what it's used for), sorted by
+/// bus and id
+pub fn can_ids(df: &DataflowSpec) -> Vec<(u8, u32, String)> {
+    let mut ids = vec![];
+    for mp in &df.message_processing {
+        let (bus, id) = bus_and_id(&mp.can);
+        ids.push((bus, id, format!("message {}", mp.name)));
+    }
+    for command in &df.commands {
+        let (bus, id) = command.can_bus_and_id();
+        ids.push((bus, id, format!("command {}", command.name)));
+        for ack in command.ack.iter().flat_map(|a| &a.ids) {
+            ids.push((bus, *ack, format!("acknowledgement of {}", command.name)));
+        }
+    }
+    ids.sort_by_key(|(bus, id, _)| (*bus, *id));
+    ids
+}
+
+/// The ids datatypes and commands are sent to the ground station with
+pub fn used_ids(df: &DataflowSpec) -> Vec<u16> {
+    datapoints(df).iter().map(|d| d.datapoint.id).chain(df.commands.iter().map(|c| c.id)).collect()
+}
+
+/// The first `count` ids that are not in `used`, searching like
+/// [`crate::nearest_id`] from `near`
+pub fn free_ids(used: &[u16], near: u16, count: usize) -> Vec<u16> {
+    let mut used = used.to_vec();
+    let mut free = vec![];
+    while free.len() < count && used.len() < 1 << 11 {
+        let id = crate::nearest_id(near, &used);
+        used.push(id);
+        free.push(id);
+    }
+    free
+}
+
+fn bus_and_id(can: &CanSpec) -> (u8, u32) {
+    match *can {
+        CanSpec::Can1 { id } => (1, id),
+        CanSpec::Can2 { id, .. } => (2, id),
+    }
+}
+
+fn limit_to_string(limit: &Limit) -> String {
+    match limit {
+        Limit::No => "none".into(),
+        Limit::Single(x) => format!("{x}"),
+        Limit::Multiple(s) => [("warn", s.warn), ("err", s.err), ("brake", s.brake)]
+            .iter()
+            .filter_map(|(severity, x)| x.map(|x| format!("{severity} {x}")))
+            .collect::<Vec<_>>()
+            .join(", "),
+    }
+}
+
+/// A procedure with its formula indented below it
+fn procedure_to_string(df: &DataflowSpec, name: &str) -> String {
+    match df.procedures.get(name) {
+        Some(p) => {
+            let formula =
+                p.formula.trim().lines().map(|l| format!("\n      {l}")).collect::<String>();
+            format!("{name}: {} -> {}{formula}", p.input, p.output)
+        },
+        None => format!("{name} (missing)"),
+    }
+}
+
+/// Everything the dataflow says about a datatype, from the CAN message it is
+/// read from to how the ground station shows it
+pub fn describe_datapoint(df: &DataflowSpec, info: &DatapointInfo) -> String {
+    let dp = info.datapoint;
+    let mut lines = vec![format!("datatype {} ({:#05x})", dp.name, dp.id)];
+    match info.source {
+        Some((mp, dpc)) => {
+            let (bus, id) = bus_and_id(&mp.can);
+            lines.push(format!(
+                "  CAN:        can{bus} {id:#x} ({}), read as {}",
+                mp.name, dpc.getter
+            ));
+            if let Some(fsm) = &mp.fsm {
+                for rule in fsm.rules() {
+                    lines.push(format!("  FSM event:  {rule}"));
+                }
+            }
+            lines.push(format!(
+                "  conversion: {}",
+                procedure_to_string(df, &dpc.can_conversion.proc_name)
+            ));
+            let suffix = &dpc.gs.conversion.procedure_suffix;
+            lines.push(format!(
+                "  sent as:    {}",
+                procedure_to_string(df, &format!("dump-{suffix}"))
+            ));
+            lines.push(format!(
+                "  GS parse:   {}",
+                procedure_to_string(df, &format!("parse-{suffix}"))
+            ));
+            if let Some(units) = &dpc.display_units {
+                lines.push(format!("  units:      {units}"));
+            }
+            if let Some(limits) = &dpc.limits {
+                lines.push(format!(
+                    "  limits:     lower {}; upper {}",
+                    limit_to_string(&limits.lower),
+                    limit_to_string(&limits.upper)
+                ));
+            }
+        },
+        None => lines.push("  standard datapoint, not read from CAN".into()),
+    }
+    if let Some((timeout, states)) = dp.critical.as_ref().and_then(|c| c.stale_check()) {
+        let states = if states.is_empty() { "any state".into() } else { states.join(", ") };
+        lines.push(format!("  critical:   stale after {timeout} ms in {states}"));
+    }
+    if let Some(store) = &dp.store {
+        lines.push(format!("  GS store:   {} (default {})", store.ty, store.default));
+    }
+    lines.join("\n")
+}
+
+/// Everything the dataflow says about a command
+pub fn describe_command(df: &DataflowSpec, command: &CommandSpec) -> String {
+    let mut lines = vec![format!("command {} ({:#05x})", command.name, command.id)];
+    let (bus, id) = command.can_bus_and_id();
+    lines.push(format!("  CAN:        can{bus} {id:#x}"));
+    if let Some(can) = &command.can {
+        let conversion = can.conversion.as_deref().unwrap_or("default_command_process");
+        lines.push(format!("  conversion: {}", procedure_to_string(df, conversion)));
+        if can.trim.0 > 0 {
+            lines.push(format!("  trim:       {} byte(s)", can.trim.0));
+        }
+    }
+    if let Some(r) = &command.retransmit {
+        lines.push(format!(
+            "  retransmit: {} time(s), {} ms backoff{}",
+            r.retries,
+            r.backoff,
+            if r.must_ack { ", GS told on failure" } else { "" }
+        ));
+    }
+    if let Some(ack) = &command.ack {
+        let ids = ack.ids.iter().map(|id| format!("{id:#x}")).collect::<Vec<_>>().join(", ");
+        lines.push(format!("  acked by:   {ids} within {} ms", ack.timeout));
+    }
+    if let Some(event) = command_event(command) {
+        lines.push(format!("  FSM event:  {event}"));
+    }
+    if command.safety != CommandSafety::Normal {
+        lines.push(format!("  safety:     {:?}", command.safety));
+    }
+    for field in &command.payload {
+        lines.push(format!("  field:      {field}"));
+    }
+    lines.join("\n")
+}
+
+/// The FSM event of a command, written like the events of CAN messages
+fn command_event(command: &CommandSpec) -> Option<String> {
+    let event = command.fsm_event.as_ref()?;
+    Some(match &command.emergency_type {
+        Some(ty) => format!("{event}({ty})"),
+        None => event.clone(),
+    })
+}
+
+/// What happened to an item between two dataflows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Changed,
+}
+
+/// A difference between two dataflows
+#[derive(Debug, Clone)]
+pub struct Change {
+    pub kind: ChangeKind,
+    pub description: String,
+    /// Whether a pod and a ground station (or a subsystem) built from the
+    /// different dataflows would misunderstand each other
+    pub wire_incompatible: bool,
+}
+
+impl Display for Change {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let sign = match self.kind {
+            ChangeKind::Added => '+',
+            ChangeKind::Removed => '-',
+            ChangeKind::Changed => '~',
+        };
+        write!(f, "{sign} {}", self.description)?;
+        if self.wire_incompatible {
+            write!(f, "  [wire-incompatible]")?;
+        }
+        Ok(())
+    }
+}
+
+/// A property of an item that is compared between dataflows
+struct Property {
+    name: &'static str,
+    value: String,
+    /// Whether the property is part of what is sent over CAN or to the GS
+    wire: bool,
+}
+
+fn property(name: &'static str, value: impl Display, wire: bool) -> Property {
+    Property { name, value: value.to_string(), wire }
+}
+
+fn formula(df: &DataflowSpec, name: &str) -> String {
+    df.procedures.get(name).map(|p| p.formula.trim().to_string()).unwrap_or_default()
+}
+
+fn datapoint_properties(df: &DataflowSpec, info: &DatapointInfo) -> Vec<Property> {
+    let dp = info.datapoint;
+    let mut properties = vec![property("id", format_args!("{:#05x}", dp.id), true)];
+    if let Some((mp, dpc)) = info.source {
+        let (bus, id) = bus_and_id(&mp.can);
+        let suffix = &dpc.gs.conversion.procedure_suffix;
+        properties.extend([
+            property("CAN id", format_args!("can{bus} {id:#x}"), true),
+            property("getter", &dpc.getter, true),
+            property("conversion", &dpc.can_conversion.proc_name, true),
+            property("conversion formula", formula(df, &dpc.can_conversion.proc_name), true),
+            property("GS conversion", suffix, true),
+            property(
+                "GS conversion formula",
+                format_args!(
+                    "{}\n{}",
+                    formula(df, &format!("dump-{suffix}")),
+                    formula(df, &format!("parse-{suffix}"))
+                ),
+                true,
+            ),
+            property("units", dpc.display_units.as_deref().unwrap_or("none"), false),
+            property(
+                "limits",
+                dpc.limits.as_ref().map_or("none".into(), |l| {
+                    format!(
+                        "lower {}; upper {}",
+                        limit_to_string(&l.lower),
+                        limit_to_string(&l.upper)
+                    )
+                }),
+                false,
+            ),
+        ]);
+    }
+    let critical = dp.critical.as_ref().and_then(|c| c.stale_check());
+    properties.push(property(
+        "critical",
+        critical.map_or("no".into(), |(t, s)| format!("{t} ms in [{}]", s.join(", "))),
+        false,
+    ));
+    properties
+}
+
+fn command_properties(df: &DataflowSpec, command: &CommandSpec) -> Vec<Property> {
+    let (bus, id) = command.can_bus_and_id();
+    let conversion = command
+        .can
+        .as_ref()
+        .map(|c| c.conversion.as_deref().unwrap_or("default_command_process"))
+        .unwrap_or("none");
+    vec![
+        property("id", format_args!("{:#05x}", command.id), true),
+        property("CAN id", format_args!("can{bus} {id:#x}"), true),
+        property("conversion", conversion, true),
+        property("conversion formula", formula(df, conversion), true),
+        property("trim", command.can.as_ref().map_or(0, |c| c.trim.0), true),
+        property(
+            "acknowledgements",
+            command.ack.as_ref().map_or("none".into(), |a| format!("{:x?}", a.ids)),
+            true,
+        ),
+        property("FSM event", command_event(command).unwrap_or_else(|| "none".into()), true),
+        property("safety", format_args!("{:?}", command.safety), false),
+        property(
+            "payload",
+            match command.payload.as_slice() {
+                [] => "none".into(),
+                fields => fields.iter().map(|f| f.to_string()).collect::<Vec<_>>().join("; "),
+            },
+            true,
+        ),
+        property(
+            "retransmit",
+            command
+                .retransmit
+                .as_ref()
+                .map_or("none".into(), |r| format!("{} x {} ms", r.retries, r.backoff)),
+            false,
+        ),
+    ]
+}
+
+/// Compares the items of one kind between two dataflows
+fn diff_items(kind: &str, old: Items, new: Items, changes: &mut Vec<Change>) {
+    for (name, (id, _)) in &old {
+        if !new.contains_key(name) {
+            changes.push(Change {
+                kind: ChangeKind::Removed,
+                description: format!("{kind} {name} ({id:#05x})"),
+                wire_incompatible: false,
+            });
+        }
+    }
+    for (name, (id, properties)) in &new {
+        let Some((_, old_properties)) = old.get(name) else {
+            // an id that meant something else before is misread by the side
+            // that still has the old dataflow
+            let reused = old.iter().find(|(_, (old_id, _))| old_id == id);
+            changes.push(Change {
+                kind: ChangeKind::Added,
+                description: match reused {
+                    Some((other, _)) => format!("{kind} {name} ({id:#05x}, was {other})"),
+                    None => format!("{kind} {name} ({id:#05x})"),
+                },
+                wire_incompatible: reused.is_some(),
+            });
+            continue;
+        };
+        for p in properties {
+            let old_value = old_properties.iter().find(|o| o.name == p.name).map(|o| &o.value);
+            if old_value == Some(&p.value) {
+                continue;
+            }
+            let description = match old_value {
+                Some(old_value) if !old_value.contains('\n') && !p.value.contains('\n') => {
+                    format!("{kind} {name}: {} {old_value} -> {}", p.name, p.value)
+                },
+                Some(_) => format!("{kind} {name}: {} changed", p.name),
+                None => format!("{kind} {name}: {} {}", p.name, p.value),
+            };
+            changes.push(Change {
+                kind: ChangeKind::Changed,
+                description,
+                wire_incompatible: p.wire,
+            });
+        }
+    }
+}
+
+/// Items of one kind, keyed by name, with their ids and properties
+type Items<'a> = BTreeMap<&'a str, (u16, Vec<Property>)>;
+
+fn datatype_items(df: &DataflowSpec) -> Items<'_> {
+    datapoints(df)
+        .iter()
+        .map(|d| (d.datapoint.name.as_str(), (d.datapoint.id, datapoint_properties(df, d))))
+        .collect()
+}
+
+fn command_items(df: &DataflowSpec) -> Items<'_> {
+    df.commands.iter().map(|c| (c.name.as_str(), (c.id, command_properties(df, c)))).collect()
+}
+
+/// The differences between two dataflows: added, removed and changed
+/// datatypes and commands, and whether the changes break communication
+/// between boards built from different versions
+pub fn diff(old: &DataflowSpec, new: &DataflowSpec) -> Vec<Change> {
+    let mut changes = vec![];
+    diff_items("datatype", datatype_items(old), datatype_items(new), &mut changes);
+    diff_items("command", command_items(old), command_items(new), &mut changes);
+    changes
+}
@@ -69,8 +69,21 @@ VAR
         No_Messages_Queued: UINT := 0;
         tx_data: ARRAY[0..7] OF USINT;
 
-        local_u16: UINT_AND_BYTES;
-        local_u32: UDINT_AND_BYTES;
+        // values are put in the local of their type, and its bytes copied
+        // out of it (the PLC is little-endian)
+        local_usint: USINT;
+        local_sint: SINT;
+        local_uint: UINT;
+        local_int: INT;
+        local_udint: UDINT;
+        local_dint: DINT;
+        local_ulint: ULINT;
+        local_lint: LINT;
+        local_real: REAL;
+        local_lreal: LREAL;
+        local_bytes: ARRAY[0..7] OF USINT;
+        local_bits: ULINT;
+        local_exponent: DINT;
 
 "#
     )
@@ -86,7 +99,9 @@ VAR
 
     for mp in &df.message_processing {
         if let CanSpec::Can2 { id, comes_from_levi: Some(l) } = &mp.can {
-            let mut tx_data_create = String::new();
+            // bit fields are ORed into the bytes, so those start out as zero
+            let mut tx_data_create =
+                String::from("    FOR i := 0 TO 7 DO\n        tx_data[i] := 0;\n    END_FOR\n");
             for dp in &mp.datapoint_conversion {
                 let Some(levi_info) = &dp.comes_from_levi_info else {
                     panic!("no");
@@ -98,70 +113,8 @@ VAR
                     levi_info.levi_type.make_input(&levi_info.name)
                 )
                 .unwrap();
-                match dp.getter.ty {
-                    Ty::U8 => {
-                        writeln!(
-                            &mut tx_data_create,
-                            "    tx_data[{}] := {};",
-                            dp.getter.can_payload_range.start,
-                            levi_info.formula.replace("$", &levi_info.name)
-                        )
-                        .unwrap();
-                    },
-                    Ty::U16 => {
-                        writeln!(
-                            &mut tx_data_create,
-                            "    local_u16.value := {};",
-                            levi_info.formula.replace("$", &levi_info.name)
-                        )
-                        .unwrap();
-                        writeln!(
-                            &mut tx_data_create,
-                            "    tx_data[{}] := local_u16.bytes[1];",
-                            dp.getter.can_payload_range.start
-                        )
-                        .unwrap();
-                        writeln!(
-                            &mut tx_data_create,
-                            "    tx_data[{}] := local_u16.bytes[0];",
-                            dp.getter.can_payload_range.start + 1
-                        )
-                        .unwrap();
-                    },
-                    Ty::U32 => {
-                        writeln!(
-                            &mut tx_data_create,
-                            "    local_u32.value := {};",
-                            levi_info.formula.replace("$", &levi_info.name)
-                        )
-                        .unwrap();
-                        writeln!(
-                            &mut tx_data_create,
-                            "    tx_data[{}] := local_u32.bytes[3];",
-                            dp.getter.can_payload_range.start
-                        )
-                        .unwrap();
-                        writeln!(
-                            &mut tx_data_create,
-                            "    tx_data[{}] := local_u32.bytes[2];",
-                            dp.getter.can_payload_range.start + 1
-                        )
-                        .unwrap();
-                        writeln!(
-                            &mut tx_data_create,
-                            "    tx_data[{}] := local_u32.bytes[1];",
-                            dp.getter.can_payload_range.start + 2
-                        )
-                        .unwrap();
-                        writeln!(
-                            &mut tx_data_create,
-                            "    tx_data[{}] := local_u32.bytes[0];",
-                            dp.getter.can_payload_range.start + 3
-                        )
-                        .unwrap();
-                    },
-                    _ => panic!("not supported"),
-                }
+                let value = levi_info.formula.replace("$", &levi_info.name);
+                write_value(&mut tx_data_create, &dp.getter, &value);
             }
             writeln!(&mut vars, "    can_{id}_periods_since_last_log : INT := 1000;").unwrap();
             writeln!(
@@ -212,3 +165,91 @@ END_IF
     "
     )
 }
+
+/// The Structured Text type of the local a value of `ty` is computed in
+fn st_type(ty: Ty) -> &'static str {
+    match ty.big_endian() {
+        Ty::U8 => "USINT",
+        Ty::I8 => "SINT",
+        Ty::U16 => "UINT",
+        Ty::I16 => "INT",
+        Ty::U32 => "UDINT",
+        Ty::I32 => "DINT",
+        Ty::U64 => "ULINT",
+        Ty::I64 => "LINT",
+        // TwinCAT has no half-precision floats, see `write_value`
+        Ty::F16 | Ty::F32 => "REAL",
+        Ty::F64 => "LREAL",
+        ty => panic!("values of type {ty} can't be sent from the Beckhoff"),
+    }
+}
+
+/// Writes the Structured Text that puts `value` in `tx_data` where `getter`
+/// reads it from.
+fn write_value(code: &mut String, getter: &GetterSpec, value: &str) {
+    let start = getter.can_payload_range.start;
+    let local = format!("local_{}", st_type(getter.ty).to_lowercase());
+
+    if let Some(bits) = &getter.bits {
+        let mask = u64::MAX >> (64 - bits.len());
+        writeln!(code, "    {local} := {value};").unwrap();
+        writeln!(
+            code,
+            "    local_bits := {}_TO_ULINT({local}) AND 16#{mask:X};",
+            st_type(getter.ty)
+        )
+        .unwrap();
+        writeln!(code, "    local_bits := SHL(local_bits, {});", bits.start - start * 8).unwrap();
+        for (k, byte) in getter.can_payload_range.clone().enumerate() {
+            writeln!(
+                code,
+                "    tx_data[{byte}] := tx_data[{byte}] OR ULINT_TO_USINT(SHR(local_bits, {}) AND 16#FF);",
+                k * 8
+            )
+            .unwrap();
+        }
+        return;
+    }
+
+    match getter.ty.big_endian() {
+        Ty::U8 => {
+            writeln!(code, "    tx_data[{start}] := {value};").unwrap();
+            return;
+        },
+        Ty::F16 => {
+            // only the exponent and the top of the mantissa of the REAL fit:
+            // values too small for them become zero, values too large (and
+            // NaNs) infinity
+            writeln!(code, "    local_real := {value};").unwrap();
+            writeln!(
+                code,
+                "    MEMCPY(destAddr := ADR(local_udint), srcAddr := ADR(local_real), n := 4);
+    local_exponent := UDINT_TO_DINT(SHR(local_udint, 23) AND 16#FF) - 127 + 15;
+    IF local_exponent >= 31 THEN
+        local_uint := 16#7C00;
+    ELSIF local_exponent <= 0 THEN
+        local_uint := 0;
+    ELSE
+        local_uint := UDINT_TO_UINT(SHL(DINT_TO_UDINT(local_exponent), 10) OR (SHR(local_udint, 13) AND 16#3FF));
+    END_IF
+    local_uint := local_uint OR UDINT_TO_UINT(SHR(local_udint, 16) AND 16#8000);
+    MEMCPY(destAddr := ADR(local_bytes), srcAddr := ADR(local_uint), n := 2);"
+            )
+            .unwrap();
+        },
+        _ => {
+            writeln!(code, "    {local} := {value};").unwrap();
+            writeln!(
+                code,
+                "    MEMCPY(destAddr := ADR(local_bytes), srcAddr := ADR({local}), n := {});",
+                getter.ty.ty_size()
+            )
+            .unwrap();
+        },
+    }
+    let size = getter.ty.ty_size();
+    for k in 0..size {
+        let from = if getter.ty.is_little_endian() { k } else { size - 1 - k };
+        writeln!(code, "    tx_data[{}] := local_bytes[{from}];", start + k).unwrap();
+    }
+}
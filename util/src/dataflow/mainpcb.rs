@@ -106,66 +106,69 @@ fn apply_trim_8(data: [u8; 8], ctxt: &str) -> [u8; 0] {
     }
     trimmed
 }
+
 "#,
     );
-    let mut can1_ids_to_events = HashMap::new();
-    let mut can2_ids_to_events = HashMap::new();
+    code.push_str(include_str!("f16_to_f32.rs"));
     let proc = make_procedures(df);
 
-    for mp in &df.message_processing {
-        if let Some(fsm) = &mp.fsm {
-            match &mp.can {
-                CanSpec::Can1 { id, .. } => {
-                    if let Some(old_event) = can1_ids_to_events.insert(*id, &fsm.event) {
-                        panic!(
-                            "duplicate event for CAN1 id: {} ({} and {})",
-                            id, old_event, fsm.event
-                        );
-                    }
-                },
-                CanSpec::Can2 { id, .. } => {
-                    if let Some(old_event) = can2_ids_to_events.insert(*id, &fsm.event) {
-                        panic!(
-                            "duplicate event for CAN2 id: {} ({} and {})",
-                            id, old_event, fsm.event
-                        );
-                    }
-                },
+    for bus in [1, 2] {
+        let mut ids_to_rules = HashMap::new();
+        for mp in &df.message_processing {
+            let id = match mp.can {
+                CanSpec::Can1 { id } if bus == 1 => id,
+                CanSpec::Can2 { id, .. } if bus == 2 => id,
+                _ => continue,
+            };
+            if let Some(fsm) = &mp.fsm {
+                if let Some(old) = ids_to_rules.insert(id, fsm.rules()) {
+                    panic!(
+                        "duplicate events for CAN{bus} id: {id} ({} and {})",
+                        old[0],
+                        fsm.rules()[0]
+                    );
+                }
             }
         }
-    }
 
-    writeln!(&mut code, "pub fn event_for_can_1_id(id: u32) -> crate::Event {{ match id {{")
+        writeln!(
+            &mut code,
+            "pub fn event_for_can_{bus}_id(id: u32, payload: &[u8]) -> crate::Event {{ match id {{"
+        )
         .unwrap();
-
-    for (id, event) in &can1_ids_to_events {
-        writeln!(&mut code, "{id} => crate::Event::{event},").unwrap();
-    }
-
-    writeln!(
-        &mut code,
-        "_ => crate::Event::NoEvent,
+        for (id, rules) in &ids_to_rules {
+            // the first rule that holds gives the event
+            write!(&mut code, "{id} => ").unwrap();
+            for rule in rules.iter() {
+                match &rule.condition {
+                    Some(condition) => write!(
+                        &mut code,
+                        "if {} {{ {} }} else ",
+                        condition.to_rust("payload"),
+                        rule.to_rust()
+                    )
+                    .unwrap(),
+                    None => {
+                        write!(&mut code, "{{ {} }}", rule.to_rust()).unwrap();
+                        break;
+                    },
+                }
+            }
+            if rules.last().is_some_and(|rule| rule.condition.is_some()) {
+                write!(&mut code, "{{ crate::Event::NoEvent }}").unwrap();
+            }
+            writeln!(&mut code, ",").unwrap();
+        }
+        writeln!(
+            &mut code,
+            "_ => crate::Event::NoEvent,
         }}
     }}",
-    )
-    .unwrap();
-
-    writeln!(&mut code, "pub fn event_for_can_2_id(id: u32) -> crate::Event {{ match id {{")
+        )
         .unwrap();
-
-    for (id, event) in &can2_ids_to_events {
-        writeln!(&mut code, "{id} => crate::Event::{event},").unwrap();
     }
 
-    writeln!(
-        &mut code,
-        "_ =>crate::Event::NoEvent,
-        }}
-    }}",
-    )
-    .unwrap();
-
-    writeln!(&mut code, "pub async fn parse_datapoints_can_1<F, Fut>(id: u32, data: &[u8], mut f: F) where F: FnMut(Datapoint) -> Fut, Fut: Future<Output=()> {{ {proc} match id {{").unwrap();
+    writeln!(&mut code, "pub async fn parse_datapoints_can_1<F, Fut>(id: u32, data: &[u8], mut f: F) where F: FnMut(Datapoint, ValueCheckResult) -> Fut, Fut: Future<Output=()> {{ {proc} match id {{").unwrap();
     for mp in &df.message_processing {
         if let CanSpec::Can1 { id, .. } = mp.can {
             writeln!(
@@ -187,7 +190,7 @@ fn apply_trim_8(data: [u8; 8], ctxt: &str) -> [u8; 0] {
     }
     writeln!(&mut code, "_ => {{}}}}}}").unwrap();
 
-    writeln!(&mut code, "pub async fn parse_datapoints_can_2<F, Fut>(id: u32, data: &[u8], mut f: F) where F: FnMut(Datapoint) -> Fut, Fut: Future<Output=()> {{ {proc} match id {{").unwrap();
+    writeln!(&mut code, "pub async fn parse_datapoints_can_2<F, Fut>(id: u32, data: &[u8], mut f: F) where F: FnMut(Datapoint, ValueCheckResult) -> Fut, Fut: Future<Output=()> {{ {proc} match id {{").unwrap();
     for mp in &df.message_processing {
         if let CanSpec::Can2 { id, .. } = mp.can {
             writeln!(
@@ -209,10 +212,18 @@ fn apply_trim_8(data: [u8; 8], ctxt: &str) -> [u8; 0] {
     }
     writeln!(&mut code, "_ => {{}}}}}}").unwrap();
 
-    writeln!(&mut code, "pub fn match_can_to_datatypes(id: u32) -> [Datatype; 8] {{ match id {{")
+    for bus in [1, 2] {
+        writeln!(
+            &mut code,
+            "pub fn match_can_{bus}_to_datatypes(id: u32) -> [Datatype; 8] {{ match id {{"
+        )
         .unwrap();
-    for mp in &df.message_processing {
-        if let CanSpec::Can2 { id, .. } = mp.can {
+        for mp in &df.message_processing {
+            let id = match mp.can {
+                CanSpec::Can1 { id } if bus == 1 => id,
+                CanSpec::Can2 { id, .. } if bus == 2 => id,
+                _ => continue,
+            };
             writeln!(&mut code, "{id} => [ ").unwrap();
 
             let mut count = 0;
@@ -232,14 +243,14 @@ fn apply_trim_8(data: [u8; 8], ctxt: &str) -> [u8; 0] {
 
             writeln!(&mut code, "],").unwrap();
         }
-    }
 
-    writeln!(
-        &mut code,
-        "_ => [{}Datatype::DefaultDatatype],}}}}",
-        "Datatype::DefaultDatatype, ".repeat(7)
-    )
-    .unwrap();
+        writeln!(
+            &mut code,
+            "_ => [{}Datatype::DefaultDatatype],}}}}",
+            "Datatype::DefaultDatatype, ".repeat(7)
+        )
+        .unwrap();
+    }
 
     let mut can1commands = vec![];
     let mut can2commands = vec![];
@@ -256,21 +267,25 @@ fn apply_trim_8(data: [u8; 8], ctxt: &str) -> [u8; 0] {
         }
     }
 
-    writeln!(&mut code, "pub async fn gs_to_can1<F, Fut>(command: Command, mut f: F) where F: FnMut(crate::can::fdcan::CanEnvelope) -> Fut, Fut: Future<Output=()> {{ {proc}\n\nmatch command {{").unwrap();
+    // the CAN envelopes only exist on the pod
+    writeln!(&mut code, "#[cfg(target_os = \"none\")]").unwrap();
+    writeln!(&mut code, "pub async fn gs_to_can1<F, Fut>(command: Command, mut f: F) where F: FnMut(crate::can::can1::CanEnvelope) -> Fut, Fut: Future<Output=()> {{ {proc}\n\nmatch command {{").unwrap();
     for (command_name, id, conversion, trim) in &can1commands {
         writeln!(
             &mut code,
             r#"Command::{command_name}(v) => {{
                 let data = {apply_trim}({conversion}(v), "{command_name}");
-                f(crate::can::can1::CanEnvelope::new_from_frame(embassy_stm32::can::frame::FdFrame::new_extended({id}, &data).expect("Invalid frame!"))).await;
+                f(crate::can::can1::CanEnvelope::new_with_id({id}, &data)).await;
             }}"#,
             conversion = conversion.as_deref().unwrap_or("default_command_process"),
             apply_trim = format_args!("apply_trim_{trim}", trim = trim.0),
         )
-            .unwrap();
+        .unwrap();
     }
     writeln!(&mut code, "_ => {{}}}}}}").unwrap();
 
+    // the CAN envelopes only exist on the pod
+    writeln!(&mut code, "#[cfg(target_os = \"none\")]").unwrap();
     writeln!(&mut code, "pub async fn gs_to_can2<F, Fut>(command: Command, mut f: F) where F: FnMut(crate::can::can2::CanEnvelope) -> Fut, Fut: Future<Output=()> {{ {proc}\n\nmatch command {{").unwrap();
     for (command_name, id, conversion, trim) in &can2commands {
         writeln!(
@@ -286,5 +301,124 @@ fn apply_trim_8(data: [u8; 8], ctxt: &str) -> [u8; 0] {
     }
     writeln!(&mut code, "_ => {{}}}}}}").unwrap();
 
+    for bus in [1, 2] {
+        writeln!(&mut code, "#[cfg(target_os = \"none\")]").unwrap();
+        writeln!(&mut code, "pub fn retransmit_policy_can{bus}(id: u32) -> Option<crate::can::RetransmitPolicy> {{ match id {{").unwrap();
+        let mut ids_to_commands = HashMap::new();
+        for command in &df.commands {
+            let Some(retransmit) = &command.retransmit else { continue };
+            let (command_bus, id) = command.can_bus_and_id();
+            if command_bus != bus {
+                continue;
+            }
+            if let Some(old_command) = ids_to_commands.insert(id, &command.name) {
+                panic!(
+                    "duplicate retransmit policy for CAN{bus} id: {} ({} and {})",
+                    id, old_command, command.name
+                );
+            }
+            writeln!(
+                &mut code,
+                "{id} => Some(crate::can::RetransmitPolicy {{ retries: {}, backoff: embassy_time::Duration::from_millis({}), must_ack: {} }}), // {}",
+                retransmit.retries, retransmit.backoff, retransmit.must_ack, command.name
+            )
+            .unwrap();
+        }
+        writeln!(&mut code, "_ => None,}}}}").unwrap();
+
+        writeln!(&mut code, "#[cfg(target_os = \"none\")]").unwrap();
+        writeln!(&mut code, "pub fn expected_acks_can{bus}(id: u32) -> Option<crate::can::ExpectedAcks> {{ match id {{").unwrap();
+        let mut ids_to_commands = HashMap::new();
+        for command in &df.commands {
+            let Some(ack) = &command.ack else { continue };
+            let (command_bus, id) = command.can_bus_and_id();
+            if command_bus != bus {
+                continue;
+            }
+            if let Some(old_command) = ids_to_commands.insert(id, &command.name) {
+                panic!(
+                    "duplicate acknowledgements for CAN{bus} id: {} ({} and {})",
+                    id, old_command, command.name
+                );
+            }
+            writeln!(
+                &mut code,
+                "{id} => Some(crate::can::ExpectedAcks {{ command: {}, ids: &{:?}, timeout: embassy_time::Duration::from_millis({}) }}), // {}",
+                command.id, ack.ids, ack.timeout, command.name
+            )
+            .unwrap();
+        }
+        writeln!(&mut code, "_ => None,}}}}").unwrap();
+    }
+
+    code.push_str(&make_event_matching(df));
+
     code
 }
+
+/// The functions matching commands to the events they give the FSM, and the
+/// events of the FSM to the CAN frames and datapoints they're sent as
+fn make_event_matching(df: &DataflowSpec) -> String {
+    let mut code = String::new();
+
+    writeln!(
+        &mut code,
+        "pub fn match_cmd_to_event(command: Command) -> crate::Event {{ match command {{"
+    )
+    .unwrap();
+    for command in &df.commands {
+        let Some(event) = &command.fsm_event else { continue };
+        if event == "NoEvent" {
+            continue;
+        }
+        writeln!(
+            &mut code,
+            "Command::{}(_) => {},",
+            command.name,
+            event_to_rust(event, command.emergency_type.as_deref())
+        )
+        .unwrap();
+    }
+    writeln!(&mut code, "_ => crate::Event::NoEvent,}}}}").unwrap();
+
+    // `Variant { .. }` matches variants with and without fields
+    writeln!(&mut code, "#[cfg(target_os = \"none\")]").unwrap();
+    writeln!(&mut code, "pub fn match_event_to_can_envelope(event: crate::Event) -> Option<crate::can::can2::CanEnvelope> {{ let [_, _, x] = event.to_bytes(); match event {{").unwrap();
+    for output in &df.events {
+        let Some(name) = &output.can else { continue };
+        let command =
+            df.commands.iter().find(|c| &c.name == name).unwrap_or_else(|| {
+                panic!("event {} is sent as unknown command {name}", output.event)
+            });
+        let (_, id) = command.can_bus_and_id();
+        writeln!(
+            &mut code,
+            "crate::Event::{} {{ .. }} => Some(crate::can::can2::CanEnvelope::new_with_id({id}, &[x])), // {name}",
+            output.event
+        )
+        .unwrap();
+    }
+    writeln!(&mut code, "_ => None,}}}}").unwrap();
+
+    writeln!(&mut code, "pub fn match_event_to_datapoint(event: crate::Event) -> Option<(Datatype, u64)> {{ let [_, _, x] = event.to_bytes(); match event {{").unwrap();
+    for output in &df.events {
+        let Some(datatype) = &output.datatype else { continue };
+        let value = output
+            .compile_value()
+            .unwrap_or_else(|e| panic!("invalid value of event {}: {e}", output.event));
+        writeln!(
+            &mut code,
+            "crate::Event::{} {{ .. }} => Some((Datatype::{datatype}, {{ {} }})),",
+            output.event,
+            expr::to_rust(&value)
+        )
+        .unwrap();
+    }
+    writeln!(&mut code, "_ => None,}}}}").unwrap();
+
+    code
+}
+
+#[cfg(test)]
+#[path = "tests/mainpcb.rs"]
+mod tests;
@@ -7,11 +7,16 @@ use crate::commands;
 use crate::datatypes::Limit;
 use crate::datatypes::StoreInfo;
 
+pub mod dbc;
+pub mod expr;
 pub mod frontend;
 pub mod gs;
+pub mod inspect;
 pub mod levi;
 pub mod mainpcb;
+pub mod payloads;
 pub mod procedures;
+pub mod validate;
 
 #[derive(serde::Deserialize, Debug)]
 #[serde(rename_all = "kebab-case")]
@@ -20,6 +25,8 @@ pub struct DataflowSpec {
     pub standard_datapoints: Vec<StandardDatapointSpec>,
     pub message_processing: Vec<MessageProcessingSpec>,
     pub commands: Vec<CommandSpec>,
+    #[serde(default)]
+    pub events: Vec<EventOutputSpec>,
     pub beckhoff: BeckhoffTaskSpec,
 }
 
@@ -46,26 +53,79 @@ pub enum Ty {
     U32,
     U32LE,
     U64,
+    U64LE,
     I8,
+    I8LE,
     I16,
+    I16LE,
     I32,
+    I32LE,
     I64,
+    I64LE,
     F16,
+    F16LE,
     F32,
+    F32LE,
     F64,
+    F64LE,
     U8Arr(usize),
 }
 
 impl Ty {
     pub fn ty_size(self) -> usize {
         match self {
-            Self::U8 | Self::U8LE | Self::I8 => 1,
-            Self::U16 | Self::U16LE | Self::I16 | Self::F16 => 2,
-            Self::U32 | Self::U32LE | Self::I32 | Self::F32 => 4,
-            Self::U64 | Self::I64 | Self::F64 => 8,
+            Self::U8 | Self::U8LE | Self::I8 | Self::I8LE => 1,
+            Self::U16 | Self::U16LE | Self::I16 | Self::I16LE | Self::F16 | Self::F16LE => 2,
+            Self::U32 | Self::U32LE | Self::I32 | Self::I32LE | Self::F32 | Self::F32LE => 4,
+            Self::U64 | Self::U64LE | Self::I64 | Self::I64LE | Self::F64 | Self::F64LE => 8,
             Self::U8Arr(n) => n,
         }
     }
+
+    /// The same type, read in big-endian byte order
+    pub fn big_endian(self) -> Ty {
+        match self {
+            Self::U8LE => Self::U8,
+            Self::U16LE => Self::U16,
+            Self::U32LE => Self::U32,
+            Self::U64LE => Self::U64,
+            Self::I8LE => Self::I8,
+            Self::I16LE => Self::I16,
+            Self::I32LE => Self::I32,
+            Self::I64LE => Self::I64,
+            Self::F16LE => Self::F16,
+            Self::F32LE => Self::F32,
+            Self::F64LE => Self::F64,
+            ty => ty,
+        }
+    }
+
+    /// Whether values of the type are read least significant byte first
+    pub fn is_little_endian(self) -> bool { self.big_endian() != self }
+
+    pub fn is_float(self) -> bool { matches!(self.big_endian(), Self::F16 | Self::F32 | Self::F64) }
+
+    pub fn is_signed(self) -> bool {
+        matches!(self.big_endian(), Self::I8 | Self::I16 | Self::I32 | Self::I64) || self.is_float()
+    }
+
+    /// The smallest and largest value of the type, if it is a number
+    pub fn value_range(self) -> Option<(f64, f64)> {
+        match self {
+            Self::U8 | Self::U8LE => Some((0.0, u8::MAX as f64)),
+            Self::U16 | Self::U16LE => Some((0.0, u16::MAX as f64)),
+            Self::U32 | Self::U32LE => Some((0.0, u32::MAX as f64)),
+            Self::U64 | Self::U64LE => Some((0.0, u64::MAX as f64)),
+            Self::I8 | Self::I8LE => Some((i8::MIN as f64, i8::MAX as f64)),
+            Self::I16 | Self::I16LE => Some((i16::MIN as f64, i16::MAX as f64)),
+            Self::I32 | Self::I32LE => Some((i32::MIN as f64, i32::MAX as f64)),
+            Self::I64 | Self::I64LE => Some((i64::MIN as f64, i64::MAX as f64)),
+            Self::F16 | Self::F16LE => Some((-65504.0, 65504.0)),
+            Self::F32 | Self::F32LE => Some((f32::MIN as f64, f32::MAX as f64)),
+            Self::F64 | Self::F64LE => Some((f64::MIN, f64::MAX)),
+            Self::U8Arr(_) => None,
+        }
+    }
 }
 
 impl FromStr for Ty {
@@ -80,13 +140,21 @@ impl FromStr for Ty {
             "u32" => Ok(Self::U32),
             "u32le" => Ok(Self::U32LE),
             "u64" => Ok(Self::U64),
+            "u64le" => Ok(Self::U64LE),
             "i8" => Ok(Self::I8),
+            "i8le" => Ok(Self::I8LE),
             "i16" => Ok(Self::I16),
+            "i16le" => Ok(Self::I16LE),
             "i32" => Ok(Self::I32),
+            "i32le" => Ok(Self::I32LE),
             "i64" => Ok(Self::I64),
+            "i64le" => Ok(Self::I64LE),
             "f16" => Ok(Self::F16),
+            "f16le" => Ok(Self::F16LE),
             "f32" => Ok(Self::F32),
+            "f32le" => Ok(Self::F32LE),
             "f64" => Ok(Self::F64),
+            "f64le" => Ok(Self::F64LE),
             s if s.starts_with("[u8;") && s.ends_with(']') => {
                 let n = s[4..s.len() - 1].trim().parse().map_err(|_| "invalid array size").unwrap();
                 Ok(Self::U8Arr(n))
@@ -112,13 +180,21 @@ impl Display for Ty {
             Self::U32 => write!(f, "u32"),
             Self::U32LE => write!(f, "u32le"),
             Self::U64 => write!(f, "u64"),
+            Self::U64LE => write!(f, "u64le"),
             Self::I8 => write!(f, "i8"),
+            Self::I8LE => write!(f, "i8le"),
             Self::I16 => write!(f, "i16"),
+            Self::I16LE => write!(f, "i16le"),
             Self::I32 => write!(f, "i32"),
+            Self::I32LE => write!(f, "i32le"),
             Self::I64 => write!(f, "i64"),
+            Self::I64LE => write!(f, "i64le"),
             Self::F16 => write!(f, "f16"),
+            Self::F16LE => write!(f, "f16le"),
             Self::F32 => write!(f, "f32"),
+            Self::F32LE => write!(f, "f32le"),
             Self::F64 => write!(f, "f64"),
+            Self::F64LE => write!(f, "f64le"),
             Self::U8Arr(n) => write!(f, "[u8; {n}]"),
         }
     }
@@ -129,6 +205,86 @@ pub struct ProcedureSpec {
     pub input: Ty,
     pub output: Ty,
     pub formula: String,
+    /// Inputs with the outputs they should give, run by `dataflow test`
+    #[serde(default)]
+    pub tests: Vec<ProcedureTest>,
+}
+
+/// An input of a procedure and the output it should give
+#[derive(serde::Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct ProcedureTest {
+    pub x: TestValue,
+    pub expect: TestValue,
+    /// How far a float output may be from `expect`. By default it may only be
+    /// as far as rounding to the output type takes it.
+    pub tolerance: Option<f64>,
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum TestValue {
+    Int(i64),
+    Float(f64),
+    Bytes(Vec<u8>),
+}
+
+impl Display for TestValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TestValue::Int(v) => write!(f, "{v}"),
+            TestValue::Float(v) => write!(f, "{v:?}"),
+            TestValue::Bytes(v) => write!(f, "{v:?}"),
+        }
+    }
+}
+
+impl ProcedureSpec {
+    /// Parses and type-checks the formula.
+    pub fn compile(&self) -> Result<expr::Block, expr::ExprError> {
+        expr::compile(&self.formula, self.input, self.output)
+    }
+
+    /// Runs the tests of the procedure, giving a description of every test
+    /// that failed.
+    pub fn run_tests(&self) -> Result<Vec<String>, expr::ExprError> {
+        let block = self.compile()?;
+        let mut failures = vec![];
+        for test in &self.tests {
+            let x = match test.x {
+                TestValue::Int(x) => expr::Value::Int(x as i128, None),
+                TestValue::Float(x) => expr::Value::Float(x, None),
+                TestValue::Bytes(_) => {
+                    failures.push(format!("x = {}: x has to be a number", test.x));
+                    continue;
+                },
+            };
+            let output = match expr::eval(&block, self.input, self.output, x) {
+                Ok(output) => output,
+                Err(e) => {
+                    failures.push(format!("x = {}: {e}", test.x));
+                    continue;
+                },
+            };
+            let relative = if self.output == Ty::F64 { 1e-12 } else { 1e-6 };
+            let close = |value: f64, expected: f64| match test.tolerance {
+                Some(tolerance) => (value - expected).abs() <= tolerance,
+                None => (value - expected).abs() <= relative * expected.abs().max(1.0),
+            };
+            let passed = match (&output, &test.expect) {
+                (expr::Value::Int(v, _), TestValue::Int(e)) => *v == *e as i128,
+                (expr::Value::Int(v, _), TestValue::Float(e)) => close(*v as f64, *e),
+                (expr::Value::Float(v, _), TestValue::Int(e)) => close(*v, *e as f64),
+                (expr::Value::Float(v, _), TestValue::Float(e)) => close(*v, *e),
+                (expr::Value::Bytes(v), TestValue::Bytes(e)) => v == e,
+                _ => false,
+            };
+            if !passed {
+                failures.push(format!("x = {}: gave {output}, expected {}", test.x, test.expect));
+            }
+        }
+        Ok(failures)
+    }
 }
 
 #[derive(serde::Deserialize, Debug)]
@@ -160,10 +316,157 @@ pub struct Can2ComesFromLevi {
     pub log_period: u32,
 }
 
+/// The event the FSM gets when a message arrives: either one `event` for
+/// every message, or a list of rules of which the first that holds gives the
+/// event.
 #[derive(serde::Deserialize, Debug)]
-#[serde(deny_unknown_fields)]
-pub struct FsmSpec {
+#[serde(untagged)]
+pub enum FsmSpec {
+    Always(FsmRule),
+    Rules(Vec<FsmRule>),
+}
+
+impl FsmSpec {
+    pub fn rules(&self) -> &[FsmRule] {
+        match self {
+            Self::Always(rule) => std::slice::from_ref(rule),
+            Self::Rules(rules) => rules,
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct FsmRule {
+    /// When the rule holds, always if there is no condition
+    #[serde(rename = "if")]
+    pub condition: Option<ConditionSpec>,
+    /// A variant of `Event` without fields, or `Emergency`
     pub event: String,
+    /// The variant of `EmergencyType` of an `Emergency` event
+    pub emergency_type: Option<String>,
+}
+
+impl FsmRule {
+    fn to_rust(&self) -> String { event_to_rust(&self.event, self.emergency_type.as_deref()) }
+}
+
+/// Event `event`, with emergency type `emergency_type` if it's an emergency,
+/// as Rust in the crate defining `Event`
+fn event_to_rust(event: &str, emergency_type: Option<&str>) -> String {
+    match emergency_type {
+        Some(ty) => {
+            format!("crate::Event::{event} {{ emergency_type: crate::EmergencyType::{ty} }}")
+        },
+        None => format!("crate::Event::{event}"),
+    }
+}
+
+impl Display for FsmRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.emergency_type {
+            Some(ty) => write!(f, "{}({ty})", self.event)?,
+            None => write!(f, "{}", self.event)?,
+        }
+        if let Some(condition) = &self.condition {
+            write!(f, " if {condition}")?;
+        }
+        Ok(())
+    }
+}
+
+/// A comparison of a value in the payload, like `u8[0..1] == 2` or
+/// `i32[0..4] <= END_OF_TRACK_LIMIT`
+#[derive(serde::Deserialize, Debug)]
+#[serde(try_from = "String")]
+pub struct ConditionSpec {
+    pub getter: GetterSpec,
+    pub op: expr::BinOp,
+    pub operand: Operand,
+}
+
+/// What a value in the payload is compared with
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operand {
+    Int(i128),
+    Float(f64),
+    /// A constant of the pod's config, like `END_OF_TRACK_LIMIT`
+    Constant(String),
+}
+
+impl ConditionSpec {
+    /// The condition as Rust, reading the payload from `payload`. A payload
+    /// too short for the getter doesn't match.
+    fn to_rust(&self, payload: &str) -> String {
+        let ty = expr::value_type(self.getter.ty);
+        let operand = match &self.operand {
+            Operand::Int(v) => format!("{v}{ty}"),
+            Operand::Float(v) => format!("{v:?}{ty}"),
+            Operand::Constant(name) => format!("{name} as {ty}"),
+        };
+        format!(
+            "{payload}.len() >= {} && {} {} {operand}",
+            self.getter.can_payload_range.end,
+            self.getter.get_from_can_frame(payload),
+            self.op.symbol()
+        )
+    }
+}
+
+impl Display for ConditionSpec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {} ", self.getter, self.op.symbol())?;
+        match &self.operand {
+            Operand::Int(v) => write!(f, "{v}"),
+            Operand::Float(v) => write!(f, "{v:?}"),
+            Operand::Constant(name) => write!(f, "{name}"),
+        }
+    }
+}
+
+impl FromStr for ConditionSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let [getter, op, operand] = s.split_whitespace().collect::<Vec<_>>()[..] else {
+            return Err(format!("expected `<getter> <comparison> <value>`, found {s:?}"));
+        };
+        let getter = getter.parse()?;
+        let op = match op {
+            "==" => expr::BinOp::Eq,
+            "!=" => expr::BinOp::Ne,
+            "<" => expr::BinOp::Lt,
+            "<=" => expr::BinOp::Le,
+            ">" => expr::BinOp::Gt,
+            ">=" => expr::BinOp::Ge,
+            _ => return Err(format!("{op:?} is not a comparison, in {s:?}")),
+        };
+        let (negative, digits) = match operand.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, operand),
+        };
+        let operand = if let Some(hex) = digits.strip_prefix("0x") {
+            let v = i128::from_str_radix(hex, 16).map_err(|e| format!("{e}, in {s:?}"))?;
+            Operand::Int(if negative { -v } else { v })
+        } else if let Ok(v) = operand.parse::<i128>() {
+            Operand::Int(v)
+        } else if let Ok(v) = operand.parse::<f64>() {
+            Operand::Float(v)
+        } else if operand.chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_')
+            && operand.starts_with(|c: char| c.is_ascii_uppercase())
+        {
+            Operand::Constant(operand.to_string())
+        } else {
+            return Err(format!("{operand:?} is not a number or the name of a constant, in {s:?}"));
+        };
+        Ok(Self { getter, op, operand })
+    }
+}
+
+impl TryFrom<String> for ConditionSpec {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> { s.parse() }
 }
 
 #[derive(serde::Deserialize, Debug)]
@@ -206,12 +509,52 @@ impl StructuredTy {
     }
 }
 
+/// Bounds of a datapoint, in the unit of the value after the CAN conversion
 #[derive(serde::Deserialize, Debug)]
 pub struct LimitsSpec {
     pub lower: Limit,
     pub upper: Limit,
 }
 
+impl LimitsSpec {
+    /// Panics if the limits don't make sense for values of type `ty`, the
+    /// output of the CAN conversion of datapoint `name`.
+    pub fn validate(&self, ty: Ty, name: &str) {
+        let Some((min, max)) = ty.value_range() else {
+            panic!("datapoint {name} has limits, but its values are of type {ty}, which is not a number");
+        };
+        for (side, limit) in [("lower", &self.lower), ("upper", &self.upper)] {
+            for threshold in limit.thresholds() {
+                if !threshold.is_finite() || threshold < min || threshold > max {
+                    panic!(
+                        "{side} limit {threshold} of datapoint {name} can never be reached by values of type {ty}"
+                    );
+                }
+            }
+        }
+        // the severities have to get worse the further the value is out of bounds
+        if let Limit::Multiple(s) = self.upper {
+            let thresholds = [s.warn, s.err, s.brake].into_iter().flatten().collect::<Vec<_>>();
+            if thresholds.windows(2).any(|w| w[0] > w[1]) {
+                panic!("upper limits of datapoint {name} must increase from warn to err to brake");
+            }
+        }
+        if let Limit::Multiple(s) = self.lower {
+            let thresholds = [s.warn, s.err, s.brake].into_iter().flatten().collect::<Vec<_>>();
+            if thresholds.windows(2).any(|w| w[0] < w[1]) {
+                panic!("lower limits of datapoint {name} must decrease from warn to err to brake");
+            }
+        }
+        if let (Some(lower), Some(upper)) =
+            (self.lower.thresholds().reduce(f64::max), self.upper.thresholds().reduce(f64::min))
+        {
+            if lower > upper {
+                panic!("lower limit {lower} of datapoint {name} is above its upper limit {upper}");
+            }
+        }
+    }
+}
+
 #[derive(serde::Deserialize, Debug)]
 #[serde(try_from = "String")]
 pub struct CanConversionSpec {
@@ -279,15 +622,54 @@ impl TryFrom<String> for ConversionGsSpec {
 pub struct DatapointSpec {
     pub name: String,
     pub id: u16,
-    pub critical: Option<bool>,
+    pub critical: Option<CriticalSpec>,
     pub store: Option<StoreInfo>,
 }
 
+/// Timeout in milliseconds of critical datapoints that are only marked with
+/// `critical: true`
+pub const DEFAULT_STALE_TIMEOUT: u64 = 2_000;
+
+/// Whether the main PCB raises an emergency when a datapoint goes stale.
+/// Either `critical: true`, or the timeout and the FSM states to check in.
+#[derive(serde::Deserialize, Debug)]
+#[serde(untagged)]
+pub enum CriticalSpec {
+    Flag(bool),
+    Check(StaleCheckSpec),
+}
+
+#[derive(serde::Deserialize, Debug)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct StaleCheckSpec {
+    /// Milliseconds without receiving the datapoint after which it is stale
+    pub timeout: u64,
+    /// The FSM states in which the datapoint is checked, all of them if empty
+    #[serde(default)]
+    pub states: Vec<String>,
+}
+
+impl CriticalSpec {
+    /// The timeout and the states to check in, if the datapoint is critical
+    pub fn stale_check(&self) -> Option<(u64, &[String])> {
+        match self {
+            CriticalSpec::Flag(true) => Some((DEFAULT_STALE_TIMEOUT, &[])),
+            CriticalSpec::Flag(false) => None,
+            CriticalSpec::Check(check) => Some((check.timeout, &check.states)),
+        }
+    }
+}
+
 #[derive(serde::Deserialize, Debug)]
 #[serde(try_from = "String")]
 pub struct GetterSpec {
     pub ty: Ty,
+    /// The bytes the value is read from
     pub can_payload_range: std::ops::Range<usize>,
+    /// For `bits[..]` getters, the bits the value is read from. Bit `n` is bit
+    /// `n % 8` of byte `n / 8`, so fields that cross a byte boundary are
+    /// little-endian. The value is the smallest unsigned type that fits.
+    pub bits: Option<std::ops::Range<usize>>,
 }
 
 #[derive(serde::Deserialize, Debug)]
@@ -296,6 +678,129 @@ pub struct CommandSpec {
     pub name: String,
     pub id: u16,
     pub can: Option<CanCommandSpec>,
+    pub retransmit: Option<RetransmitSpec>,
+    pub ack: Option<AckSpec>,
+    /// The event the FSM gets when the command arrives, `NoEvent` for
+    /// commands that deliberately don't reach the FSM
+    pub fsm_event: Option<String>,
+    /// The variant of `EmergencyType` of an `Emergency` `fsm-event`
+    pub emergency_type: Option<String>,
+    /// The fields the `u64` of the command is made of, if it's more than one
+    /// number
+    #[serde(default)]
+    pub payload: Vec<FieldSpec>,
+    #[serde(default)]
+    pub safety: commands::CommandSafety,
+}
+
+impl CommandSpec {
+    /// The bus and CAN id the frames for this command are sent with: those in
+    /// `can` if it's there, otherwise the command id on CAN2, which is how the
+    /// FSM sends commands of its own (like `StopHV`).
+    pub fn can_bus_and_id(&self) -> (u8, u32) {
+        match &self.can {
+            Some(CanCommandSpec { can: CanSpec::Can1 { id }, .. }) => (1, *id),
+            Some(CanCommandSpec { can: CanSpec::Can2 { id, .. }, .. }) => (2, *id),
+            None => (2, self.id as u32),
+        }
+    }
+}
+
+/// A field of the payload of a command. The payload is sent as the 8 bytes of
+/// its `u64` in big-endian order, which is also how the default conversion puts
+/// it on CAN, and the field is read from and written to those bytes with its
+/// getter.
+#[derive(serde::Deserialize, Debug)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct FieldSpec {
+    pub name: String,
+    pub getter: GetterSpec,
+    /// The unit of the value that is sent, for the people sending it
+    pub unit: Option<String>,
+    /// The lowest and the highest value the field can be sent with
+    pub range: Option<[f64; 2]>,
+    #[serde(default)]
+    pub default: f64,
+}
+
+impl FieldSpec {
+    /// The Rust type of the field
+    pub fn value_type(&self) -> Ty { expr::value_type(self.getter.ty) }
+
+    /// The lowest and the highest value the field can have: its range, or
+    /// those that fit in its type or bits
+    pub fn limits(&self) -> Option<(f64, f64)> {
+        match (self.range, &self.getter.bits) {
+            (Some([min, max]), _) => Some((min, max)),
+            (None, Some(bits)) => Some((0.0, (u64::MAX >> (64 - bits.len())) as f64)),
+            (None, None) => self.getter.ty.value_range(),
+        }
+    }
+}
+
+impl Display for FieldSpec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.name, self.getter)?;
+        if let Some(unit) = &self.unit {
+            write!(f, " in {unit}")?;
+        }
+        if let Some([min, max]) = self.range {
+            write!(f, ", {min}..={max}")?;
+        }
+        write!(f, ", default {}", self.default)
+    }
+}
+
+/// What the main PCB sends when its FSM gets an event, besides handling it.
+/// The payload of the event is its `u8`, the byte of its emergency type, or 0
+/// if it has no data.
+#[derive(serde::Deserialize, Debug)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct EventOutputSpec {
+    pub event: String,
+    /// The command whose frame is sent on CAN2, with the payload as its only
+    /// byte
+    pub can: Option<String>,
+    /// The datatype sent to the ground station
+    pub datatype: Option<String>,
+    /// The value of the datatype, a formula of the payload `x` giving a u64
+    #[serde(default = "default_event_value")]
+    pub value: String,
+}
+
+fn default_event_value() -> String { "x as u64".to_string() }
+
+impl EventOutputSpec {
+    pub fn compile_value(&self) -> Result<expr::Block, expr::ExprError> {
+        expr::compile(&self.value, Ty::U8, Ty::U64)
+    }
+}
+
+/// The messages the subsystems answer a command with, on the bus the command
+/// is sent on.
+#[derive(serde::Deserialize, Debug)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct AckSpec {
+    /// CAN ids of the acknowledgements, one per subsystem that should answer
+    pub ids: Vec<u32>,
+    /// Milliseconds after sending the command within which every
+    /// acknowledgement should arrive
+    pub timeout: u64,
+}
+
+/// What the main PCB does when a frame for a command is dropped from the
+/// transmit mailboxes before it was sent.
+#[derive(serde::Deserialize, Debug)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct RetransmitSpec {
+    /// How many times the frame is sent again
+    pub retries: u8,
+    /// Milliseconds to wait before the first retry, doubled after every retry
+    pub backoff: u64,
+    /// Whether the ground station is told when the frame could not be
+    /// delivered after all retries
+    #[serde(default)]
+    pub must_ack: bool,
 }
 
 #[derive(serde::Deserialize, Debug)]
@@ -327,54 +832,57 @@ impl TryFrom<usize> for Trim {
 }
 
 impl GetterSpec {
-    fn get_from_can_frame(&self, data_slice: &str) -> String {
-        let start = self.can_payload_range.start;
-
-        fn size_2_bytes(data_slice: &str, start: usize) -> String {
-            format!("[{data_slice}[{start}], {data_slice}[{}]]", start + 1)
+    /// The bits of the payload the value is read from, in the numbering of
+    /// `bits`
+    pub fn bit_range(&self) -> std::ops::Range<usize> {
+        match &self.bits {
+            Some(bits) => bits.clone(),
+            None => self.can_payload_range.start * 8..self.can_payload_range.end * 8,
         }
+    }
 
-        fn size_4_bytes(data_slice: &str, start: usize) -> String {
-            format!(
-                "[{data_slice}[{start}], {data_slice}[{}], {data_slice}[{}], {data_slice}[{}]]",
-                start + 1,
-                start + 2,
-                start + 3
-            )
+    fn get_from_can_frame(&self, data_slice: &str) -> String {
+        let range = self.can_payload_range.clone();
+        let bytes = || {
+            let bytes = range.clone().map(|i| format!("{data_slice}[{i}]")).collect::<Vec<_>>();
+            format!("[{}]", bytes.join(", "))
+        };
+
+        if let Some(bits) = &self.bits {
+            // the bytes as one little-endian number, shifted to the first bit
+            let word = range
+                .clone()
+                .map(|i| match (i - range.start) * 8 {
+                    0 => format!("{data_slice}[{i}] as u64"),
+                    shift => format!("(({data_slice}[{i}] as u64) << {shift})"),
+                })
+                .collect::<Vec<_>>()
+                .join(" | ");
+            let word = match bits.start - range.start * 8 {
+                0 => format!("({word})"),
+                shift => format!("(({word}) >> {shift})"),
+            };
+            let mask = u64::MAX >> (64 - bits.len());
+            return format!("({word} & {mask:#x}) as {}", self.ty);
         }
 
-        fn size_8_bytes(data_slice: &str, start: usize) -> String {
-            format!("[{data_slice}[{start}], {data_slice}[{}], {data_slice}[{}], {data_slice}[{}], {data_slice}[{}], {data_slice}[{}], {data_slice}[{}], {data_slice}[{}]]", start + 1, start + 2, start + 3, start + 4, start + 5, start + 6, start + 7)
+        let from_bytes = if self.ty.is_little_endian() { "from_le_bytes" } else { "from_be_bytes" };
+        match self.ty.big_endian() {
+            Ty::U8 => format!("{data_slice}[{}]", range.start),
+            Ty::I8 => format!("{data_slice}[{}] as i8", range.start),
+            // there is no `f16` on stable, so its bits are converted by hand
+            Ty::F16 => format!("f16_to_f32(u16::{from_bytes}({}))", bytes()),
+            Ty::U8Arr(_) => bytes(),
+            ty => format!("{ty}::{from_bytes}({})", bytes()),
         }
+    }
+}
 
-        match self.ty {
-            Ty::U8 | Ty::U8LE => format!("{data_slice}[{start}]"),
-            Ty::U16 => {
-                format!("u16::from_be_bytes({})", size_2_bytes(data_slice, start))
-            },
-            Ty::U16LE => {
-                format!("u16::from_le_bytes({})", size_2_bytes(data_slice, start))
-            },
-            Ty::U32 => format!("u32::from_be_bytes({})", size_4_bytes(data_slice, start)),
-            Ty::U32LE => format!("u32::from_le_bytes({})", size_4_bytes(data_slice, start)),
-            Ty::U64 => format!("u64::from_be_bytes({})", size_8_bytes(data_slice, start)),
-            Ty::I8 => todo!(),
-            Ty::I16 => format!("i16::from_be_bytes({})", size_2_bytes(data_slice, start)),
-            Ty::I32 => format!("i32::from_be_bytes({})", size_4_bytes(data_slice, start)),
-            Ty::I64 => format!("i64::from_be_bytes({})", size_8_bytes(data_slice, start)),
-            Ty::F16 => {
-                format!("f16::from_be_bytes({})", size_2_bytes(data_slice, start))
-            },
-            Ty::F32 => format!("f32::from_be_bytes({})", size_4_bytes(data_slice, start)),
-            Ty::F64 => format!("f64::from_be_bytes({})", size_8_bytes(data_slice, start)),
-            Ty::U8Arr(n) => {
-                let mut s = String::from("[");
-                for i in 0..n {
-                    s.push_str(&format!("{data_slice}[{start} + {i}], "));
-                }
-                s.push(']');
-                s
-            },
+impl Display for GetterSpec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.bits {
+            Some(bits) => write!(f, "bits[{bits:?}]"),
+            None => write!(f, "{}[{:?}]", self.ty, self.can_payload_range),
         }
     }
 }
@@ -387,7 +895,6 @@ impl FromStr for GetterSpec {
             return Err(format!("missing opening bracket `[` in {s:?}"));
         };
 
-        let ty = ty_str.parse::<Ty>().unwrap();
         let Some(range) = range_and_closing_delim.strip_suffix(']') else {
             return Err(format!("missing closing bracket `]` in {s:?}"));
         };
@@ -396,19 +903,38 @@ impl FromStr for GetterSpec {
             return Err(format!("missing range delimiter `..` in {s:?}"));
         };
 
-        let start =
-            start.parse().map_err(|e| format!("invalid range start ({e}) in {s:?}")).unwrap();
-        let end = end.parse().map_err(|e| format!("invalid range end ({e}) in {s:?}")).unwrap();
+        let start: usize =
+            start.parse().map_err(|e| format!("invalid range start ({e}) in {s:?}"))?;
+        let end: usize = end.parse().map_err(|e| format!("invalid range end ({e}) in {s:?}"))?;
+        if end <= start {
+            return Err(format!("empty range in {s:?}"));
+        }
 
+        if ty_str == "bits" {
+            let bytes = start / 8..end.div_ceil(8);
+            if bytes.len() > 8 {
+                return Err(format!("bits {start}..{end} span more than 8 bytes in {s:?}"));
+            }
+            let ty = match end - start {
+                1..=8 => Ty::U8,
+                9..=16 => Ty::U16,
+                17..=32 => Ty::U32,
+                33..=64 => Ty::U64,
+                _ => return Err(format!("bit fields can be at most 64 bits, in {s:?}")),
+            };
+            return Ok(Self { ty, can_payload_range: bytes, bits: Some(start..end) });
+        }
+
+        let ty = ty_str.parse::<Ty>().map_err(|e| format!("{e} {ty_str:?} in {s:?}"))?;
         if end - start != ty.ty_size() {
             return Err(format!(
                 "range size does not match type size: expected {} but found {} in {s:?}",
-                end - start,
-                ty.ty_size()
+                ty.ty_size(),
+                end - start
             ));
         }
 
-        Ok(Self { ty, can_payload_range: start..end })
+        Ok(Self { ty, can_payload_range: start..end, bits: None })
     }
 }
 
@@ -423,23 +949,15 @@ fn make_datapoint_parser(spec: &MessageProcessingSpec) -> String {
     for dpc in &spec.datapoint_conversion {
         let s = dpc.getter.get_from_can_frame("data");
         writeln!(&mut code, "let d = {s};").unwrap();
-
-        if dpc.getter.ty != dpc.can_conversion.input {
-            panic!(
-                "getter type does not match can conversion input type ({} ≠ {}) for datapoint {}",
-                dpc.getter.ty, dpc.can_conversion.input, dpc.datapoint.name
-            );
-        }
-
         writeln!(&mut code, "let c = {}(d);", dpc.can_conversion.proc_name).unwrap();
 
-        if dpc.can_conversion.output != dpc.gs.conversion.input {
-            panic!(
-                "can conversion output type does not match gs conversion input type\
-                    ({} ≠ {}) for datapoint {}",
-                dpc.can_conversion.output, dpc.gs.conversion.input, dpc.datapoint.name
-            );
-        }
+        // the limits are in the unit of the converted value, which the ground
+        // station also checks them against
+        let check = if dpc.limits.is_some() {
+            format!("Datatype::{}.check_bounds(c as f64)", dpc.datapoint.name)
+        } else {
+            "ValueCheckResult::Fine".to_string()
+        };
 
         writeln!(
             &mut code,
@@ -447,7 +965,7 @@ fn make_datapoint_parser(spec: &MessageProcessingSpec) -> String {
                 Datatype::{},
                 dump_{}(c),
                 embassy_time::Instant::now().as_ticks(),
-            )).await;",
+            ), {check}).await;",
             dpc.datapoint.name, dpc.gs.conversion.procedure_suffix
         )
         .unwrap();
@@ -459,12 +977,13 @@ pub fn collect_data_types(df: &DataflowSpec) -> crate::datatypes::Config {
     let mut data_types = crate::datatypes::Config::default();
     for mp in &df.message_processing {
         for dpc in &mp.datapoint_conversion {
-            let mut critical = false;
-            if dpc.datapoint.critical.is_some() {
-                critical = dpc.datapoint.critical.unwrap();
-                if critical {
-                    data_types.criticalDatapoints.push(dpc.datapoint.name.clone())
-                }
+            if let Some(limits) = &dpc.limits {
+                limits.validate(dpc.can_conversion.output, &dpc.datapoint.name);
+            }
+            let stale_check = dpc.datapoint.critical.as_ref().and_then(|c| c.stale_check());
+            let critical = stale_check.is_some();
+            if critical {
+                data_types.criticalDatapoints.push(dpc.datapoint.name.clone())
             }
             data_types.Datatype.push(crate::datatypes::Datatype {
                 id: dpc.datapoint.id,
@@ -472,6 +991,8 @@ pub fn collect_data_types(df: &DataflowSpec) -> crate::datatypes::Config {
                 lower: dpc.limits.as_ref().map(|l| l.lower).unwrap_or(Limit::No),
                 upper: dpc.limits.as_ref().map(|l| l.upper).unwrap_or(Limit::No),
                 critical,
+                stale_timeout: stale_check.map(|(timeout, _)| timeout),
+                stale_states: stale_check.map(|(_, states)| states.to_vec()).unwrap_or_default(),
                 display_units: dpc.display_units.clone(),
                 priority: None,
                 store: dpc.datapoint.store.clone(),
@@ -485,6 +1006,8 @@ pub fn collect_data_types(df: &DataflowSpec) -> crate::datatypes::Config {
             lower: Limit::No,
             upper: Limit::No,
             critical: false,
+            stale_timeout: None,
+            stale_states: Vec::new(),
             display_units: None,
             priority: sd.priority,
             store: sd.datapoint.store.clone(),
@@ -497,9 +1020,19 @@ pub fn collect_data_types(df: &DataflowSpec) -> crate::datatypes::Config {
 pub fn collect_commands(df: &DataflowSpec) -> commands::Config {
     let mut commands = commands::Config { Command: Vec::new() };
     for cmd in &df.commands {
-        commands.Command.push(commands::Command { id: cmd.id, name: cmd.name.clone() });
+        commands.Command.push(commands::Command {
+            id: cmd.id,
+            name: cmd.name.clone(),
+            safety: cmd.safety,
+        });
     }
     commands
 }
 
-pub fn parse_from(data: &str) -> DataflowSpec { serde_yaml::from_str(data).unwrap() }
+pub fn parse_from(data: &str) -> DataflowSpec {
+    try_parse_from(data).unwrap_or_else(|e| panic!("invalid dataflow: {e}"))
+}
+
+pub fn try_parse_from(data: &str) -> Result<DataflowSpec, serde_yaml::Error> {
+    serde_yaml::from_str(data)
+}
@@ -0,0 +1,242 @@
+use crate::dataflow::*;
+
+/// The typed payloads of the commands with `payload` fields, shared by the pod
+/// and the ground station: a struct per command with builders, and encoding
+/// and decoding to and from the `u64` of the command that check the range of
+/// every field.
+pub fn make_payload_code(df: &DataflowSpec) -> String {
+    let mut code = String::new();
+    code.push_str(
+        r#"
+/// A field of the payload of a command that is out of its range
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(target_os = "none", derive(defmt::Format))]
+pub struct PayloadError {
+    pub command: &'static str,
+    pub field: &'static str,
+}
+
+impl core::fmt::Display for PayloadError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "field {} of command {} is out of its range", self.field, self.command)
+    }
+}
+"#,
+    );
+
+    let commands = df.commands.iter().filter(|c| !c.payload.is_empty()).collect::<Vec<_>>();
+    for command in &commands {
+        make_payload(&mut code, command);
+    }
+
+    writeln!(&mut code, "impl Command {{").unwrap();
+    writeln!(
+        &mut code,
+        "/// Checks that the fields of the payload of the command are in their range"
+    )
+    .unwrap();
+    writeln!(&mut code, "pub fn check_payload(&self) -> Result<(), PayloadError> {{").unwrap();
+    writeln!(&mut code, "#[allow(unreachable_patterns)]\nmatch *self {{").unwrap();
+    for command in &commands {
+        writeln!(
+            &mut code,
+            "Command::{name}(value) => {name}Payload::decode(value).map(|_| ()),",
+            name = command.name
+        )
+        .unwrap();
+    }
+    writeln!(&mut code, "_ => Ok(()),}}}}}}").unwrap();
+
+    code
+}
+
+fn make_payload(code: &mut String, command: &CommandSpec) {
+    let name = &command.name;
+    let payload = format!("{name}Payload");
+
+    writeln!(code, "/// The payload of command `{name}`").unwrap();
+    let zero_defaults = command.payload.iter().all(|f| f.default == 0.0);
+    let derive_default = if zero_defaults { ", Default" } else { "" };
+    writeln!(code, "#[derive(Debug, Clone, Copy, PartialEq{derive_default})]").unwrap();
+    writeln!(code, "#[cfg_attr(target_os = \"none\", derive(defmt::Format))]").unwrap();
+    writeln!(code, "pub struct {payload} {{").unwrap();
+    for field in &command.payload {
+        writeln!(code, "/// {}", describe_field(field)).unwrap();
+        writeln!(code, "pub {}: {},", field.name, field.value_type()).unwrap();
+    }
+    writeln!(code, "}}").unwrap();
+
+    // a derived `Default` would give the same, which clippy prefers
+    if !zero_defaults {
+        writeln!(code, "impl Default for {payload} {{ fn default() -> Self {{ Self {{").unwrap();
+        for field in &command.payload {
+            writeln!(code, "{}: {},", field.name, literal(field.value_type(), field.default))
+                .unwrap();
+        }
+        writeln!(code, "}}}}}}").unwrap();
+    }
+
+    writeln!(code, "impl {payload} {{").unwrap();
+    for field in &command.payload {
+        writeln!(
+            code,
+            "/// Sets `{f}`\npub fn {f}(mut self, {f}: {ty}) -> Self {{ self.{f} = {f}; self }}",
+            f = field.name,
+            ty = field.value_type()
+        )
+        .unwrap();
+    }
+
+    writeln!(code, "/// Checks that every field is in its range").unwrap();
+    writeln!(code, "pub fn check(&self) -> Result<(), PayloadError> {{").unwrap();
+    for field in &command.payload {
+        let ty = field.value_type();
+        let (min, max) = match (field.range, &field.getter.bits) {
+            (Some([min, max]), _) => (literal(ty, min), literal(ty, max)),
+            (None, Some(bits)) if bits.len() < 64 => {
+                ("0".to_string(), format!("{:#x}", u64::MAX >> (64 - bits.len())))
+            },
+            // the field can have every value of its type
+            _ => continue,
+        };
+        writeln!(
+            code,
+            "if !({min}..={max}).contains(&self.{f}) {{ return Err(PayloadError {{ command: \"{name}\", field: \"{f}\" }}); }}",
+            f = field.name
+        )
+        .unwrap();
+    }
+    writeln!(code, "Ok(())}}").unwrap();
+
+    writeln!(code, "/// The value of the command with this payload").unwrap();
+    writeln!(code, "pub fn encode(&self) -> Result<u64, PayloadError> {{").unwrap();
+    writeln!(code, "self.check()?;\nlet mut data = [0u8; 8];").unwrap();
+    for field in &command.payload {
+        write_field(code, field);
+    }
+    writeln!(code, "Ok(u64::from_be_bytes(data))}}").unwrap();
+
+    writeln!(code, "/// The payload of the command with value `value`").unwrap();
+    writeln!(code, "pub fn decode(value: u64) -> Result<Self, PayloadError> {{").unwrap();
+    writeln!(code, "let data = value.to_be_bytes();\nlet payload = Self {{").unwrap();
+    for field in &command.payload {
+        writeln!(code, "{}: {},", field.name, field.getter.get_from_can_frame("data")).unwrap();
+    }
+    writeln!(code, "}};\npayload.check()?;\nOk(payload)}}").unwrap();
+
+    writeln!(code, "/// Command `{name}` with this payload").unwrap();
+    writeln!(
+        code,
+        "pub fn to_command(&self) -> Result<Command, PayloadError> {{ Ok(Command::{name}(self.encode()?)) }}"
+    )
+    .unwrap();
+    writeln!(code, "}}").unwrap();
+}
+
+/// The unit and range of a field, for its doc comment
+fn describe_field(field: &FieldSpec) -> String {
+    let mut description = format!("`{}`", field.getter);
+    if let Some(unit) = &field.unit {
+        write!(&mut description, ", in {unit}").unwrap();
+    }
+    if let Some([min, max]) = field.range {
+        write!(&mut description, ", from {min} to {max}").unwrap();
+    }
+    description
+}
+
+/// `value` as a literal of type `ty`
+fn literal(ty: Ty, value: f64) -> String {
+    if ty.is_float() {
+        format!("{value:?}")
+    } else {
+        format!("{}", value as i128)
+    }
+}
+
+/// Writes field `field` of `self` into the bytes `data`, the inverse of its
+/// getter
+fn write_field(code: &mut String, field: &FieldSpec) {
+    let range = &field.getter.can_payload_range;
+    let Some(bits) = &field.getter.bits else {
+        let to_bytes =
+            if field.getter.ty.is_little_endian() { "to_le_bytes" } else { "to_be_bytes" };
+        writeln!(
+            code,
+            "data[{}..{}].copy_from_slice(&self.{}.{to_bytes}());",
+            range.start, range.end, field.name
+        )
+        .unwrap();
+        return;
+    };
+
+    // bit fields are little-endian across their bytes, and share them with
+    // other fields
+    let value = match field.value_type() {
+        Ty::U64 => format!("self.{}", field.name),
+        _ => format!("(self.{} as u64)", field.name),
+    };
+    let mask = u64::MAX >> (64 - bits.len());
+    match bits.start - range.start * 8 {
+        0 => writeln!(code, "{{ let bits = {value} & {mask:#x};").unwrap(),
+        shift => writeln!(code, "{{ let bits = ({value} & {mask:#x}) << {shift};").unwrap(),
+    }
+    for (k, byte) in range.clone().enumerate() {
+        match k {
+            0 => writeln!(code, "data[{byte}] |= bits as u8;").unwrap(),
+            k => writeln!(code, "data[{byte}] |= (bits >> {}) as u8;", k * 8).unwrap(),
+        }
+    }
+    writeln!(code, "}}").unwrap();
+}
+
+/// `command_from_fields`, which makes a command from the values of its payload
+/// fields by name, for the ground station
+pub fn make_command_from_fields(df: &DataflowSpec) -> String {
+    let mut code = String::new();
+    code.push_str(
+        r#"
+/// Command `name` with the payload fields in `fields`, by name. Fields that
+/// are left out have their default.
+pub fn command_from_fields(name: &str, fields: &std::collections::HashMap<String, f64>) -> Result<Command, String> {
+    match name {
+"#,
+    );
+    for command in df.commands.iter().filter(|c| !c.payload.is_empty()) {
+        writeln!(
+            &mut code,
+            "\"{name}\" => {{ let mut payload = {name}Payload::default();\nfor (field, &value) in fields {{ match field.as_str() {{",
+            name = command.name
+        )
+        .unwrap();
+        for field in &command.payload {
+            let value = if field.value_type().is_float() {
+                format!("value as {}", field.value_type())
+            } else {
+                "payload_field(name, field, value)?".to_string()
+            };
+            writeln!(&mut code, "\"{f}\" => payload.{f} = {value},", f = field.name).unwrap();
+        }
+        writeln!(
+            &mut code,
+            "_ => return Err(format!(\"command {{name}} has no field {{field}}\")),}}}}\npayload.to_command().map_err(|e| e.to_string())}}"
+        )
+        .unwrap();
+    }
+    code.push_str(
+        r#"        _ => Err(format!("command {name} has no payload fields")),
+    }
+}
+
+/// `value` as field `field` of `command`, if it is a whole number that fits
+fn payload_field<T: TryFrom<i64>>(command: &str, field: &str, value: f64) -> Result<T, String> {
+    let int = value as i64;
+    if int as f64 != value {
+        return Err(format!("field {field} of command {command} is a whole number, not {value}"));
+    }
+    T::try_from(int).map_err(|_| format!("{value} doesn't fit in field {field} of command {command}"))
+}
+"#,
+    );
+    code
+}
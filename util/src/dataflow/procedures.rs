@@ -1,17 +1,20 @@
+use crate::dataflow::expr::value_type;
 use crate::dataflow::*;
 
 pub fn make_procedures(df: &DataflowSpec) -> String {
     let mut code = String::from("");
 
     for (name, spec) in &df.procedures {
+        let body =
+            spec.compile().unwrap_or_else(|e| panic!("invalid formula of procedure {name}: {e}"));
         code.push_str(&format!(
             "fn {}(x: {}) -> {} {{\n",
             name.replace('-', "_"),
-            spec.input,
-            spec.output,
+            value_type(spec.input),
+            value_type(spec.output),
         ));
-        code.push_str(&spec.formula);
-        code.push_str("}\n\n");
+        code.push_str(&expr::to_rust(&body));
+        code.push_str("\n}\n\n");
     }
 
     code
@@ -0,0 +1,224 @@
+//! Tests for parsing, checking and evaluating formulas.
+
+use super::compile;
+use super::eval;
+use super::parse;
+use super::to_rust;
+use super::Value;
+use crate::dataflow::Ty;
+
+/// Compiles `formula` and evaluates it for `x`.
+fn run(formula: &str, input: Ty, output: Ty, x: Value) -> Result<Value, String> {
+    let block = compile(formula, input, output).map_err(|e| e.message)?;
+    eval(&block, input, output, x).map_err(|e| e.message)
+}
+
+fn int(v: i128) -> Value { Value::Int(v, None) }
+
+fn float(v: f64) -> Value { Value::Float(v, None) }
+
+/// The message of the error compiling `formula` gives.
+fn rejection(formula: &str, input: Ty, output: Ty) -> String {
+    compile(formula, input, output).expect_err(formula).message
+}
+
+#[test]
+fn operators_bind_like_in_rust() {
+    assert_eq!(run("1 + 2 * 3", Ty::U8, Ty::U8, int(0)), Ok(Value::Int(7, Some(Ty::U8))));
+    assert_eq!(run("(1 + 2) * 3", Ty::U8, Ty::U8, int(0)), Ok(Value::Int(9, Some(Ty::U8))));
+    assert_eq!(run("x - 2 - 3", Ty::I32, Ty::I32, int(10)), Ok(Value::Int(5, Some(Ty::I32))));
+    // shifts bind stronger than `&`
+    assert_eq!(run("x & 0xF0 >> 4", Ty::U8, Ty::U8, int(0xAB)), Ok(Value::Int(0x0B, Some(Ty::U8))));
+    // `-` binds stronger than `as`
+    assert_eq!(run("-2 as i8 as u8", Ty::U8, Ty::U8, int(0)), Ok(Value::Int(254, Some(Ty::U8))));
+
+    let either = "if x > 1 && x < 5 || x == 9 { 1 } else { 0 }";
+    for (x, expected) in [(3, 1), (9, 1), (5, 0), (0, 0)] {
+        assert_eq!(run(either, Ty::U8, Ty::U8, int(x)), Ok(Value::Int(expected, Some(Ty::U8))));
+    }
+}
+
+#[test]
+fn generated_rust_keeps_the_grouping() {
+    assert_eq!(to_rust(&parse("(x + 1) * 2").unwrap()), "(x + 1) * 2");
+    assert_eq!(to_rust(&parse("x + (1 * 2)").unwrap()), "x + 1 * 2");
+    assert_eq!(to_rust(&parse("(x - 1) - (2 - 3)").unwrap()), "x - 1 - (2 - 3)");
+    assert_eq!(to_rust(&parse("-(x as i8) as u8").unwrap()), "-(x as i8) as u8");
+}
+
+#[test]
+fn comparisons_cannot_be_chained() {
+    assert_eq!(parse("x < 1 < 2").unwrap_err().message, "comparisons can't be chained");
+    assert_eq!(parse("x == (1 < 2)").unwrap_err().message, "comparisons can't be chained");
+}
+
+#[test]
+fn casts_convert_like_as() {
+    assert_eq!(run("x as u8", Ty::U16, Ty::U8, int(0x1234)), Ok(Value::Int(0x34, Some(Ty::U8))));
+    assert_eq!(run("x as i8", Ty::U8, Ty::I8, int(200)), Ok(Value::Int(-56, Some(Ty::I8))));
+    assert_eq!(run("x as u16", Ty::I8, Ty::U16, int(-1)), Ok(Value::Int(0xFFFF, Some(Ty::U16))));
+    assert_eq!(run("x as f32", Ty::U8, Ty::F32, int(3)), Ok(Value::Float(3.0, Some(Ty::F32))));
+
+    // floats are truncated and saturate, and NaN becomes 0
+    assert_eq!(run("x as i32", Ty::F32, Ty::I32, float(-3.9)), Ok(Value::Int(-3, Some(Ty::I32))));
+    assert_eq!(run("x as u8", Ty::F32, Ty::U8, float(300.7)), Ok(Value::Int(255, Some(Ty::U8))));
+    assert_eq!(run("x as u8", Ty::F32, Ty::U8, float(-5.0)), Ok(Value::Int(0, Some(Ty::U8))));
+    assert_eq!(run("x as u8", Ty::F32, Ty::U8, float(f64::NAN)), Ok(Value::Int(0, Some(Ty::U8))));
+
+    // values of f32 are rounded to f32
+    assert_eq!(
+        run("x as f32", Ty::F64, Ty::F32, float(0.1)),
+        Ok(Value::Float(0.1f32 as f64, Some(Ty::F32)))
+    );
+}
+
+#[test]
+fn arithmetic_fails_when_it_overflows() {
+    assert_eq!(run("x + 1", Ty::U8, Ty::U8, int(254)), Ok(Value::Int(255, Some(Ty::U8))));
+    assert_eq!(run("x + 1", Ty::U8, Ty::U8, int(255)), Err("256 doesn't fit in u8".into()));
+    assert_eq!(run("x - 1", Ty::U8, Ty::U8, int(0)), Err("-1 doesn't fit in u8".into()));
+    assert_eq!(run("x * 2", Ty::I8, Ty::I8, int(-100)), Err("-200 doesn't fit in i8".into()));
+    assert_eq!(run("-x", Ty::I8, Ty::I8, int(-128)), Err("128 doesn't fit in i8".into()));
+    assert_eq!(run("x + 256", Ty::U8, Ty::U8, int(0)), Err("256 doesn't fit in u8".into()));
+    assert_eq!(run("x / 0", Ty::U8, Ty::U8, int(1)), Err("division by zero".into()));
+    assert_eq!(run("x % 0", Ty::U8, Ty::U8, int(1)), Err("division by zero".into()));
+    // the same goes for the value the procedure gives
+    assert_eq!(
+        run("if x > 1 { 300 } else { 0 }", Ty::U8, Ty::U8, int(2)),
+        Err("300 doesn't fit in u8".into())
+    );
+}
+
+#[test]
+fn bit_operations_stay_within_the_type() {
+    assert_eq!(run("!x", Ty::U8, Ty::U8, int(0x0F)), Ok(Value::Int(0xF0, Some(Ty::U8))));
+    assert_eq!(run("!x", Ty::I8, Ty::I8, int(0)), Ok(Value::Int(-1, Some(Ty::I8))));
+    assert_eq!(run("x ^ 0xFF", Ty::U8, Ty::U8, int(0x0F)), Ok(Value::Int(0xF0, Some(Ty::U8))));
+    assert_eq!(run("x | 0b1", Ty::U8, Ty::U8, int(0x10)), Ok(Value::Int(0x11, Some(Ty::U8))));
+    assert_eq!(run("x << 4", Ty::U8, Ty::U8, int(0xAB)), Ok(Value::Int(0xB0, Some(Ty::U8))));
+    // `>>` of a signed value keeps the sign
+    assert_eq!(run("x >> 1", Ty::I8, Ty::I8, int(-128)), Ok(Value::Int(-64, Some(Ty::I8))));
+    assert_eq!(run("x << 8", Ty::U8, Ty::U8, int(1)), Err("can't shift by 8".into()));
+
+    assert_eq!(
+        run("x.to_le_bytes()", Ty::U16, Ty::U8Arr(2), int(0x1234)),
+        Ok(Value::Bytes(vec![0x34, 0x12]))
+    );
+    assert_eq!(
+        run("x.to_be_bytes()", Ty::U16, Ty::U8Arr(2), int(0x1234)),
+        Ok(Value::Bytes(vec![0x12, 0x34]))
+    );
+    assert_eq!(
+        run("x.to_be_bytes()", Ty::F32, Ty::U8Arr(4), float(1.0)),
+        Ok(Value::Bytes(vec![0x3F, 0x80, 0, 0]))
+    );
+
+    assert_eq!(rejection("x & 1", Ty::F32, Ty::F32), "can't apply `&` to f32 and integer");
+    assert_eq!(rejection("x << 1.0", Ty::U8, Ty::U8), "can't apply `<<` to u8 and float");
+    assert_eq!(rejection("!1.5", Ty::F32, Ty::F32), "can't apply `!` to float");
+}
+
+#[test]
+fn both_branches_of_an_if_have_one_type() {
+    let clamp = "if x > 10 { 10 } else { x }";
+    assert_eq!(run(clamp, Ty::U8, Ty::U8, int(20)), Ok(Value::Int(10, Some(Ty::U8))));
+    assert_eq!(run(clamp, Ty::U8, Ty::U8, int(3)), Ok(Value::Int(3, Some(Ty::U8))));
+
+    let steps = "if x < 10 { 1 } else if x < 20 { 2 } else { 3 }";
+    for (x, expected) in [(5, 1), (15, 2), (25, 3)] {
+        assert_eq!(run(steps, Ty::U8, Ty::U8, int(x)), Ok(Value::Int(expected, Some(Ty::U8))));
+    }
+
+    // a float literal takes the float type of the other branch
+    assert_eq!(
+        run("if x > 1.0 { 1.0 } else { x }", Ty::F32, Ty::F32, float(0.5)),
+        Ok(Value::Float(0.5, Some(Ty::F32)))
+    );
+
+    assert_eq!(
+        rejection("if x > 10 { 1 } else { 2.5 }", Ty::U8, Ty::U8),
+        "one branch gives integer and the other float"
+    );
+    assert_eq!(
+        rejection("if x > 10 { x } else { x as u16 }", Ty::U8, Ty::U16),
+        "one branch gives u8 and the other u16"
+    );
+    assert_eq!(
+        rejection("if x { 1 } else { 2 }", Ty::U8, Ty::U8),
+        "the condition is u8 instead of bool"
+    );
+    assert_eq!(parse("if x > 10 { 1 }").unwrap_err().message, "`if` needs an `else`");
+}
+
+#[test]
+fn logf_is_the_natural_logarithm_of_an_f32() {
+    let e = std::f32::consts::E;
+    assert_eq!(
+        run("logf(x)", Ty::F32, Ty::F32, float(e as f64)),
+        Ok(Value::Float(e.ln() as f64, Some(Ty::F32)))
+    );
+    assert_eq!(
+        run("log_32::logf(x as f32)", Ty::U16, Ty::F32, int(1)),
+        Ok(Value::Float(0.0, Some(Ty::F32)))
+    );
+    assert_eq!(
+        run("logf(2.0)", Ty::U8, Ty::F32, int(0)),
+        Ok(Value::Float(2f32.ln() as f64, Some(Ty::F32)))
+    );
+    assert_eq!(to_rust(&parse("logf(x)").unwrap()), "log_32::logf(x)");
+
+    assert_eq!(rejection("logf(x)", Ty::U8, Ty::F32), "`logf` takes f32, not u8");
+    assert_eq!(rejection("logf(x)", Ty::F64, Ty::F32), "`logf` takes f32, not f64");
+    assert_eq!(parse("log_32::log(x)").unwrap_err().message, "expected `logf`");
+    assert_eq!(parse("log2(x)").unwrap_err().message, "unknown function `log2`");
+}
+
+#[test]
+fn errors_point_at_the_problem() {
+    let position = |formula: &str| {
+        let error = match compile(formula, Ty::U8, Ty::U8) {
+            Ok(_) => panic!("{formula} compiled"),
+            Err(error) => error,
+        };
+        (error.message.clone(), error.position(formula))
+    };
+
+    assert_eq!(position("x +\n  y"), ("there is no `y`".into(), (2, 3)));
+    assert_eq!(position("x $ 1"), ("unexpected character `$`".into(), (1, 3)));
+    assert_eq!(position("x +"), ("unexpected end of the formula".into(), (1, 4)));
+    assert_eq!(position("x 1"), ("expected the end of the formula".into(), (1, 3)));
+    assert_eq!(position("12ab"), ("invalid number `12ab`".into(), (1, 1)));
+    assert_eq!(
+        position("let a = 1;\nlet b: u8 = 2.5;\na + b"),
+        ("`b` is declared as u8, but is given float".into(), (2, 13))
+    );
+    assert_eq!(
+        position("// the input\nx\n  + 1.0"),
+        ("can't apply `+` to u8 and float".into(), (3, 3))
+    );
+}
+
+#[test]
+fn mismatched_types_are_rejected() {
+    assert_eq!(rejection("x + 1.0", Ty::U8, Ty::U8), "can't apply `+` to u8 and float");
+    assert_eq!(rejection("let y: u16 = 1; x + y", Ty::U8, Ty::U8), "can't apply `+` to u8 and u16");
+    assert_eq!(rejection("x > 1 && x", Ty::U8, Ty::U8), "can't apply `&&` to bool and u8");
+    assert_eq!(rejection("-x", Ty::U8, Ty::U8), "can't negate u8");
+    assert_eq!(rejection("(x > 1) as f32", Ty::U8, Ty::F32), "can't cast bool to f32");
+    assert_eq!(
+        rejection("1.to_le_bytes()", Ty::U8, Ty::U8Arr(4)),
+        "can't get the bytes of integer, cast it to a type first"
+    );
+    assert_eq!(
+        rejection("x as f32", Ty::U8, Ty::U8),
+        "the formula gives f32, but the procedure should give u8"
+    );
+    assert_eq!(
+        rejection("x > 1", Ty::U8, Ty::U8),
+        "the formula gives bool, but the procedure should give u8"
+    );
+    // the byte order of the input doesn't change its type
+    assert!(compile("x + 1", Ty::U16LE, Ty::U16).is_ok());
+    // and half-precision floats are read as f32
+    assert!(compile("logf(x)", Ty::F16, Ty::F32).is_ok());
+}
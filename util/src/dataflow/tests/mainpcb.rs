@@ -0,0 +1,155 @@
+//! Tests for the code the main PCB reads values from CAN frames with.
+
+use crate::dataflow::GetterSpec;
+
+include!("../f16_to_f32.rs");
+
+/// A frame whose bytes all differ, so that reading the wrong ones shows
+const FRAME: [u8; 8] = [0x01, 0x23, 0x45, 0x67, 0x89, 0xAB, 0xCD, 0xEF];
+
+/// Checks that `getter` is read from `data` with the code after it, and gives
+/// the value that code has.
+macro_rules! read {
+    ($getter:literal, $($code:tt)*) => {{
+        let getter: GetterSpec = $getter.parse().unwrap();
+        let squash = |code: &str| code.split_whitespace().collect::<String>();
+        assert_eq!(
+            squash(&getter.get_from_can_frame("data")),
+            squash(stringify!($($code)*)),
+            "code for {}",
+            $getter
+        );
+        $($code)*
+    }};
+}
+
+#[test]
+fn f16_zeros_keep_their_sign() {
+    assert_eq!(f16_to_f32(0x0000).to_bits(), 0.0f32.to_bits());
+    assert_eq!(f16_to_f32(0x8000).to_bits(), (-0.0f32).to_bits());
+}
+
+#[test]
+fn f16_subnormals_are_exact() {
+    let smallest = 2f32.powi(-24);
+    assert_eq!(f16_to_f32(0x0001), smallest);
+    assert_eq!(f16_to_f32(0x8001), -smallest);
+    assert_eq!(f16_to_f32(0x0200), 2f32.powi(-15));
+    assert_eq!(f16_to_f32(0x03FF), 1023.0 * smallest);
+    // the smallest normal value follows the largest subnormal one
+    assert_eq!(f16_to_f32(0x0400), 2f32.powi(-14));
+}
+
+#[test]
+fn f16_normal_values() {
+    assert_eq!(f16_to_f32(0x3C00), 1.0);
+    assert_eq!(f16_to_f32(0xC000), -2.0);
+    assert_eq!(f16_to_f32(0x3555), 0.33325195);
+    assert_eq!(f16_to_f32(0x5640), 100.0);
+    assert_eq!(f16_to_f32(0x7BFF), 65504.0);
+    assert_eq!(f16_to_f32(0xFBFF), -65504.0);
+}
+
+#[test]
+fn f16_infinities_and_nans() {
+    assert_eq!(f16_to_f32(0x7C00), f32::INFINITY);
+    assert_eq!(f16_to_f32(0xFC00), f32::NEG_INFINITY);
+    assert!(f16_to_f32(0x7E00).is_nan());
+    assert!(f16_to_f32(0x7C01).is_nan());
+    assert!(f16_to_f32(0xFFFF).is_nan());
+}
+
+// the code is as it is generated, which reads every field the same way
+#[allow(clippy::identity_op, clippy::unnecessary_cast)]
+#[test]
+fn bit_fields_cross_byte_boundaries() {
+    let data = FRAME;
+    assert_eq!(read!("bits[0..4]", ((data[0] as u64) & 0xf) as u8), 0x1);
+    assert_eq!(
+        read!("bits[4..12]", (((data[0] as u64 | ((data[1] as u64) << 8)) >> 4) & 0xff) as u8),
+        0x30
+    );
+    assert_eq!(
+        read!("bits[12..20]", (((data[1] as u64 | ((data[2] as u64) << 8)) >> 4) & 0xff) as u8),
+        0x52
+    );
+    assert_eq!(
+        read!(
+            "bits[6..17]",
+            (((data[0] as u64 | ((data[1] as u64) << 8) | ((data[2] as u64) << 16)) >> 6) & 0x7ff)
+                as u16
+        ),
+        0x48C
+    );
+    assert_eq!(read!("bits[60..64]", (((data[7] as u64) >> 4) & 0xf) as u8), 0xE);
+    assert_eq!(
+        read!(
+            "bits[0..64]",
+            ((data[0] as u64
+                | ((data[1] as u64) << 8)
+                | ((data[2] as u64) << 16)
+                | ((data[3] as u64) << 24)
+                | ((data[4] as u64) << 32)
+                | ((data[5] as u64) << 40)
+                | ((data[6] as u64) << 48)
+                | ((data[7] as u64) << 56))
+                & 0xffffffffffffffff) as u64
+        ),
+        0xEFCD_AB89_6745_2301
+    );
+}
+
+#[test]
+fn little_endian_getters_read_the_least_significant_byte_first() {
+    let data = FRAME;
+    assert_eq!(read!("u8le[1..2]", data[1]), 0x23);
+    assert_eq!(read!("i8le[5..6]", data[5] as i8), 0xABu8 as i8);
+    assert_eq!(read!("u16le[2..4]", u16::from_le_bytes([data[2], data[3]])), 0x6745);
+    assert_eq!(read!("i16le[4..6]", i16::from_le_bytes([data[4], data[5]])), 0xAB89u16 as i16);
+    assert_eq!(
+        read!("u32le[0..4]", u32::from_le_bytes([data[0], data[1], data[2], data[3]])),
+        0x6745_2301
+    );
+    assert_eq!(
+        read!("i32le[4..8]", i32::from_le_bytes([data[4], data[5], data[6], data[7]])),
+        0xEFCD_AB89u32 as i32
+    );
+    assert_eq!(
+        read!(
+            "u64le[0..8]",
+            u64::from_le_bytes([
+                data[0], data[1], data[2], data[3], data[4], data[5], data[6], data[7]
+            ])
+        ),
+        0xEFCD_AB89_6745_2301
+    );
+    assert_eq!(
+        read!(
+            "i64le[0..8]",
+            i64::from_le_bytes([
+                data[0], data[1], data[2], data[3], data[4], data[5], data[6], data[7]
+            ])
+        ),
+        0xEFCD_AB89_6745_2301u64 as i64
+    );
+    assert_eq!(
+        read!("f32le[0..4]", f32::from_le_bytes([data[0], data[1], data[2], data[3]])),
+        f32::from_bits(0x6745_2301)
+    );
+    assert_eq!(
+        read!(
+            "f64le[0..8]",
+            f64::from_le_bytes([
+                data[0], data[1], data[2], data[3], data[4], data[5], data[6], data[7]
+            ])
+        ),
+        f64::from_bits(0xEFCD_AB89_6745_2301)
+    );
+
+    let data = [0x00, 0x3C, 0x3C, 0x00];
+    assert_eq!(read!("f16le[0..2]", f16_to_f32(u16::from_le_bytes([data[0], data[1]]))), 1.0);
+    assert_eq!(read!("f16[2..4]", f16_to_f32(u16::from_be_bytes([data[2], data[3]]))), 1.0);
+    // the same bytes the other way around
+    assert_eq!(read!("u16[2..4]", u16::from_be_bytes([data[2], data[3]])), 0x3C00);
+    assert_eq!(read!("u16le[2..4]", u16::from_le_bytes([data[2], data[3]])), 0x003C);
+}
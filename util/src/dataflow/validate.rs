@@ -0,0 +1,642 @@
+//! Checks of a dataflow that go beyond what deserializing it checks, so that
+//! mistakes in dataflow.yaml are reported with their location before any code
+//! is generated from it.
+
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::fmt::Formatter;
+
+use crate::dataflow::*;
+
+/// Highest standard (11-bit) CAN id
+const MAX_STANDARD_ID: u32 = 0x7FF;
+/// Highest extended (29-bit) CAN id
+const MAX_EXTENDED_ID: u32 = 0x1FFF_FFFF;
+/// Payload size of a classic CAN frame
+const CAN2_PAYLOAD_SIZE: usize = 8;
+/// Payload size of a CAN-FD frame
+const CAN1_PAYLOAD_SIZE: usize = 64;
+
+/// A step on the way from the top of dataflow.yaml to a value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PathSegment {
+    Key(&'static str),
+    /// A key that is a name from the dataflow, like that of a procedure
+    Entry(String),
+    Index(usize),
+}
+
+impl PathSegment {
+    /// The key this segment is, if it isn't an index
+    fn key(&self) -> Option<&str> {
+        match self {
+            PathSegment::Key(key) => Some(key),
+            PathSegment::Entry(key) => Some(key),
+            PathSegment::Index(_) => None,
+        }
+    }
+}
+
+/// The keys and indices leading to a value in dataflow.yaml
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Path(Vec<PathSegment>);
+
+impl Path {
+    fn key(&self, key: &'static str) -> Self {
+        let mut path = self.clone();
+        path.0.push(PathSegment::Key(key));
+        path
+    }
+
+    fn entry(&self, key: &str) -> Self {
+        let mut path = self.clone();
+        path.0.push(PathSegment::Entry(key.to_string()));
+        path
+    }
+
+    fn index(&self, index: usize) -> Self {
+        let mut path = self.clone();
+        path.0.push(PathSegment::Index(index));
+        path
+    }
+
+    /// Line and column, both starting at 1, of the value in `source`, the
+    /// text the dataflow was parsed from. Only understands the block style
+    /// dataflow.yaml is written in.
+    pub fn locate(&self, source: &str) -> Option<(usize, usize)> {
+        let lines = source
+            .lines()
+            .enumerate()
+            .filter(|(_, l)| !l.trim().is_empty() && !l.trim_start().starts_with('#'))
+            .map(|(n, l)| (n, YamlLine::new(l)))
+            .collect::<Vec<_>>();
+
+        let mut range = 0..lines.len();
+        let mut position = None;
+        for segment in &self.0 {
+            let first = &lines.get(range.start)?.1;
+            match segment {
+                PathSegment::Key(_) | PathSegment::Entry(_) => {
+                    let key = segment.key();
+                    let column = first.key_column();
+                    let i = range
+                        .clone()
+                        .find(|&i| lines[i].1.key_column() == column && lines[i].1.key() == key)?;
+                    let end = (i + 1..range.end)
+                        .find(|&j| lines[j].1.indent <= column)
+                        .unwrap_or(range.end);
+                    position = Some((lines[i].0 + 1, column + 1));
+                    range = i + 1..end;
+                },
+                PathSegment::Index(index) => {
+                    let indent = first.indent;
+                    let i = range
+                        .clone()
+                        .filter(|&i| lines[i].1.item && lines[i].1.indent == indent)
+                        .nth(*index)?;
+                    let end = (i + 1..range.end)
+                        .find(|&j| lines[j].1.indent <= indent)
+                        .unwrap_or(range.end);
+                    position = Some((lines[i].0 + 1, indent + 1));
+                    // the first key of the item is on the line of the dash
+                    range = i..end;
+                },
+            }
+        }
+        position
+    }
+}
+
+impl Display for Path {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (i, segment) in self.0.iter().enumerate() {
+            match segment {
+                PathSegment::Index(index) => write!(f, "[{index}]")?,
+                _ if i == 0 => write!(f, "{}", segment.key().unwrap())?,
+                _ => write!(f, ".{}", segment.key().unwrap())?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A non-empty line of dataflow.yaml
+struct YamlLine<'a> {
+    /// Number of spaces before the content, or before the dash of an item
+    indent: usize,
+    /// Whether the line starts a sequence item
+    item: bool,
+    /// The line without the indentation and the dash
+    content: &'a str,
+}
+
+impl<'a> YamlLine<'a> {
+    fn new(line: &'a str) -> Self {
+        let content = line.trim_start();
+        let indent = line.len() - content.len();
+        match content.strip_prefix("- ") {
+            Some(content) => Self { indent, item: true, content: content.trim_start() },
+            None => Self { indent, item: false, content },
+        }
+    }
+
+    /// Column of the key on this line
+    fn key_column(&self) -> usize {
+        if self.item {
+            self.indent + 2
+        } else {
+            self.indent
+        }
+    }
+
+    /// The key on this line, if there is one
+    fn key(&self) -> Option<&'a str> {
+        let (key, _) = self.content.split_once(':')?;
+        key.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_').then_some(key)
+    }
+}
+
+/// A problem found in the dataflow
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    /// Where the problem is
+    pub path: Path,
+    pub message: String,
+}
+
+/// Checks the dataflow for:
+/// - duplicate ids and names of datatypes and commands
+/// - CAN ids that don't fit in 11 bits on CAN2, or in 29 bits on CAN1
+/// - getters that overlap or don't fit in the CAN message they're read from
+/// - procedures that don't exist or don't have the types they're used with
+/// - FSM events and emergency types, of CAN messages and commands, that are not
+///   variants of `lib::Event` and `lib::EmergencyType`, and conditions on them
+///   that can't hold
+/// - formulas of procedures that don't parse or don't have the types of their
+///   procedure
+/// - payload fields of commands that don't fit in the value of the command, or
+///   whose ranges and defaults don't fit in the field
+/// - events sent on to CAN or the GS that don't exist, are sent with commands
+///   or datatypes that don't exist, or whose value formula doesn't compile
+pub fn validate(df: &DataflowSpec, pod: &PodEnums) -> Vec<Diagnostic> {
+    let mut diagnostics = vec![];
+    let mut error = |path: Path, message: String| diagnostics.push(Diagnostic { path, message });
+
+    // datatypes and commands share the ids and names the GS refers to them by
+    let mut ids = HashMap::new();
+    let mut names = HashMap::new();
+    let mut check_item = |error: &mut dyn FnMut(Path, String), path: Path, name: &str, id: u16| {
+        if let Some(other) = ids.insert(id, name.to_string()) {
+            error(path.key("id"), format!("id {id:#05x} of {name} is already used by {other}"));
+        }
+        if names.insert(name.to_string(), id).is_some() {
+            error(path.key("name"), format!("{name} is defined more than once"));
+        }
+    };
+
+    let root = Path::default();
+    let mut procedures = df.procedures.iter().collect::<Vec<_>>();
+    procedures.sort_by_key(|(name, _)| *name);
+    for (name, procedure) in procedures {
+        if let Err(e) = procedure.compile() {
+            let (line, column) = e.position(&procedure.formula);
+            error(
+                root.key("procedures").entry(name).key("formula"),
+                format!("in the formula of {name}, at {line}:{column} of it: {e}"),
+            );
+        }
+    }
+
+    for (i, sd) in df.standard_datapoints.iter().enumerate() {
+        let path = root.key("standard-datapoints").index(i).key("datapoint");
+        check_item(&mut error, path, &sd.datapoint.name, sd.datapoint.id);
+    }
+
+    for (i, mp) in df.message_processing.iter().enumerate() {
+        let mp_path = root.key("message-processing").index(i);
+        let (id, payload_size) = match mp.can {
+            CanSpec::Can1 { id } => {
+                if id > MAX_EXTENDED_ID {
+                    error(
+                        mp_path.key("can").key("id"),
+                        format!("CAN id {id:#x} doesn't fit in 29 bits"),
+                    );
+                }
+                (id, CAN1_PAYLOAD_SIZE)
+            },
+            CanSpec::Can2 { id, .. } => {
+                if id > MAX_STANDARD_ID {
+                    error(
+                        mp_path.key("can").key("id"),
+                        format!("CAN id {id:#x} doesn't fit in 11 bits"),
+                    );
+                }
+                (id, CAN2_PAYLOAD_SIZE)
+            },
+        };
+
+        if let Some(fsm) = &mp.fsm {
+            let mut unconditional = false;
+            for (j, rule) in fsm.rules().iter().enumerate() {
+                let path = match fsm {
+                    FsmSpec::Always(_) => mp_path.key("fsm"),
+                    FsmSpec::Rules(_) => mp_path.key("fsm").index(j),
+                };
+                if unconditional {
+                    error(
+                        path.clone(),
+                        format!("rule {rule} of CAN id {id:#x} comes after one that always holds"),
+                    );
+                }
+                unconditional |= rule.condition.is_none();
+                check_rule(&mut error, path, rule, id, payload_size, pod);
+            }
+        }
+
+        let mut read: Vec<(&String, &GetterSpec)> = vec![];
+        for (j, dpc) in mp.datapoint_conversion.iter().enumerate() {
+            let path = mp_path.key("datapoint-conversion").index(j);
+            let name = &dpc.datapoint.name;
+            let getter = &dpc.getter;
+            check_item(&mut error, path.key("datapoint"), name, dpc.datapoint.id);
+
+            if getter.can_payload_range.end > payload_size {
+                error(
+                    path.key("getter"),
+                    format!(
+                        "{name} is read with {getter}, but CAN id {id:#x} only has {payload_size} bytes"
+                    ),
+                );
+            }
+            // bit fields can share a byte, so the bits have to overlap
+            let bits = getter.bit_range();
+            for (other, other_getter) in &read {
+                let other_bits = other_getter.bit_range();
+                if bits.start < other_bits.end && other_bits.start < bits.end {
+                    error(
+                        path.key("getter"),
+                        format!(
+                            "{name} is read with {getter}, which overlaps with {other_getter} of {other}"
+                        ),
+                    );
+                }
+            }
+            read.push((name, getter));
+
+            let conversion = &dpc.can_conversion;
+            if dpc.getter.ty != conversion.input {
+                error(
+                    path.key("getter"),
+                    format!(
+                        "{name} is read as {}, but its CAN conversion takes {}",
+                        dpc.getter.ty, conversion.input
+                    ),
+                );
+            }
+            if conversion.output != dpc.gs.conversion.input {
+                error(
+                    path.key("gs").key("conversion"),
+                    format!(
+                        "the CAN conversion of {name} gives {}, but its GS conversion takes {}",
+                        conversion.output, dpc.gs.conversion.input
+                    ),
+                );
+            }
+            check_procedure(
+                df,
+                &mut error,
+                path.key("can-conversion"),
+                &conversion.proc_name,
+                conversion.input,
+                conversion.output,
+            );
+
+            let suffix = &dpc.gs.conversion.procedure_suffix;
+            check_procedure(
+                df,
+                &mut error,
+                path.key("gs").key("conversion"),
+                &format!("dump-{suffix}"),
+                dpc.gs.conversion.input,
+                Ty::U64,
+            );
+            check_procedure(
+                df,
+                &mut error,
+                path.key("gs").key("conversion"),
+                &format!("parse-{suffix}"),
+                Ty::U64,
+                Ty::F64,
+            );
+        }
+    }
+
+    for (i, command) in df.commands.iter().enumerate() {
+        let path = root.key("commands").index(i);
+        check_item(&mut error, path.clone(), &command.name, command.id);
+
+        let what = format!("command {}", command.name);
+        match (&command.fsm_event, &command.emergency_type) {
+            (Some(event), emergency_type) => {
+                // `check_event` reports at `event`, which is `fsm-event` here
+                let mut error = |p: Path, message| {
+                    error(if p == path.key("event") { path.key("fsm-event") } else { p }, message)
+                };
+                check_event(&mut error, &path, event, emergency_type.as_deref(), &what, pod);
+            },
+            (None, Some(_)) => error(
+                path.key("emergency-type"),
+                format!("{what} has an `emergency-type` but no `fsm-event`"),
+            ),
+            (None, None) => {},
+        }
+
+        check_payload(&mut error, &path, command);
+
+        let Some(can) = &command.can else { continue };
+        match can.can {
+            CanSpec::Can1 { id } if id > MAX_EXTENDED_ID => {
+                error(path.key("can").key("id"), format!("CAN id {id:#x} doesn't fit in 29 bits"))
+            },
+            CanSpec::Can2 { id, .. } if id > MAX_STANDARD_ID => {
+                error(path.key("can").key("id"), format!("CAN id {id:#x} doesn't fit in 11 bits"))
+            },
+            _ => {},
+        }
+        let (path, conversion) = match &can.conversion {
+            Some(conversion) => (path.key("can").key("conversion"), conversion.as_str()),
+            None => (path.key("can"), "default_command_process"),
+        };
+        check_procedure(df, &mut error, path, conversion, Ty::U64, Ty::U8Arr(8));
+    }
+
+    let datapoints = df
+        .standard_datapoints
+        .iter()
+        .map(|sd| &sd.datapoint.name)
+        .chain(
+            df.message_processing
+                .iter()
+                .flat_map(|mp| mp.datapoint_conversion.iter().map(|dpc| &dpc.datapoint.name)),
+        )
+        .collect::<Vec<_>>();
+    let mut outputs = HashMap::new();
+    for (i, output) in df.events.iter().enumerate() {
+        let path = root.key("events").index(i);
+        let event = &output.event;
+        if !pod.events.contains(event) {
+            error(path.key("event"), format!("event {event} is not a variant of `Event`"));
+        }
+        if outputs.insert(event, i).is_some() {
+            error(path.key("event"), format!("event {event} is in `events` more than once"));
+        }
+        if let Some(name) = &output.can {
+            match df.commands.iter().find(|c| &c.name == name) {
+                None => error(path.key("can"), format!("there is no command {name}")),
+                Some(command) if command.can_bus_and_id().0 != 2 => error(
+                    path.key("can"),
+                    format!("event {event} is sent on CAN2, but command {name} is on CAN1"),
+                ),
+                Some(_) => {},
+            }
+        }
+        if let Some(datatype) = &output.datatype {
+            if !datapoints.contains(&datatype) {
+                error(path.key("datatype"), format!("there is no datatype {datatype}"));
+            }
+        }
+        if let Err(e) = output.compile_value() {
+            let (line, column) = e.position(&output.value);
+            error(
+                path.key("value"),
+                format!("in the value of event {event}, at {line}:{column} of it: {e}"),
+            );
+        }
+    }
+
+    diagnostics
+}
+
+/// Checks that procedure `name` exists and converts `input` to `output`.
+fn check_procedure(
+    df: &DataflowSpec,
+    error: &mut impl FnMut(Path, String),
+    path: Path,
+    name: &str,
+    input: Ty,
+    output: Ty,
+) {
+    let Some(procedure) = df.procedures.get(name) else {
+        error(path, format!("there is no procedure {name}"));
+        return;
+    };
+    if procedure.input != input || procedure.output != output {
+        error(
+            path,
+            format!(
+                "procedure {name} converts {} to {}, but is used to convert {input} to {output}",
+                procedure.input, procedure.output
+            ),
+        );
+    }
+}
+
+/// The variants of the enums of the pod that the dataflow refers to
+pub struct PodEnums {
+    pub events: Vec<String>,
+    pub emergency_types: Vec<String>,
+}
+
+impl PodEnums {
+    /// The variants of `Event` and `EmergencyType` in `source`
+    pub fn from_source(source: &str) -> Self {
+        let names = |name| {
+            crate::events::enum_variants(source, name).into_iter().map(|(name, _)| name).collect()
+        };
+        Self { events: names("Event"), emergency_types: names("EmergencyType") }
+    }
+}
+
+fn check_rule(
+    error: &mut impl FnMut(Path, String),
+    path: Path,
+    rule: &FsmRule,
+    id: u32,
+    payload_size: usize,
+    pod: &PodEnums,
+) {
+    let what = format!("CAN id {id:#x}");
+    check_event(error, &path, &rule.event, rule.emergency_type.as_deref(), &what, pod);
+
+    let Some(condition) = &rule.condition else { return };
+    let getter = &condition.getter;
+    if getter.can_payload_range.end > payload_size {
+        error(
+            path.key("if"),
+            format!("{condition} reads {getter}, but CAN id {id:#x} only has {payload_size} bytes"),
+        );
+    }
+    let ty = getter.ty;
+    match condition.operand {
+        Operand::Int(v) => {
+            if let Some((min, max)) = ty.value_range() {
+                if (v as f64) < min || (v as f64) > max {
+                    error(path.key("if"), format!("{v} in {condition} doesn't fit in {ty}"));
+                }
+            }
+        },
+        Operand::Float(v) if !ty.is_float() => {
+            error(path.key("if"), format!("{v:?} in {condition} is not a {ty}"))
+        },
+        _ => {},
+    }
+    if matches!(ty, Ty::U8Arr(_)) {
+        error(path.key("if"), format!("{condition} compares bytes, not a number"));
+    }
+}
+
+/// Checks that `event` of `what`, at `path.event`, is a variant of `Event`,
+/// and that it has an emergency type if and only if it's an emergency.
+fn check_event(
+    error: &mut impl FnMut(Path, String),
+    path: &Path,
+    event: &str,
+    emergency_type: Option<&str>,
+    what: &str,
+    pod: &PodEnums,
+) {
+    if !pod.events.iter().any(|e| e == event) {
+        error(path.key("event"), format!("event {event} of {what} is not a variant of `Event`"));
+    }
+    match emergency_type {
+        None if event == "Emergency" => {
+            error(path.key("event"), format!("the emergency of {what} needs an `emergency-type`"))
+        },
+        Some(_) if event != "Emergency" => error(
+            path.key("emergency-type"),
+            format!("event {event} of {what} is not an emergency"),
+        ),
+        Some(ty) if !pod.emergency_types.iter().any(|e| e == ty) => error(
+            path.key("emergency-type"),
+            format!("emergency type {ty} of {what} is not a variant of `EmergencyType`"),
+        ),
+        _ => {},
+    }
+}
+
+/// Checks that the payload fields of `command`, at `path`, have names, fit in
+/// the 8 bytes of its value without overlapping or being trimmed off, and have
+/// ranges and defaults their type can hold.
+fn check_payload(error: &mut impl FnMut(Path, String), path: &Path, command: &CommandSpec) {
+    let name = &command.name;
+    let trim = command.can.as_ref().map_or(0, |c| c.trim.0);
+    for (i, field) in command.payload.iter().enumerate() {
+        let path = path.key("payload").index(i);
+        let what = format!("field {} of command {name}", field.name);
+        let valid_name = field.name.starts_with(|c: char| c.is_ascii_lowercase() || c == '_')
+            && field.name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+        if !valid_name {
+            error(path.key("name"), format!("{what} is not named in snake_case"));
+        }
+        if command.payload[..i].iter().any(|f| f.name == field.name) {
+            error(path.key("name"), format!("{what} is defined more than once"));
+        }
+
+        let getter = &field.getter;
+        let bytes = &getter.can_payload_range;
+        if matches!(getter.ty.big_endian(), Ty::U8Arr(_) | Ty::F16) {
+            error(path.key("getter"), format!("{what} is a {}, which can't be sent", getter.ty));
+        }
+        if bytes.end > 8 {
+            error(path.key("getter"), format!("{what} is {getter}, but a payload has 8 bytes"));
+        } else if bytes.start < trim {
+            error(
+                path.key("getter"),
+                format!("{what} is {getter}, but the first {trim} byte(s) are trimmed off"),
+            );
+        }
+        let bits = getter.bit_range();
+        for other in &command.payload[..i] {
+            let other_bits = other.getter.bit_range();
+            if bits.start < other_bits.end && other_bits.start < bits.end {
+                error(
+                    path.key("getter"),
+                    format!("{what} is {getter}, which overlaps with field {}", other.name),
+                );
+            }
+        }
+
+        let Some((min, max)) = field.limits() else { continue };
+        let whole = |v: f64| field.value_type().is_float() || v.fract() == 0.0;
+        if let Some([low, high]) = field.range {
+            let (type_min, type_max) = match &getter.bits {
+                Some(bits) => (0.0, (u64::MAX >> (64 - bits.len())) as f64),
+                // fields that can't be sent are reported above
+                None => getter.ty.value_range().unwrap_or((low, high)),
+            };
+            if low > high || low < type_min || high > type_max || !whole(low) || !whole(high) {
+                error(
+                    path.key("range"),
+                    format!("range {low}..={high} of {what} doesn't fit in {getter}"),
+                );
+            }
+        }
+        if field.default < min || field.default > max || !whole(field.default) {
+            error(
+                path.key("default"),
+                format!("default {} of {what} is outside of {min}..={max}", field.default),
+            );
+        }
+    }
+}
+
+/// Things in the dataflow that are allowed but probably mistakes:
+/// - commands that neither give the FSM an event nor are sent over CAN, so
+///   the pod ignores them
+pub fn warnings(df: &DataflowSpec) -> Vec<Diagnostic> {
+    let root = Path::default();
+    df.commands
+        .iter()
+        .enumerate()
+        .filter(|(_, c)| c.fsm_event.is_none() && c.can.is_none())
+        .map(|(i, c)| Diagnostic {
+            path: root.key("commands").index(i),
+            message: format!(
+                "command {} has no `fsm-event` and isn't sent over CAN, so the pod ignores it",
+                c.name
+            ),
+        })
+        .collect()
+}
+
+/// Formats the diagnostics like the compiler does, pointing at the line and
+/// column in `file`, whose contents are `source`.
+pub fn report(diagnostics: &[Diagnostic], file: &str, source: &str) -> String {
+    let mut report = String::new();
+    for d in diagnostics {
+        writeln!(&mut report, "error: {}", d.message).unwrap();
+        match d.path.locate(source) {
+            Some((line, column)) => {
+                writeln!(&mut report, "  --> {file}:{line}:{column} ({})", d.path).unwrap()
+            },
+            None => writeln!(&mut report, "  --> {file} ({})", d.path).unwrap(),
+        }
+    }
+    writeln!(&mut report, "{} problem(s) found in {file}", diagnostics.len()).unwrap();
+    report
+}
+
+/// Reads, parses and validates the dataflow at `file`, with the FSM events
+/// and emergency types being the variants of `Event` and `EmergencyType` in
+/// `events_file`. Fails with a report of every problem found.
+pub fn load(file: &str, events_file: &str) -> anyhow::Result<DataflowSpec> {
+    let read = |path| std::fs::read_to_string(path).map_err(|e| anyhow::anyhow!("{path}: {e}"));
+    let source = read(file)?;
+    let pod = PodEnums::from_source(&read(events_file)?);
+    let df = try_parse_from(&source).map_err(|e| anyhow::anyhow!("{file}: {e}"))?;
+    let diagnostics = validate(&df, &pod);
+    if !diagnostics.is_empty() {
+        anyhow::bail!("invalid dataflow\n{}", report(&diagnostics, file, &source));
+    }
+    Ok(df)
+}
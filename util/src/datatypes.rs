@@ -22,6 +22,10 @@ pub struct Datatype {
     pub lower: Limit,
     pub upper: Limit,
     pub critical: bool,
+    #[serde(default)]
+    pub stale_timeout: Option<u64>,
+    #[serde(default)]
+    pub stale_states: Vec<String>,
     pub display_units: Option<String>,
     pub priority: Option<usize>,
     pub store: Option<StoreInfo>,
@@ -37,36 +41,70 @@ pub struct StoreInfo {
 
 fn number_s() -> String { "number".into() }
 
-#[derive(Debug, Hash, Clone, Copy)]
+/// A bound of a datatype, in engineering units
+#[derive(Debug, Clone, Copy)]
 pub enum Limit {
     No,
-    Single(u64),
+    Single(f64),
     Multiple(Severities),
 }
 
+impl Limit {
+    /// All thresholds of the limit, in no particular order
+    pub fn thresholds(&self) -> impl Iterator<Item = f64> {
+        match *self {
+            Limit::No => [None, None, None],
+            Limit::Single(x) => [Some(x), None, None],
+            Limit::Multiple(s) => [s.warn, s.err, s.brake],
+        }
+        .into_iter()
+        .flatten()
+    }
+}
+
+// floats aren't `Hash`, so hash their bits
+impl Hash for Limit {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match *self {
+            Limit::No => {},
+            Limit::Single(x) => x.to_bits().hash(state),
+            Limit::Multiple(s) => s.hash(state),
+        }
+    }
+}
+
 impl Display for Limit {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
         match *self {
             Limit::No => write!(f, "Limit::No"),
-            Limit::Single(x) => write!(f, "Limit::Single({x})"),
+            Limit::Single(x) => write!(f, "Limit::Single({x:?})"),
             Limit::Multiple(y) => {
                 write!(
                     f,
                     "Limit::Multiple(Severities {{ warn: {}, err: {}, brake: {} }})",
-                    y.warn.map(|x| format!("Some({x})")).unwrap_or("None".into()),
-                    y.err.map(|x| format!("Some({x})")).unwrap_or("None".into()),
-                    y.brake.map(|x| format!("Some({x})")).unwrap_or("None".into()),
+                    y.warn.map(|x| format!("Some({x:?})")).unwrap_or("None".into()),
+                    y.err.map(|x| format!("Some({x:?})")).unwrap_or("None".into()),
+                    y.brake.map(|x| format!("Some({x:?})")).unwrap_or("None".into()),
                 )
             },
         }
     }
 }
 
-#[derive(Deserialize, Hash, Clone, Copy, Debug)]
+#[derive(Deserialize, Clone, Copy, Debug)]
 pub struct Severities {
-    pub warn: Option<u64>,
-    pub err: Option<u64>,
-    pub brake: Option<u64>,
+    pub warn: Option<f64>,
+    pub err: Option<f64>,
+    pub brake: Option<f64>,
+}
+
+impl Hash for Severities {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        for x in [self.warn, self.err, self.brake] {
+            x.map(f64::to_bits).hash(state);
+        }
+    }
 }
 
 pub fn get_data_config(path: &str) -> Result<Config> {
@@ -95,6 +133,8 @@ pub fn generate_data_types_from_config(config: &Config, drv: bool) -> Result<Str
     let mut units = String::from("    pub fn unit(&self) -> String {\n        match *self {\n");
 
     let mut priorities = String::new();
+    let mut stale_timeouts = String::new();
+    let mut stale_states = String::new();
 
     for dtp in criticalDatapoints {
         criticalDatapointResult.push_str(&format!("\n\t\t\tDatatype::{dtp} => true,"));
@@ -116,6 +156,22 @@ pub fn generate_data_types_from_config(config: &Config, drv: bool) -> Result<Str
                 dtype.name, u
             ));
         }
+        if let Some(t) = dtype.stale_timeout {
+            stale_timeouts
+                .push_str(&format!("            Datatype::{} => Some({t}),\n", dtype.name));
+            let states = if dtype.stale_states.is_empty() {
+                "_".to_string()
+            } else {
+                dtype
+                    .stale_states
+                    .iter()
+                    .map(|s| format!("States::{s}"))
+                    .collect::<Vec<_>>()
+                    .join(" | ")
+            };
+            stale_states
+                .push_str(&format!("            (Datatype::{}, {states}) => true,\n", dtype.name));
+        }
         if let Some(p) = dtype.priority {
             priorities.push_str(&format!("            Datatype::{} => {},\n", dtype.name, p));
         }
@@ -127,16 +183,17 @@ pub fn generate_data_types_from_config(config: &Config, drv: bool) -> Result<Str
         "\n
 pub enum Limit {{
     No,
-    Single(u64),
+    Single(f64),
     Multiple(Severities)
 }}
 
 pub struct Severities {{
-    pub warn: Option<u64>,
-    pub err: Option<u64>,
-    pub brake: Option<u64>,
+    pub warn: Option<f64>,
+    pub err: Option<f64>,
+    pub brake: Option<f64>,
 }}
 
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum ValueCheckResult {{
     Fine,
     Warn,
@@ -166,6 +223,24 @@ impl Datatype {{
         }}
     }}
 
+    /// Time in milliseconds without receiving a critical datatype after
+    /// which it is considered stale
+    pub fn stale_timeout(&self) -> Option<u64> {{
+        match *self {{
+{stale_timeouts}
+            _ => None,
+        }}
+    }}
+
+    /// Whether the staleness of a critical datatype is checked in `state`
+    pub fn checks_staleness(&self, state: States) -> bool {{
+        #[allow(unreachable_patterns)]
+        match (*self, state) {{
+{stale_states}
+            _ => false,
+        }}
+    }}
+
     pub fn from_id(id:u16) -> Self {{
         #[deny(unreachable_patterns)]
         match id {{
@@ -189,7 +264,9 @@ impl Datatype {{
         }}
     }}
 
-    pub fn check_bounds(&self, value: u64) -> ValueCheckResult {{
+    /// Checks a value, in engineering units, against the limits of the
+    /// datatype
+    pub fn check_bounds(&self, value: f64) -> ValueCheckResult {{
         let (up, low) = self.bounds();
         let ok_up = match up {{
             Limit::No => 0,
@@ -199,12 +276,12 @@ impl Datatype {{
                 err: b,
                 brake: c,
             }}) => {{
-                if let Some(cc) = c {{
-                    if value > cc {{ 100 }} else {{ 0 }}
-                }} else if let Some(bb) = b {{
-                    if value > bb {{ 10 }} else {{ 0 }}
-                }} else if let Some(aa) = a {{
-                    if value > aa {{ 1 }} else {{ 0 }}
+                if c.is_some_and(|cc| value > cc) {{
+                    100
+                }} else if b.is_some_and(|bb| value > bb) {{
+                    10
+                }} else if a.is_some_and(|aa| value > aa) {{
+                    1
                 }} else {{ 0 }}
             }}
         }};
@@ -216,12 +293,12 @@ impl Datatype {{
                 err: b,
                 brake: c,
             }}) => {{
-                if let Some(cc) = c {{
-                    if value < cc {{ 100 }} else {{ 0 }}
-                }} else if let Some(bb) = b {{
-                    if value < bb {{ 10 }} else {{ 0 }}
-                }} else if let Some(aa) = a {{
-                    if value < aa {{ 1 }} else {{ 0 }}
+                if c.is_some_and(|cc| value < cc) {{
+                    100
+                }} else if b.is_some_and(|bb| value < bb) {{
+                    10
+                }} else if a.is_some_and(|aa| value < aa) {{
+                    1
                 }} else {{ 0 }}
             }}
         }};
@@ -66,3 +66,76 @@ pub enum Event {{
         enum_definitions,
     ) + &format!("\npub const EVENTS_HASH: u64 = {hash};"))
 }
+
+/// Returns the names of the variants of `pub enum {name}` in `source` together
+/// with their discriminants, which are the tags they're encoded with on the
+/// pod. Variants without an explicit discriminant follow the previous one.
+///
+/// Panics if `source` doesn't define the enum.
+pub fn enum_variants(source: &str, name: &str) -> Vec<(String, u8)> {
+    let start = source
+        .find(&format!("pub enum {name} {{"))
+        .unwrap_or_else(|| panic!("Couldn't find `pub enum {name}`"));
+
+    let mut depth = 0;
+    let mut next = 0;
+    let mut current = None;
+    let mut variants = vec![];
+    for line in source[start..].lines().map(str::trim) {
+        if line.is_empty() || line.starts_with("//") || line.starts_with('#') {
+            continue;
+        }
+        if depth == 1 && current.is_none() {
+            let end = line.find(|c: char| !(c.is_alphanumeric() || c == '_')).unwrap_or(line.len());
+            current = Some(line[..end].to_string());
+        }
+        depth += line.matches('{').count();
+        depth -= line.matches('}').count();
+        if depth == 0 {
+            break;
+        }
+        // a variant ends with a comma at the depth of the enum, after its
+        // fields and discriminant
+        if depth == 1 && line.ends_with(',') {
+            let variant = current.take().unwrap();
+            if let Some((_, value)) = line.rsplit_once('=') {
+                next = value.trim_end_matches(',').trim().parse().unwrap_or_else(|_| {
+                    panic!("Couldn't parse the discriminant of {name}::{variant}")
+                });
+            }
+            variants.push((variant, next));
+            next += 1;
+        }
+    }
+    variants
+}
+
+/// Generates `POD_EVENT_NAMES` and `POD_EMERGENCY_NAMES` from the `Event` and
+/// `EmergencyType` enums of the pod, used by the ground station to print the
+/// FSM history.
+///
+/// -`path`: path to the file defining the enums
+pub fn generate_pod_event_names(path: &str) -> Result<String> {
+    let source = fs::read_to_string(path)?;
+    // indexed by the discriminant, with gaps filled by "Unknown"
+    let names = |name: &str| {
+        let variants = enum_variants(&source, name);
+        let len = variants.iter().map(|(_, d)| *d as usize + 1).max().unwrap_or(0);
+        let mut names = vec!["Unknown"; len];
+        for (variant, d) in &variants {
+            names[*d as usize] = variant;
+        }
+        format!(
+            "[&str; {len}] = [{}]",
+            names.iter().map(|v| format!("\"{v}\",")).collect::<String>()
+        )
+    };
+
+    Ok(format!(
+        "\n/// Names of the `Event` variants of the pod, indexed by their discriminant\npub const \
+         POD_EVENT_NAMES: {};\n/// Names of the `EmergencyType` variants of the pod, indexed by \
+         their discriminant\npub const POD_EMERGENCY_NAMES: {};\n",
+        names("Event"),
+        names("EmergencyType")
+    ))
+}
@@ -1,3 +1,5 @@
+use std::fmt::Write;
+
 use serde::Deserialize;
 
 #[derive(Debug, Deserialize)]
@@ -5,4 +7,265 @@ pub struct FSMState {
     pub state: String,
     pub doc: String,
     pub index: u8,
+    /// Whether the FSM checks the frontend heartbeat in this state
+    #[serde(default)]
+    pub check_heartbeat: bool,
+    /// Maximum time in milliseconds the FSM stays in this state
+    pub timeout: Option<u64>,
+    /// The state the FSM falls back to after `timeout`
+    pub timeout_state: Option<String>,
+    /// `Info` shown on the ground station when this state times out
+    pub timeout_info: Option<String>,
+}
+
+/// A `[[Transition]]` entry in `config.toml`
+#[derive(Debug, Deserialize)]
+pub struct Transition {
+    /// The states in which `event` triggers this transition
+    pub from: Vec<String>,
+    /// Name of the (unit) `Event` variant that triggers the transition
+    pub event: String,
+    /// The state the FSM goes to
+    pub to: String,
+    /// Name of a check that has to pass for the transition to happen
+    pub guard: Option<String>,
+    /// Name of an action the FSM runs when entering `to`
+    pub action: Option<String>,
+    /// If set, `to` is only requested and the FSM enters it once this event
+    /// is received
+    pub ack: Option<String>,
+    /// How long in milliseconds to wait for `ack` before reporting a
+    /// `TransitionFail`
+    pub ack_timeout: Option<u64>,
+    /// Don't report a `TransitionFail` when `event` is received in a state
+    /// where it doesn't trigger a transition. Meant for events that are sent
+    /// periodically by the subsystems.
+    #[serde(default)]
+    pub silent: bool,
+}
+
+/// One `(state, event)` pair of the generated transition table
+struct Row<'a> {
+    from: &'a String,
+    event: &'a String,
+    to: &'a String,
+    guard: Option<&'a String>,
+    action: Option<&'a String>,
+    ack: Option<&'a String>,
+    ack_timeout: Option<u64>,
+}
+
+/// Generates the transition table of the main FSM from the `[[Transition]]`
+/// entries.
+///
+/// Panics if a transition refers to an unknown state, or if an event triggers
+/// more than one transition in the same state.
+pub fn generate_transitions(states: &[FSMState], transitions: &[Transition]) -> String {
+    let check_state = |s: &String| {
+        if !states.iter().any(|x| &x.state == s) {
+            panic!("Transition refers to unknown state {s}");
+        }
+    };
+
+    let mut guards: Vec<&String> = vec![];
+    let mut actions: Vec<&String> = vec![];
+    let mut rows: Vec<Row> = vec![];
+    let mut failed_targets: Vec<(&String, &String)> = vec![];
+
+    for t in transitions {
+        check_state(&t.to);
+        if t.ack_timeout.is_some() && t.ack.is_none() {
+            panic!("Transition on {} has an ack_timeout but no ack", t.event);
+        }
+        if let Some(guard) = &t.guard {
+            if !guards.contains(&guard) {
+                guards.push(guard);
+            }
+        }
+        if let Some(action) = &t.action {
+            if !actions.contains(&action) {
+                actions.push(action);
+            }
+        }
+        for from in &t.from {
+            check_state(from);
+            match &t.ack {
+                // the request only checks the guard, the action runs once the state is entered
+                Some(ack) => {
+                    rows.push(Row {
+                        from,
+                        event: &t.event,
+                        to: &t.to,
+                        guard: t.guard.as_ref(),
+                        action: None,
+                        ack: Some(ack),
+                        ack_timeout: t.ack_timeout,
+                    });
+                    rows.push(Row {
+                        from,
+                        event: ack,
+                        to: &t.to,
+                        guard: None,
+                        action: t.action.as_ref(),
+                        ack: None,
+                        ack_timeout: None,
+                    });
+                },
+                None => rows.push(Row {
+                    from,
+                    event: &t.event,
+                    to: &t.to,
+                    guard: t.guard.as_ref(),
+                    action: t.action.as_ref(),
+                    ack: None,
+                    ack_timeout: None,
+                }),
+            }
+        }
+        if !t.silent && !failed_targets.iter().any(|(e, _)| *e == &t.event) {
+            failed_targets.push((&t.event, &t.to));
+        }
+    }
+
+    for (i, row) in rows.iter().enumerate() {
+        if rows[..i].iter().any(|r| r.from == row.from && r.event == row.event) {
+            panic!("Event {} triggers more than one transition in state {}", row.event, row.from);
+        }
+    }
+
+    let mut code = String::new();
+    writeln!(
+        code,
+        "\n/// Checks that have to pass for a transition to happen. Implemented by the `MainFSM`.
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum TransitionGuard {{ {} }}",
+        guards.iter().map(|g| format!("{g},")).collect::<String>()
+    )
+    .unwrap();
+    writeln!(
+        code,
+        "\n/// Actions run when entering the new state of a transition, partly before and partly after it. Implemented by the `MainFSM`.
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum TransitionAction {{ {} }}",
+        actions.iter().map(|a| format!("{a},")).collect::<String>()
+    )
+    .unwrap();
+    writeln!(
+        code,
+        "\n/// A transition of the `MainFSM`, generated from the `[[Transition]]` entries in `config.toml`
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub struct Transition {{
+    /// The state the FSM goes to
+    pub to: States,
+    /// Has to pass for the transition to happen
+    pub guard: Option<TransitionGuard>,
+    /// Runs when entering `to`
+    pub action: Option<TransitionAction>,
+    /// If set, `to` is only requested and entered once this event is received
+    pub ack: Option<crate::Event>,
+    /// How long in milliseconds to wait for `ack` before reporting a `TransitionFail`
+    pub ack_timeout: Option<u64>,
+}}
+
+impl Transition {{
+    /// Looks up the transition triggered by `event` in `state`.
+    pub fn find(state: States, event: crate::Event) -> Option<Transition> {{
+        match (state, event) {{"
+    )
+    .unwrap();
+    let opt = |prefix: &str, x: Option<&String>| match x {
+        Some(x) => format!("Some({prefix}::{x})"),
+        None => "None".to_string(),
+    };
+    for row in &rows {
+        writeln!(
+            code,
+            "(States::{}, crate::Event::{}) => Some(Transition {{ to: States::{}, guard: {}, action: {}, ack: {}, ack_timeout: {:?} }}),",
+            row.from,
+            row.event,
+            row.to,
+            opt("TransitionGuard", row.guard),
+            opt("TransitionAction", row.action),
+            opt("crate::Event", row.ack),
+            row.ack_timeout,
+        )
+        .unwrap();
+    }
+    writeln!(
+        code,
+        "_ => None,
+        }}
+    }}
+
+    /// The state `event` would have moved the FSM to. Reported to the ground station in a
+    /// `TransitionFail` when the event is received in a state where it isn't a trigger.
+    pub fn failed_target(event: crate::Event) -> Option<States> {{
+        match event {{"
+    )
+    .unwrap();
+    for (event, to) in &failed_targets {
+        writeln!(code, "crate::Event::{event} => Some(States::{to}),").unwrap();
+    }
+    writeln!(code, "_ => None, }} }} }}").unwrap();
+
+    code
+}
+
+/// Generates `States::timeout`, returning the maximum time a state may last and
+/// the state the FSM falls back to afterwards.
+pub fn generate_state_timeouts(states: &[FSMState]) -> String {
+    let mut arms = String::new();
+    for state in states {
+        match (state.timeout, &state.timeout_state) {
+            (Some(timeout), Some(fallback)) => {
+                if !states.iter().any(|x| &x.state == fallback) {
+                    panic!("State {} falls back to unknown state {fallback}", state.state);
+                }
+                writeln!(arms, "States::{} => Some(({timeout}, States::{fallback})),", state.state)
+                    .unwrap();
+            },
+            (None, None) => {},
+            _ => panic!("State {} needs both a timeout and a timeout_state", state.state),
+        }
+    }
+
+    format!(
+        "
+impl States {{
+    /// Maximum time in milliseconds the FSM stays in this state, and the state it falls back
+    /// to afterwards
+    pub fn timeout(&self) -> Option<(u64, States)> {{
+        match self {{
+{arms}
+            _ => None,
+        }}
+    }}
+}}
+"
+    )
+}
+
+/// Generates `States::timeout_info`, the `Info` the ground station shows when
+/// a state times out.
+pub fn generate_state_timeout_infos(states: &[FSMState]) -> String {
+    let arms = states
+        .iter()
+        .filter_map(|x| {
+            Some(format!("States::{} => Some(Info::{}),\n", x.state, x.timeout_info.as_ref()?))
+        })
+        .collect::<String>();
+
+    format!(
+        "
+impl States {{
+    /// The `Info` shown on the ground station when this state times out
+    pub fn timeout_info(&self) -> Option<Info> {{
+        match self {{
+{arms}
+            _ => None,
+        }}
+    }}
+}}
+"
+    )
 }
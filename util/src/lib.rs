@@ -87,7 +87,11 @@ pub fn check_config(dp: &str, cp: &str, conf: &str) -> Result<String> {
     Ok(format!("\npub const CONFIG_HASH: u64 = {hash};\n"))
 }
 
-fn nearest_id(id: u16, ids: &[u16]) -> u16 {
+/// The first id from `id` upwards that is not in `ids`, or the highest free
+/// one below it. Ids are 11 bits.
+///
+/// Panics if every id is taken.
+pub fn nearest_id(id: u16, ids: &[u16]) -> u16 {
     for i in min(id, 2u16.pow(11))..2u16.pow(11) {
         if !ids.contains(&i) {
             return i;
@@ -20,21 +20,28 @@ impl<'de> Deserialize<'de> for Limit {
             type Value = Limit;
 
             fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-                formatter.write_str("an integer, a table with severities, or the string 'no'")
+                formatter.write_str("a number, a table with severities, or the string 'no'")
             }
 
             fn visit_u64<E>(self, value: u64) -> Result<Limit, E>
             where
                 E: de::Error,
             {
-                Ok(Limit::Single(value))
+                Ok(Limit::Single(value as f64))
             }
 
             fn visit_i64<E>(self, value: i64) -> Result<Limit, E>
             where
                 E: de::Error,
             {
-                Ok(Limit::Single(value as u64))
+                Ok(Limit::Single(value as f64))
+            }
+
+            fn visit_f64<E>(self, value: f64) -> Result<Limit, E>
+            where
+                E: de::Error,
+            {
+                Ok(Limit::Single(value))
             }
 
             fn visit_map<M>(self, map: M) -> Result<Limit, M::Error>